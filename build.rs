@@ -7,11 +7,26 @@
 //! Cargo re-run the build script whenever `memory.x` is changed,
 //! updating `memory.x` ensures a rebuild of the application with the
 //! new memory settings.
+//!
+//! It also converts the reel symbols in `assets/symbols` and their animations
+//! in `assets/animations` to compressed RGB565 and generates the symbol table
+//! the game includes, see `convert_symbols`. The WAVs in `assets/samples` become
+//! 8 bit PCM, see `convert_samples`.
 
 use std::env;
-use std::fs::File;
+use std::fmt::Write as _;
+use std::fs::{self, File};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+// shared with the preview tool, which doesn't need all of it here
+#[allow(dead_code)]
+#[path = "tools/pcm_preview/src/wav.rs"]
+mod wav;
+
+const SYMBOLS_DIR: &str = "assets/symbols";
+const ANIMATIONS_DIR: &str = "assets/animations";
+const SAMPLES_DIR: &str = "assets/samples";
 
 fn main() {
     // Put `memory.x` in our output directory and ensure it's
@@ -33,4 +48,441 @@ fn main() {
     println!("cargo:rustc-link-arg-bins=-Tlink.x");
     // Required for `defmt`
     println!("cargo:rustc-link-arg-bins=-Tdefmt.x");
-}
\ No newline at end of file
+
+    convert_symbols(out);
+    convert_samples(out);
+}
+
+/// One decoded source image.
+struct Source {
+    name: String,
+    width: u32,
+    height: u32,
+    /// RGB565, row by row
+    pixels: Vec<u16>,
+}
+
+/// Converts every `.png` and `.bmp` in `assets/symbols` to RGB565, compresses
+/// it and writes `symbols.rs` with one entry per image.
+///
+/// Files are named `<order>_<name>.<ext>`, e.g. `00_rusty_crab.bmp`. The order
+/// prefix is the symbol id, the paytable depends on it, so new symbols go at
+/// the end. All symbols must have the same size because they share the reel
+/// strip.
+fn convert_symbols(out: &Path) {
+    // watching the directory picks up added and removed files
+    println!("cargo:rerun-if-changed={}", SYMBOLS_DIR);
+
+    let paths = images_in(SYMBOLS_DIR);
+    assert!(!paths.is_empty(), "no symbols in {}", SYMBOLS_DIR);
+
+    let sources: Vec<Source> = paths.iter().map(|path| load(path)).collect();
+
+    let (width, height) = (sources[0].width, sources[0].height);
+    for source in &sources {
+        assert!(
+            source.width == width && source.height == height,
+            "symbol `{}` is {}x{}, all symbols must be {}x{} like `{}`",
+            source.name,
+            source.width,
+            source.height,
+            width,
+            height,
+            sources[0].name
+        );
+    }
+
+    let mut code = String::new();
+    writeln!(
+        code,
+        "// generated by build.rs from {}, do not edit",
+        SYMBOLS_DIR
+    )
+    .unwrap();
+    writeln!(code).unwrap();
+    writeln!(code, "pub const SYMBOL_COUNT: usize = {};", sources.len()).unwrap();
+    writeln!(code, "pub const SYMBOL_WIDTH: u32 = {};", width).unwrap();
+    writeln!(code, "pub const SYMBOL_HEIGHT: u32 = {};", height).unwrap();
+    writeln!(code).unwrap();
+    for (id, source) in sources.iter().enumerate() {
+        writeln!(
+            code,
+            "pub const {}: usize = {};",
+            source.name.to_uppercase(),
+            id
+        )
+        .unwrap();
+    }
+    writeln!(code).unwrap();
+
+    let animations = convert_animations(out, &sources, &mut code);
+
+    writeln!(code, "pub static SYMBOLS: [Symbol; SYMBOL_COUNT] = [").unwrap();
+    for (id, source) in sources.iter().enumerate() {
+        let file = out.join(format!("{}.qoi565", source.name));
+        fs::write(&file, compress(&source.pixels)).unwrap();
+
+        writeln!(code, "    Symbol {{").unwrap();
+        writeln!(code, "        id: {},", id).unwrap();
+        writeln!(code, "        name: {:?},", source.name).unwrap();
+        writeln!(
+            code,
+            "        data: include_bytes!({:?}),",
+            file.display().to_string()
+        )
+        .unwrap();
+        if animations.contains(&source.name) {
+            writeln!(
+                code,
+                "        animation: Some(&{}_ANIMATION),",
+                source.name.to_uppercase()
+            )
+            .unwrap();
+        } else {
+            writeln!(code, "        animation: None,").unwrap();
+        }
+        writeln!(code, "    }},").unwrap();
+    }
+    writeln!(code, "];").unwrap();
+    writeln!(code).unwrap();
+
+    // uncompressed copies for comparing, they only end up in binaries that use them
+    writeln!(code, "pub static RAW_SYMBOLS: [&[u8]; SYMBOL_COUNT] = [").unwrap();
+    for source in &sources {
+        let file = out.join(format!("{}.rgb565", source.name));
+        let bytes: Vec<u8> = source.pixels.iter().flat_map(|p| p.to_le_bytes()).collect();
+        fs::write(&file, bytes).unwrap();
+
+        writeln!(
+            code,
+            "    include_bytes!({:?}),",
+            file.display().to_string()
+        )
+        .unwrap();
+    }
+    writeln!(code, "];").unwrap();
+
+    fs::write(out.join("symbols.rs"), code).unwrap();
+}
+
+/// Converts the sprite atlases in `assets/animations` and writes one `Animation`
+/// per atlas to `code`. Returns the names of the animated symbols.
+///
+/// An atlas is named after its symbol, e.g. `rusty_crab.png`, and has the frames
+/// side by side, each the size of a symbol. Next to it, `rusty_crab.frames` lists
+/// how many milliseconds every frame is shown, separated by whitespace. Lines
+/// starting with `#` are comments.
+fn convert_animations(out: &Path, symbols: &[Source], code: &mut String) -> Vec<String> {
+    println!("cargo:rerun-if-changed={}", ANIMATIONS_DIR);
+
+    let (width, height) = (symbols[0].width, symbols[0].height);
+    let mut names = Vec::new();
+
+    for path in images_in(ANIMATIONS_DIR) {
+        let name = path.file_stem().unwrap().to_str().unwrap().to_string();
+        assert!(
+            symbols.iter().any(|symbol| symbol.name == name),
+            "{} doesn't belong to any symbol in {}",
+            path.display(),
+            SYMBOLS_DIR
+        );
+
+        let (atlas_width, atlas_height, pixels) = decode(&path);
+        assert!(
+            atlas_height == height && atlas_width % width == 0 && atlas_width > 0,
+            "{} is {}x{}, it should be frames of {}x{} side by side",
+            path.display(),
+            atlas_width,
+            atlas_height,
+            width,
+            height
+        );
+        let count = (atlas_width / width) as usize;
+
+        let timing = path.with_extension("frames");
+        let durations: Vec<u32> = fs::read_to_string(&timing)
+            .unwrap_or_else(|e| panic!("can't read {}: {}", timing.display(), e))
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .flat_map(|line| line.split_whitespace())
+            .map(|ms| {
+                ms.parse()
+                    .unwrap_or_else(|_| panic!("{}: `{}` is not a number", timing.display(), ms))
+            })
+            .collect();
+        assert!(
+            durations.len() == count,
+            "{} has {} durations for {} frames",
+            timing.display(),
+            durations.len(),
+            count
+        );
+
+        writeln!(
+            code,
+            "static {}_ANIMATION: Animation = Animation {{",
+            name.to_uppercase()
+        )
+        .unwrap();
+        writeln!(code, "    frames: &[").unwrap();
+        for (i, duration) in durations.iter().enumerate() {
+            let frame: Vec<u16> = pixels
+                .chunks_exact(atlas_width as usize)
+                .flat_map(|row| &row[i * width as usize..(i + 1) * width as usize])
+                .copied()
+                .collect();
+            let file = out.join(format!("{}_{}.qoi565", name, i));
+            fs::write(&file, compress(&frame)).unwrap();
+
+            writeln!(code, "        AnimationFrame {{").unwrap();
+            writeln!(
+                code,
+                "            data: include_bytes!({:?}),",
+                file.display().to_string()
+            )
+            .unwrap();
+            writeln!(code, "            duration_ms: {},", duration).unwrap();
+            writeln!(code, "        }},").unwrap();
+        }
+        writeln!(code, "    ],").unwrap();
+        writeln!(code, "}};").unwrap();
+        writeln!(code).unwrap();
+
+        names.push(name);
+    }
+    names
+}
+
+/// Converts every `.wav` in `assets/samples` to 8 bit PCM and writes `samples.rs`
+/// with one `Sample` per file, named after it: `coin_drop.wav` is `COIN_DROP`.
+fn convert_samples(out: &Path) {
+    println!("cargo:rerun-if-changed={}", SAMPLES_DIR);
+    println!("cargo:rerun-if-changed=tools/pcm_preview/src/wav.rs");
+
+    let mut paths: Vec<PathBuf> = fs::read_dir(SAMPLES_DIR)
+        .unwrap_or_else(|e| panic!("can't read {}: {}", SAMPLES_DIR, e))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("wav"))
+        .filter(|path| !path.to_string_lossy().ends_with(".preview.wav"))
+        .collect();
+    paths.sort();
+
+    let mut code = String::new();
+    writeln!(
+        code,
+        "// generated by build.rs from {}, do not edit",
+        SAMPLES_DIR
+    )
+    .unwrap();
+    writeln!(code).unwrap();
+    writeln!(
+        code,
+        "const _: () = assert!(SAMPLE_RATE == {}, \"build.rs converts to another rate\");",
+        wav::SAMPLE_RATE
+    )
+    .unwrap();
+    writeln!(code).unwrap();
+
+    for path in paths {
+        let name = path.file_stem().unwrap().to_str().unwrap();
+        assert!(
+            name.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+                && name.starts_with(|c: char| c.is_ascii_lowercase()),
+            "sample name `{}` in {} must be lowercase letters, digits and _",
+            name,
+            path.display()
+        );
+
+        let source = wav::read(&fs::read(&path).unwrap())
+            .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        let file = out.join(format!("{}.pcm", name));
+        fs::write(&file, wav::to_pcm(&source)).unwrap();
+
+        writeln!(
+            code,
+            "pub static {}: Sample = Sample {{",
+            name.to_uppercase()
+        )
+        .unwrap();
+        writeln!(code, "    name: {:?},", name).unwrap();
+        writeln!(
+            code,
+            "    data: include_bytes!({:?}),",
+            file.display().to_string()
+        )
+        .unwrap();
+        writeln!(code, "}};").unwrap();
+    }
+
+    fs::write(out.join("samples.rs"), code).unwrap();
+}
+
+/// The `.png` and `.bmp` files in `dir`, sorted by name.
+fn images_in(dir: &str) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("can't read {}: {}", dir, e))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("png" | "bmp")
+            )
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn load(path: &Path) -> Source {
+    let stem = path.file_stem().unwrap().to_str().unwrap();
+    let name = match stem.split_once('_') {
+        Some((order, name)) if order.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => panic!("{} should be named <order>_<name>", path.display()),
+    };
+    assert!(
+        name.chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+            && name.starts_with(|c: char| c.is_ascii_lowercase()),
+        "symbol name `{}` in {} must be lowercase letters, digits and _",
+        name,
+        path.display()
+    );
+
+    let (width, height, pixels) = decode(path);
+
+    Source {
+        name: name.to_string(),
+        width,
+        height,
+        pixels,
+    }
+}
+
+/// Reads a PNG or BMP as RGB565, row by row.
+fn decode(path: &Path) -> (u32, u32, Vec<u16>) {
+    let bytes = fs::read(path).unwrap();
+    let (width, height, rgb) = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("png") => decode_png(path, &bytes),
+        _ => decode_bmp(path, &bytes),
+    };
+
+    let pixels = rgb
+        .iter()
+        .map(|&[r, g, b]| (u16::from(r >> 3) << 11) | (u16::from(g >> 2) << 5) | u16::from(b >> 3))
+        .collect();
+    (width, height, pixels)
+}
+
+fn decode_png(path: &Path, bytes: &[u8]) -> (u32, u32, Vec<[u8; 3]>) {
+    let mut decoder = png::Decoder::new(bytes);
+    // palette and 16 bit images come out as plain 8 bit channels
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder
+        .read_info()
+        .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+    let data = &buffer[..info.buffer_size()];
+
+    // transparent pixels end up on the black reel background
+    let blend = |c: u8, a: u8| (u16::from(c) * u16::from(a) / 255) as u8;
+    let rgb = match info.color_type {
+        png::ColorType::Rgb => data.chunks_exact(3).map(|p| [p[0], p[1], p[2]]).collect(),
+        png::ColorType::Rgba => data
+            .chunks_exact(4)
+            .map(|p| [blend(p[0], p[3]), blend(p[1], p[3]), blend(p[2], p[3])])
+            .collect(),
+        png::ColorType::Grayscale => data.iter().map(|&l| [l, l, l]).collect(),
+        png::ColorType::GrayscaleAlpha => data
+            .chunks_exact(2)
+            .map(|p| {
+                let l = blend(p[0], p[1]);
+                [l, l, l]
+            })
+            .collect(),
+        other => panic!("{}: unsupported PNG color type {:?}", path.display(), other),
+    };
+    (info.width, info.height, rgb)
+}
+
+fn decode_bmp(path: &Path, bytes: &[u8]) -> (u32, u32, Vec<[u8; 3]>) {
+    let bmp = tinybmp::RawBmp::from_slice(bytes)
+        .unwrap_or_else(|e| panic!("{}: {:?}", path.display(), e));
+    let header = bmp.header();
+    let (width, height) = (header.image_size.width, header.image_size.height);
+
+    let mut rgb = vec![[0; 3]; (width * height) as usize];
+    for pixel in bmp.pixels() {
+        let color = match header.bpp {
+            tinybmp::Bpp::Bits24 | tinybmp::Bpp::Bits32 => pixel.color,
+            bpp => panic!("{}: use a 24 or 32 bit BMP, not {:?}", path.display(), bpp),
+        };
+        let index = pixel.position.y as usize * width as usize + pixel.position.x as usize;
+        rgb[index] = [(color >> 16) as u8, (color >> 8) as u8, color as u8];
+    }
+    (width, height, rgb)
+}
+
+/// The encoder for `src/compressed.rs`, the format is described there.
+fn compress(pixels: &[u16]) -> Vec<u8> {
+    const DIFF: u8 = 0x40;
+    const RUN: u8 = 0x80;
+    const LITERAL: u8 = 0xff;
+    const MAX_RUN: u8 = LITERAL - RUN;
+
+    let fields = |c: u16| (c >> 11, (c >> 5) & 0x3f, c & 0x1f);
+    let hash = |c: u16| {
+        let (r, g, b) = fields(c);
+        (r as usize * 3 + g as usize * 5 + b as usize * 7) % 64
+    };
+    // -2..=1 as a 2 bit field biased by 2, wrapping like the decoder
+    let small = |from: u16, to: u16, bits: u32| {
+        let mask = (1 << bits) - 1;
+        let d = (to.wrapping_sub(from).wrapping_add(2)) & mask;
+        (d < 4).then_some(d as u8)
+    };
+
+    let mut out = Vec::new();
+    let mut index = [0u16; 64];
+    let mut previous = 0u16;
+    let mut run = 0u8;
+
+    for &pixel in pixels {
+        if pixel == previous {
+            run += 1;
+            if run == MAX_RUN {
+                out.push(RUN + run - 1);
+                run = 0;
+            }
+            continue;
+        }
+        if run > 0 {
+            out.push(RUN + run - 1);
+            run = 0;
+        }
+
+        let slot = hash(pixel);
+        if index[slot] == pixel {
+            out.push(slot as u8);
+        } else {
+            let (pr, pg, pb) = fields(previous);
+            let (r, g, b) = fields(pixel);
+            match (small(pr, r, 5), small(pg, g, 6), small(pb, b, 5)) {
+                (Some(dr), Some(dg), Some(db)) => out.push(DIFF | dr << 4 | dg << 2 | db),
+                _ => {
+                    out.push(LITERAL);
+                    out.extend_from_slice(&pixel.to_le_bytes());
+                }
+            }
+        }
+        index[slot] = pixel;
+        previous = pixel;
+    }
+    if run > 0 {
+        out.push(RUN + run - 1);
+    }
+    out
+}
@@ -0,0 +1,261 @@
+//! What the machine counts for the operator: the audit meters and the last games.
+
+use core::fmt;
+
+/// How many games `History` keeps.
+pub const HISTORY_LEN: usize = 32;
+
+/// Totals since the firmware started, in credits where it is money.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Meters {
+    pub games: u32,
+    /// Everything bet.
+    pub coin_in: u64,
+    /// Everything won.
+    pub coin_out: u64,
+    pub wins: u32,
+    pub jackpots: u32,
+    pub cards_in: u32,
+    pub cash_outs: u32,
+}
+
+impl Meters {
+    /// Every meter with its name, in the order the console prints them.
+    pub fn entries(&self) -> [(&'static str, u64); 7] {
+        [
+            ("games", self.games as u64),
+            ("coin_in", self.coin_in),
+            ("coin_out", self.coin_out),
+            ("wins", self.wins as u64),
+            ("jackpots", self.jackpots as u64),
+            ("cards_in", self.cards_in as u64),
+            ("cash_outs", self.cash_outs as u64),
+        ]
+    }
+
+    /// Sets the meter called `name`, as `entries` names it. `false` for a name it
+    /// doesn't know.
+    pub fn set(&mut self, name: &str, value: u64) -> bool {
+        match name {
+            "games" => self.games = value as u32,
+            "coin_in" => self.coin_in = value,
+            "coin_out" => self.coin_out = value,
+            "wins" => self.wins = value as u32,
+            "jackpots" => self.jackpots = value as u32,
+            "cards_in" => self.cards_in = value as u32,
+            "cash_outs" => self.cash_outs = value as u32,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// One game as the console prints it: `<number> <bet> <reel> <reel> <reel> <win>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Game {
+    /// Counts up from 1 since the firmware started.
+    pub number: u32,
+    pub bet: i32,
+    /// The symbols the reels stopped on.
+    pub reels: [u8; 3],
+    /// Zero for a loss.
+    pub win: i32,
+}
+
+impl fmt::Display for Game {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [a, b, c] = self.reels;
+        write!(
+            f,
+            "{} {} {} {} {} {}",
+            self.number, self.bet, a, b, c, self.win
+        )
+    }
+}
+
+impl Game {
+    /// Reads a line the way `Display` writes it.
+    pub fn parse(line: &str) -> Option<Game> {
+        let mut words = line.split_ascii_whitespace();
+        let mut next = || words.next();
+        let game = Game {
+            number: next()?.parse().ok()?,
+            bet: next()?.parse().ok()?,
+            reels: [
+                next()?.parse().ok()?,
+                next()?.parse().ok()?,
+                next()?.parse().ok()?,
+            ],
+            win: next()?.parse().ok()?,
+        };
+        next().is_none().then_some(game)
+    }
+}
+
+/// The last `HISTORY_LEN` games, the oldest is dropped first.
+pub struct History {
+    games: [Option<Game>; HISTORY_LEN],
+    next: usize,
+}
+
+impl History {
+    pub const fn new() -> Self {
+        History {
+            games: [None; HISTORY_LEN],
+            next: 0,
+        }
+    }
+
+    pub fn push(&mut self, game: Game) {
+        self.games[self.next] = Some(game);
+        self.next = (self.next + 1) % HISTORY_LEN;
+    }
+
+    /// Oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &Game> {
+        let (newer, older) = self.games.split_at(self.next);
+        older.iter().chain(newer).flatten()
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The meters and the history, updated by the game and read by the console.
+#[derive(Default)]
+pub struct Audit {
+    pub meters: Meters,
+    pub history: History,
+}
+
+impl Audit {
+    pub const fn new() -> Self {
+        Audit {
+            meters: Meters {
+                games: 0,
+                coin_in: 0,
+                coin_out: 0,
+                wins: 0,
+                jackpots: 0,
+                cards_in: 0,
+                cash_outs: 0,
+            },
+            history: History::new(),
+        }
+    }
+
+    /// Counts a finished game, `jackpot` if it won the top tier.
+    pub fn game(&mut self, bet: i32, reels: [u8; 3], win: i32, jackpot: bool) {
+        let meters = &mut self.meters;
+        meters.games += 1;
+        meters.coin_in += bet.max(0) as u64;
+        if win > 0 {
+            meters.coin_out += win as u64;
+            meters.wins += 1;
+        }
+        if jackpot {
+            meters.jackpots += 1;
+        }
+        self.history.push(Game {
+            number: meters.games,
+            bet,
+            reels,
+            win,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_games_and_money() {
+        let mut audit = Audit::new();
+        audit.game(500, [0, 1, 2], 0, false);
+        audit.game(1000, [3, 3, 3], 50_000, true);
+        assert_eq!(
+            audit.meters,
+            Meters {
+                games: 2,
+                coin_in: 1500,
+                coin_out: 50_000,
+                wins: 1,
+                jackpots: 1,
+                ..Meters::default()
+            }
+        );
+    }
+
+    #[test]
+    fn history_keeps_the_last_games_oldest_first() {
+        let mut audit = Audit::new();
+        for _ in 0..HISTORY_LEN + 5 {
+            audit.game(500, [0, 0, 1], 0, false);
+        }
+        let mut numbers = audit.history.iter().map(|game| game.number);
+        assert_eq!(numbers.next(), Some(6));
+        assert_eq!(numbers.last(), Some(HISTORY_LEN as u32 + 5));
+        assert_eq!(audit.history.iter().count(), HISTORY_LEN);
+    }
+
+    #[test]
+    fn meters_read_back_by_name() {
+        let meters = Meters {
+            games: 3,
+            coin_in: 1500,
+            cash_outs: 1,
+            ..Meters::default()
+        };
+        let mut read = Meters::default();
+        for (name, value) in meters.entries() {
+            assert!(read.set(name, value));
+        }
+        assert_eq!(read, meters);
+        assert!(!read.set("credits", 1));
+    }
+
+    #[test]
+    fn games_read_back_the_way_they_print() {
+        let game = Game {
+            number: 12,
+            bet: 1000,
+            reels: [0, 0, 5],
+            win: 15_000,
+        };
+        let mut text = [0u8; 32];
+        let mut out = Out(&mut text, 0);
+        fmt::write(&mut out, format_args!("{}", game)).unwrap();
+        let len = out.1;
+        let line = core::str::from_utf8(&text[..len]).unwrap();
+        assert_eq!(line, "12 1000 0 0 5 15000");
+        assert_eq!(Game::parse(line), Some(game));
+        assert_eq!(Game::parse("12 1000 0 0 5"), None);
+        assert_eq!(Game::parse("12 1000 0 0 5 15000 1"), None);
+        assert_eq!(Game::parse("12 1000 0 0 x 15000"), None);
+    }
+
+    struct Out<'a>(&'a mut [u8], usize);
+
+    impl fmt::Write for Out<'_> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let end = self.1 + s.len();
+            self.0
+                .get_mut(self.1..end)
+                .ok_or(fmt::Error)?
+                .copy_from_slice(s.as_bytes());
+            self.1 = end;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn an_empty_history_has_no_games() {
+        assert_eq!(History::new().iter().count(), 0);
+    }
+}
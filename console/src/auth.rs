@@ -0,0 +1,173 @@
+//! The login for commands that move money.
+//!
+//! A login lasts `SESSION_MS` after the last command. After `MAX_FAILURES` wrong PINs
+//! in a row the console takes no login for `LOCKOUT_MS`, so guessing a four digit PIN
+//! takes days. The PIN is stored in the EEPROM, a machine without one takes no login
+//! at all until `pin set` stores the first one.
+
+use crate::Command;
+
+/// A login ends this long after the last command.
+pub const SESSION_MS: u64 = 5 * 60 * 1000;
+pub const MAX_FAILURES: u8 = 3;
+pub const LOCKOUT_MS: u64 = 60 * 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AuthError {
+    /// No PIN was set yet.
+    NoPin,
+    WrongPin,
+    /// Too many wrong PINs, try again at `until_ms`.
+    Locked {
+        until_ms: u64,
+    },
+}
+
+pub struct Auth {
+    pin: Option<u32>,
+    /// Logged in until then.
+    session_ends_ms: Option<u64>,
+    failures: u8,
+    locked_until_ms: u64,
+}
+
+impl Auth {
+    pub const fn new(pin: Option<u32>) -> Self {
+        Auth {
+            pin,
+            session_ends_ms: None,
+            failures: 0,
+            locked_until_ms: 0,
+        }
+    }
+
+    pub fn login(&mut self, pin: u32, now_ms: u64) -> Result<(), AuthError> {
+        let Some(expected) = self.pin else {
+            return Err(AuthError::NoPin);
+        };
+        if now_ms < self.locked_until_ms {
+            return Err(AuthError::Locked {
+                until_ms: self.locked_until_ms,
+            });
+        }
+        if pin != expected {
+            self.session_ends_ms = None;
+            self.failures += 1;
+            if self.failures >= MAX_FAILURES {
+                self.failures = 0;
+                self.locked_until_ms = now_ms + LOCKOUT_MS;
+                return Err(AuthError::Locked {
+                    until_ms: self.locked_until_ms,
+                });
+            }
+            return Err(AuthError::WrongPin);
+        }
+        self.failures = 0;
+        self.session_ends_ms = Some(now_ms + SESSION_MS);
+        Ok(())
+    }
+
+    pub fn logout(&mut self) {
+        self.session_ends_ms = None;
+    }
+
+    /// Takes `pin` from now on, once it is stored.
+    pub fn set_pin(&mut self, pin: u32) {
+        self.pin = Some(pin);
+    }
+
+    /// Whether `command` may run at `now_ms`. Nobody can log in without a PIN, so the
+    /// first `pin set` runs without a login.
+    pub fn allows(&mut self, command: &Command, now_ms: u64) -> bool {
+        let logged_in = self.check(now_ms);
+        !command.needs_login()
+            || logged_in
+            || (self.pin.is_none() && matches!(command, Command::PinSet { .. }))
+    }
+
+    /// Whether somebody is logged in at `now_ms`. Every call made while logged in
+    /// keeps the login going for another `SESSION_MS`.
+    pub fn check(&mut self, now_ms: u64) -> bool {
+        match self.session_ends_ms {
+            Some(ends) if now_ms < ends => {
+                self.session_ends_ms = Some(now_ms + SESSION_MS);
+                true
+            }
+            _ => {
+                self.session_ends_ms = None;
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logs_in_with_the_right_pin() {
+        let mut auth = Auth::new(Some(2580));
+        assert!(!auth.check(0));
+        assert_eq!(auth.login(2580, 0), Ok(()));
+        assert!(auth.check(1000));
+        auth.logout();
+        assert!(!auth.check(1000));
+    }
+
+    #[test]
+    fn a_wrong_pin_ends_the_login() {
+        let mut auth = Auth::new(Some(2580));
+        auth.login(2580, 0).unwrap();
+        assert_eq!(auth.login(1111, 10), Err(AuthError::WrongPin));
+        assert!(!auth.check(20));
+    }
+
+    #[test]
+    fn the_login_runs_out_without_commands() {
+        let mut auth = Auth::new(Some(2580));
+        auth.login(2580, 0).unwrap();
+        assert!(auth.check(SESSION_MS - 1));
+        // the check above started the session over
+        assert!(auth.check(2 * SESSION_MS - 2));
+        assert!(!auth.check(3 * SESSION_MS));
+    }
+
+    #[test]
+    fn locks_after_too_many_wrong_pins() {
+        let mut auth = Auth::new(Some(2580));
+        assert_eq!(auth.login(1, 0), Err(AuthError::WrongPin));
+        assert_eq!(auth.login(2, 0), Err(AuthError::WrongPin));
+        let locked = Err(AuthError::Locked {
+            until_ms: LOCKOUT_MS,
+        });
+        assert_eq!(auth.login(3, 0), locked);
+        // not even the right pin gets in while locked
+        assert_eq!(auth.login(2580, LOCKOUT_MS - 1), locked);
+        assert_eq!(auth.login(2580, LOCKOUT_MS), Ok(()));
+    }
+
+    #[test]
+    fn the_right_pin_resets_the_failures() {
+        let mut auth = Auth::new(Some(2580));
+        auth.login(1, 0).unwrap_err();
+        auth.login(2, 0).unwrap_err();
+        auth.login(2580, 0).unwrap();
+        assert_eq!(auth.login(3, 0), Err(AuthError::WrongPin));
+    }
+
+    #[test]
+    fn takes_no_login_without_a_pin() {
+        let mut auth = Auth::new(None);
+        assert_eq!(auth.login(0, 0), Err(AuthError::NoPin));
+        assert!(!auth.allows(&Command::Reboot, 0));
+        // the first pin is set without a login, the next one needs it
+        assert!(auth.allows(&Command::PinSet { pin: 4711 }, 0));
+        auth.set_pin(4711);
+        assert!(!auth.allows(&Command::PinSet { pin: 1234 }, 0));
+        auth.login(4711, 0).unwrap();
+        assert!(auth.allows(&Command::PinSet { pin: 1234 }, 10));
+        assert!(auth.allows(&Command::Balance, 10));
+    }
+}
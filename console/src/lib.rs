@@ -0,0 +1,451 @@
+//! The operator console on the machine's USB serial port.
+//!
+//! Commands are lines of text, ended by `\r`, `\n` or both:
+//!
+//! ```text
+//! help                        what the console understands
+//! balance                     the balance in the machine and whether a card is in
+//! cards list                  every known card with its balance
+//! card set <uid> <amount>     changes a card's balance, needs a login
+//! meters                      the audit meters
+//! log dump                    the last games, oldest first
+//! paytable                    every line with what it pays for the base bet
+//! paytable set <line> <pays>  changes what a line pays, needs a login
+//! rng seed                    what the game's random numbers were seeded with, needs a login
+//! selftest                    checks the EEPROM and the card reader
+//! eeprom read <addr> <len>    up to 64 bytes of the EEPROM as hex
+//! eeprom write <addr> <hex>   writes up to 16 bytes in one page, needs a login
+//! reboot                      restarts the machine, needs a login
+//! pin set <pin>               stores the console PIN, needs a login once there is one
+//! login <pin> / logout
+//! ```
+//!
+//! Every answer is zero or more lines of output and then a line that is either `OK` or
+//! `ERR <reason>`, so a program on the other end knows where an answer stops. UIDs are
+//! 8 hex digits, colons between the bytes are allowed. EEPROM addresses are hex and
+//! data is two hex digits per byte. The console doesn't echo, use a
+//! terminal with local echo.
+//!
+//! Only `core` is used, the parser builds for the firmware and for the host.
+
+#![no_std]
+
+pub mod audit;
+mod auth;
+mod line;
+
+use core::fmt;
+use core::str::SplitAsciiWhitespace;
+
+pub use auth::{Auth, AuthError, LOCKOUT_MS, MAX_FAILURES, SESSION_MS};
+pub use line::{LineBuffer, LineError};
+
+/// Longest line the console takes.
+pub const MAX_LINE: usize = 64;
+/// Balances are `i32` in the firmware.
+pub const MAX_AMOUNT: u32 = i32::MAX as u32;
+/// Most bytes `eeprom read` prints, a page.
+pub const EEPROM_READ_MAX: usize = 64;
+/// Most bytes `eeprom write` takes, what fits on a line.
+pub const EEPROM_WRITE_MAX: usize = 16;
+
+pub type Uid = [u8; 4];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Command {
+    Help,
+    Balance,
+    CardsList,
+    CardSet {
+        uid: Uid,
+        amount: u32,
+    },
+    Meters,
+    LogDump,
+    Paytable,
+    PaytableSet {
+        line: u8,
+        pays: u32,
+    },
+    RngSeed,
+    SelfTest,
+    EepromRead {
+        addr: u16,
+        len: u8,
+    },
+    /// The first `len` bytes of `data`.
+    EepromWrite {
+        addr: u16,
+        len: u8,
+        data: [u8; EEPROM_WRITE_MAX],
+    },
+    Reboot,
+    PinSet {
+        pin: u32,
+    },
+    Login {
+        pin: u32,
+    },
+    Logout,
+}
+
+impl Command {
+    /// Commands that change a balance or the payouts, or could lose a balance, only
+    /// run after a login. A reboot drops the balance of a card that is in the machine,
+    /// an EEPROM write can change anything and the rng seed tells what the reels do next.
+    /// Setting the PIN needs one too, except for the first PIN, see `Auth::allows`.
+    pub fn needs_login(&self) -> bool {
+        matches!(
+            self,
+            Command::CardSet { .. }
+                | Command::PaytableSet { .. }
+                | Command::EepromWrite { .. }
+                | Command::RngSeed
+                | Command::Reboot
+                | Command::PinSet { .. }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ParseError {
+    Empty,
+    UnknownCommand,
+    /// The command is known but an argument is missing.
+    MissingArgument,
+    TooManyArguments,
+    BadUid,
+    BadNumber,
+    BadHex,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ParseError::Empty => "empty line",
+            ParseError::UnknownCommand => "unknown command, try help",
+            ParseError::MissingArgument => "missing argument",
+            ParseError::TooManyArguments => "too many arguments",
+            ParseError::BadUid => "a uid is 8 hex digits",
+            ParseError::BadNumber => "not a number",
+            ParseError::BadHex => "data is 1 to 16 bytes, two hex digits each",
+        })
+    }
+}
+
+/// Words are separated by any amount of whitespace, command words are not case
+/// sensitive.
+pub fn parse(line: &str) -> Result<Command, ParseError> {
+    let mut words = line.split_ascii_whitespace();
+    let first = words.next().ok_or(ParseError::Empty)?;
+    let is = |word: &str, name: &str| word.eq_ignore_ascii_case(name);
+    let command = if is(first, "help") {
+        Command::Help
+    } else if is(first, "balance") {
+        Command::Balance
+    } else if is(first, "cards") {
+        match word(&mut words)? {
+            second if is(second, "list") => Command::CardsList,
+            _ => return Err(ParseError::UnknownCommand),
+        }
+    } else if is(first, "card") {
+        match word(&mut words)? {
+            second if is(second, "set") => {
+                let uid = parse_uid(word(&mut words)?)?;
+                let amount = parse_number(word(&mut words)?)?;
+                if amount > MAX_AMOUNT {
+                    return Err(ParseError::BadNumber);
+                }
+                Command::CardSet { uid, amount }
+            }
+            _ => return Err(ParseError::UnknownCommand),
+        }
+    } else if is(first, "meters") {
+        Command::Meters
+    } else if is(first, "log") {
+        match word(&mut words)? {
+            second if is(second, "dump") => Command::LogDump,
+            _ => return Err(ParseError::UnknownCommand),
+        }
+    } else if is(first, "paytable") {
+        match words.next() {
+            None => Command::Paytable,
+            Some(second) if is(second, "set") => {
+                let line = parse_number(word(&mut words)?)?;
+                let pays = parse_number(word(&mut words)?)?;
+                Command::PaytableSet {
+                    line: line.try_into().map_err(|_| ParseError::BadNumber)?,
+                    pays,
+                }
+            }
+            Some(_) => return Err(ParseError::UnknownCommand),
+        }
+    } else if is(first, "rng") {
+        match word(&mut words)? {
+            second if is(second, "seed") => Command::RngSeed,
+            _ => return Err(ParseError::UnknownCommand),
+        }
+    } else if is(first, "selftest") {
+        Command::SelfTest
+    } else if is(first, "eeprom") {
+        match word(&mut words)? {
+            second if is(second, "read") => {
+                let addr = parse_addr(word(&mut words)?)?;
+                let len = parse_number(word(&mut words)?)?;
+                if len == 0 || len as usize > EEPROM_READ_MAX {
+                    return Err(ParseError::BadNumber);
+                }
+                Command::EepromRead {
+                    addr,
+                    len: len as u8,
+                }
+            }
+            second if is(second, "write") => {
+                let addr = parse_addr(word(&mut words)?)?;
+                let (data, len) = parse_data(word(&mut words)?)?;
+                Command::EepromWrite { addr, len, data }
+            }
+            _ => return Err(ParseError::UnknownCommand),
+        }
+    } else if is(first, "reboot") {
+        Command::Reboot
+    } else if is(first, "pin") {
+        match word(&mut words)? {
+            second if is(second, "set") => Command::PinSet {
+                pin: parse_number(word(&mut words)?)?,
+            },
+            _ => return Err(ParseError::UnknownCommand),
+        }
+    } else if is(first, "login") {
+        Command::Login {
+            pin: parse_number(word(&mut words)?)?,
+        }
+    } else if is(first, "logout") {
+        Command::Logout
+    } else {
+        return Err(ParseError::UnknownCommand);
+    };
+
+    match words.next() {
+        Some(_) => Err(ParseError::TooManyArguments),
+        None => Ok(command),
+    }
+}
+
+/// `50f36d14` or `50:f3:6d:14`.
+pub fn parse_uid(text: &str) -> Result<Uid, ParseError> {
+    let mut uid = [0u8; 4];
+    let mut digits = 0;
+    for c in text.chars() {
+        if c == ':' {
+            continue;
+        }
+        let value = c.to_digit(16).ok_or(ParseError::BadUid)? as u8;
+        if digits == 8 {
+            return Err(ParseError::BadUid);
+        }
+        uid[digits / 2] = uid[digits / 2] << 4 | value;
+        digits += 1;
+    }
+    if digits != 8 {
+        return Err(ParseError::BadUid);
+    }
+    Ok(uid)
+}
+
+fn word<'a>(words: &mut SplitAsciiWhitespace<'a>) -> Result<&'a str, ParseError> {
+    words.next().ok_or(ParseError::MissingArgument)
+}
+
+fn parse_number(text: &str) -> Result<u32, ParseError> {
+    text.parse().map_err(|_| ParseError::BadNumber)
+}
+
+fn parse_addr(text: &str) -> Result<u16, ParseError> {
+    u16::from_str_radix(text, 16).map_err(|_| ParseError::BadNumber)
+}
+
+fn parse_data(text: &str) -> Result<([u8; EEPROM_WRITE_MAX], u8), ParseError> {
+    let digits = text.as_bytes();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) || digits.len() > 2 * EEPROM_WRITE_MAX {
+        return Err(ParseError::BadHex);
+    }
+    let mut data = [0u8; EEPROM_WRITE_MAX];
+    for (byte, pair) in data.iter_mut().zip(digits.chunks(2)) {
+        let pair = core::str::from_utf8(pair).map_err(|_| ParseError::BadHex)?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| ParseError::BadHex)?;
+    }
+    Ok((data, (digits.len() / 2) as u8))
+}
+
+/// Writes a UID the way `parse_uid` reads it, `50f36d14`.
+pub struct UidHex(pub Uid);
+
+impl fmt::Display for UidHex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Hex(&self.0).fmt(f)
+    }
+}
+
+/// Writes bytes the way `eeprom write` takes them and `eeprom read` prints them.
+pub struct Hex<'a>(pub &'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_command() {
+        assert_eq!(parse("help"), Ok(Command::Help));
+        assert_eq!(parse("balance"), Ok(Command::Balance));
+        assert_eq!(parse("cards list"), Ok(Command::CardsList));
+        assert_eq!(
+            parse("card set 50f36d14 80000"),
+            Ok(Command::CardSet {
+                uid: [0x50, 0xf3, 0x6d, 0x14],
+                amount: 80000
+            })
+        );
+        assert_eq!(parse("meters"), Ok(Command::Meters));
+        assert_eq!(parse("log dump"), Ok(Command::LogDump));
+        assert_eq!(parse("paytable"), Ok(Command::Paytable));
+        assert_eq!(
+            parse("paytable set 8 200"),
+            Ok(Command::PaytableSet { line: 8, pays: 200 })
+        );
+        assert_eq!(parse("rng seed"), Ok(Command::RngSeed));
+        assert_eq!(parse("selftest"), Ok(Command::SelfTest));
+        assert_eq!(
+            parse("eeprom read 7fc0 13"),
+            Ok(Command::EepromRead {
+                addr: 0x7fc0,
+                len: 13
+            })
+        );
+        let mut data = [0u8; EEPROM_WRITE_MAX];
+        data[..3].copy_from_slice(&[0x43, 0x54, 0x01]);
+        assert_eq!(
+            parse("eeprom write 0 435401"),
+            Ok(Command::EepromWrite {
+                addr: 0,
+                len: 3,
+                data
+            })
+        );
+        assert_eq!(parse("reboot"), Ok(Command::Reboot));
+        assert_eq!(parse("pin set 4711"), Ok(Command::PinSet { pin: 4711 }));
+        assert_eq!(parse("login 1234"), Ok(Command::Login { pin: 1234 }));
+        assert_eq!(parse("logout"), Ok(Command::Logout));
+    }
+
+    #[test]
+    fn ignores_case_and_extra_whitespace() {
+        assert_eq!(parse("  CARDS   List \t"), Ok(Command::CardsList));
+        assert_eq!(
+            parse("Card SET 0a:55:34:00 0"),
+            Ok(Command::CardSet {
+                uid: [0x0a, 0x55, 0x34, 0x00],
+                amount: 0
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        assert_eq!(parse(""), Err(ParseError::Empty));
+        assert_eq!(parse("   "), Err(ParseError::Empty));
+        assert_eq!(parse("jackpot"), Err(ParseError::UnknownCommand));
+        assert_eq!(parse("cards"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("cards delete"), Err(ParseError::UnknownCommand));
+        assert_eq!(parse("card set 50f36d14"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("balance now"), Err(ParseError::TooManyArguments));
+        assert_eq!(parse("card set 50f36d14 -5"), Err(ParseError::BadNumber));
+        assert_eq!(
+            parse("card set 50f36d14 99999999999"),
+            Err(ParseError::BadNumber)
+        );
+        assert_eq!(
+            parse("card set 50f36d14 3000000000"),
+            Err(ParseError::BadNumber)
+        );
+        assert_eq!(parse("login pin"), Err(ParseError::BadNumber));
+        assert_eq!(parse("paytable set 8"), Err(ParseError::MissingArgument));
+        assert_eq!(parse("paytable set 300 1"), Err(ParseError::BadNumber));
+        assert_eq!(parse("paytable show"), Err(ParseError::UnknownCommand));
+        assert_eq!(parse("eeprom read 8000x 1"), Err(ParseError::BadNumber));
+        assert_eq!(parse("eeprom read 0 65"), Err(ParseError::BadNumber));
+        assert_eq!(parse("eeprom read 0 0"), Err(ParseError::BadNumber));
+        assert_eq!(parse("eeprom write 0 435"), Err(ParseError::BadHex));
+        assert_eq!(parse("eeprom write 0 4x"), Err(ParseError::BadHex));
+        assert_eq!(
+            parse("eeprom write 0 00112233445566778899aabbccddeeff00"),
+            Err(ParseError::BadHex)
+        );
+    }
+
+    #[test]
+    fn uids_are_eight_hex_digits() {
+        assert_eq!(parse_uid("0A553400"), Ok([0x0a, 0x55, 0x34, 0x00]));
+        assert_eq!(parse_uid("0a5534"), Err(ParseError::BadUid));
+        assert_eq!(parse_uid("0a55340000"), Err(ParseError::BadUid));
+        assert_eq!(parse_uid("0a55340g"), Err(ParseError::BadUid));
+    }
+
+    #[test]
+    fn uids_print_the_way_they_parse() {
+        let uid = [0x0a, 0x55, 0x34, 0x00];
+        let mut text = [0u8; 8];
+        let mut out = Out(&mut text, 0);
+        fmt::write(&mut out, format_args!("{}", UidHex(uid))).unwrap();
+        assert_eq!(&text, b"0a553400");
+        assert_eq!(parse_uid("0a553400"), Ok(uid));
+    }
+
+    #[test]
+    fn money_commands_need_a_login() {
+        let card_set = Command::CardSet {
+            uid: [0; 4],
+            amount: 0,
+        };
+        assert!(card_set.needs_login());
+        assert!(Command::Reboot.needs_login());
+        assert!(Command::PaytableSet { line: 0, pays: 0 }.needs_login());
+        let write = Command::EepromWrite {
+            addr: 0,
+            len: 1,
+            data: [0; EEPROM_WRITE_MAX],
+        };
+        assert!(write.needs_login());
+        assert!(Command::RngSeed.needs_login());
+        assert!(Command::PinSet { pin: 1 }.needs_login());
+        assert!(!Command::EepromRead { addr: 0, len: 1 }.needs_login());
+        assert!(!Command::Paytable.needs_login());
+        assert!(!Command::Balance.needs_login());
+        assert!(!Command::CardsList.needs_login());
+    }
+
+    struct Out<'a>(&'a mut [u8], usize);
+
+    impl fmt::Write for Out<'_> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let end = self.1 + s.len();
+            self.0
+                .get_mut(self.1..end)
+                .ok_or(fmt::Error)?
+                .copy_from_slice(s.as_bytes());
+            self.1 = end;
+            Ok(())
+        }
+    }
+}
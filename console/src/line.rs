@@ -0,0 +1,125 @@
+//! Collects the bytes that come in over USB into lines.
+
+use core::{fmt, str};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LineError {
+    /// The line was longer than the buffer, the rest of it was dropped.
+    TooLong,
+    NotUtf8,
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            LineError::TooLong => "line too long",
+            LineError::NotUtf8 => "not text",
+        })
+    }
+}
+
+/// A line is done at `\r` or `\n`, so `\r\n` gives one line and an empty one, which
+/// the caller skips. Backspace and delete take back the last byte.
+pub struct LineBuffer<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+    overflow: bool,
+    /// The last call returned a line, the next byte starts a new one.
+    done: bool,
+}
+
+impl<const N: usize> LineBuffer<N> {
+    pub const fn new() -> Self {
+        LineBuffer {
+            bytes: [0; N],
+            len: 0,
+            overflow: false,
+            done: false,
+        }
+    }
+
+    /// Adds `byte`, returns the line once it is complete.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&str, LineError>> {
+        if self.done {
+            self.len = 0;
+            self.overflow = false;
+            self.done = false;
+        }
+        match byte {
+            b'\r' | b'\n' => {
+                self.done = true;
+                if self.overflow {
+                    return Some(Err(LineError::TooLong));
+                }
+                Some(str::from_utf8(&self.bytes[..self.len]).map_err(|_| LineError::NotUtf8))
+            }
+            0x08 | 0x7f => {
+                self.len = self.len.saturating_sub(1);
+                None
+            }
+            _ if self.len == N => {
+                self.overflow = true;
+                None
+            }
+            _ => {
+                self.bytes[self.len] = byte;
+                self.len += 1;
+                None
+            }
+        }
+    }
+}
+
+impl<const N: usize> Default for LineBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed<'a, const N: usize>(
+        buffer: &'a mut LineBuffer<N>,
+        bytes: &[u8],
+    ) -> Option<Result<&'a str, LineError>> {
+        let (last, rest) = bytes.split_last().unwrap();
+        for &byte in rest {
+            assert_eq!(buffer.push(byte), None);
+        }
+        buffer.push(*last)
+    }
+
+    #[test]
+    fn ends_lines_at_cr_or_lf() {
+        let mut buffer = LineBuffer::<16>::new();
+        assert_eq!(feed(&mut buffer, b"meters\r"), Some(Ok("meters")));
+        assert_eq!(buffer.push(b'\n'), Some(Ok("")));
+        assert_eq!(feed(&mut buffer, b"balance\n"), Some(Ok("balance")));
+    }
+
+    #[test]
+    fn backspace_takes_back_a_byte() {
+        let mut buffer = LineBuffer::<16>::new();
+        assert_eq!(feed(&mut buffer, b"metx\x08ers\n"), Some(Ok("meters")));
+        assert_eq!(feed(&mut buffer, b"\x7f\x7fok\n"), Some(Ok("ok")));
+    }
+
+    #[test]
+    fn drops_lines_that_are_too_long() {
+        let mut buffer = LineBuffer::<4>::new();
+        assert_eq!(
+            feed(&mut buffer, b"balance\n"),
+            Some(Err(LineError::TooLong))
+        );
+        assert_eq!(feed(&mut buffer, b"help\n"), Some(Ok("help")));
+    }
+
+    #[test]
+    fn rejects_invalid_utf8() {
+        let mut buffer = LineBuffer::<4>::new();
+        assert_eq!(feed(&mut buffer, b"\xff\n"), Some(Err(LineError::NotUtf8)));
+    }
+}
@@ -0,0 +1,302 @@
+//! What the game keeps in the 32 KB AT24C256 on I2C, and where.
+//!
+//! ```text
+//! 0x0000  card table   "CT" | version | count | crc16
+//! 0x0010  card 0       uid[4] | balance | crc16
+//! 0x0020  card 1       ...one 16 byte slot per card, up to MAX_CARDS
+//! 0x7f40  console pin  "PN" | version | pin | crc16
+//! 0x7f80  paytable     "PT" | version | 9 payouts | crc16, see src/paytable.rs
+//! 0x7fc0  settings     "CF" | version | settings | crc16, see src/config.rs
+//! ```
+//!
+//! Numbers are big endian and every CRC is CRC-16/CCITT-FALSE over the bytes of the
+//! record before it. No record crosses a 64 byte page, so each one goes to the chip in
+//! a single write and a write cut short by a power loss only spoils its own CRC. A
+//! blank chip reads 0xff everywhere, which is never a valid record.
+//!
+//! Firmware from before the card table kept two cards at 0x0000 and 0x0008 as
+//! `uid[4] | balance` with no header and no CRC. `legacy_cards` reads those so they
+//! can be moved into the table.
+//!
+//! Only `core` is used, the layout builds for the firmware and for the host.
+
+#![no_std]
+
+use core::fmt;
+
+pub use arcade_link::crc16;
+
+pub const EEPROM_SIZE: usize = 0x8000;
+/// The chip takes at most a page per write and wraps around inside it.
+pub const PAGE_SIZE: usize = 64;
+
+pub type Uid = [u8; 4];
+
+pub const CARDS_ADDR: u16 = 0x0000;
+pub const HEADER_SIZE: usize = 6;
+/// Every card has a slot this big, four to a page.
+pub const SLOT_SIZE: usize = 16;
+pub const CARD_SIZE: usize = 10;
+pub const MAX_CARDS: usize = 64;
+/// Balances are `i32` in the firmware.
+pub const MAX_BALANCE: u32 = i32::MAX as u32;
+
+const CARDS_MAGIC: [u8; 2] = *b"CT";
+const CARDS_VERSION: u8 = 1;
+
+/// The page before the paytable. Without this record the console takes no login.
+pub const PIN_ADDR: u16 = 0x7f40;
+pub const PIN_SIZE: usize = 9;
+const PIN_MAGIC: [u8; 2] = *b"PN";
+const PIN_VERSION: u8 = 1;
+
+/// The page before the settings.
+pub const PAYTABLE_ADDR: u16 = 0x7f80;
+pub const PAYTABLE_SIZE: usize = 41;
+pub const PAYTABLE_MAGIC: [u8; 2] = *b"PT";
+pub const PAYTABLE_VERSION: u8 = 1;
+
+/// The last page.
+pub const CONFIG_ADDR: u16 = 0x7fc0;
+pub const CONFIG_SIZE: usize = 15;
+pub const CONFIG_MAGIC: [u8; 2] = *b"CF";
+pub const CONFIG_VERSION: u8 = 1;
+
+/// The two cards of the old layout.
+pub const LEGACY_SIZE: usize = 16;
+
+const _: () = assert!(card_addr(MAX_CARDS) <= PIN_ADDR);
+const _: () =
+    assert!(PIN_SIZE <= PAGE_SIZE && PAYTABLE_SIZE <= PAGE_SIZE && CONFIG_SIZE <= PAGE_SIZE);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RecordError {
+    /// Nothing was ever written there.
+    Blank,
+    BadMagic,
+    /// A version this firmware doesn't know.
+    BadVersion(u8),
+    BadCrc,
+    /// The CRC is right but a value is out of range.
+    BadValue,
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordError::Blank => f.write_str("blank"),
+            RecordError::BadMagic => f.write_str("not a record"),
+            RecordError::BadVersion(version) => write!(f, "unknown version {}", version),
+            RecordError::BadCrc => f.write_str("bad crc"),
+            RecordError::BadValue => f.write_str("value out of range"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Card {
+    pub uid: Uid,
+    pub balance: u32,
+}
+
+impl Card {
+    pub fn to_bytes(&self) -> [u8; CARD_SIZE] {
+        let mut bytes = [0u8; CARD_SIZE];
+        bytes[0..4].copy_from_slice(&self.uid);
+        bytes[4..8].copy_from_slice(&self.balance.to_be_bytes());
+        let crc = crc16(&bytes[..8]);
+        bytes[8..10].copy_from_slice(&crc.to_be_bytes());
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; CARD_SIZE]) -> Result<Card, RecordError> {
+        if bytes.iter().all(|&b| b == 0xff) {
+            return Err(RecordError::Blank);
+        }
+        if crc16(&bytes[..8]) != u16::from_be_bytes([bytes[8], bytes[9]]) {
+            return Err(RecordError::BadCrc);
+        }
+        let card = Card {
+            uid: [bytes[0], bytes[1], bytes[2], bytes[3]],
+            balance: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+        };
+        if card.balance > MAX_BALANCE {
+            return Err(RecordError::BadValue);
+        }
+        Ok(card)
+    }
+}
+
+/// Where the slot of card `index` starts.
+pub const fn card_addr(index: usize) -> u16 {
+    CARDS_ADDR + ((1 + index) * SLOT_SIZE) as u16
+}
+
+/// The card table header for `count` cards.
+pub fn header_bytes(count: usize) -> [u8; HEADER_SIZE] {
+    debug_assert!(count <= MAX_CARDS);
+    let mut bytes = [0u8; HEADER_SIZE];
+    bytes[0..2].copy_from_slice(&CARDS_MAGIC);
+    bytes[2] = CARDS_VERSION;
+    bytes[3] = count as u8;
+    let crc = crc16(&bytes[..4]);
+    bytes[4..6].copy_from_slice(&crc.to_be_bytes());
+    bytes
+}
+
+/// How many cards the table holds.
+pub fn read_header(bytes: &[u8; HEADER_SIZE]) -> Result<usize, RecordError> {
+    check_record(bytes, CARDS_MAGIC, CARDS_VERSION)?;
+    match bytes[3] as usize {
+        count if count <= MAX_CARDS => Ok(count),
+        _ => Err(RecordError::BadValue),
+    }
+}
+
+/// The console PIN record for `pin`.
+pub fn pin_bytes(pin: u32) -> [u8; PIN_SIZE] {
+    let mut bytes = [0u8; PIN_SIZE];
+    bytes[0..2].copy_from_slice(&PIN_MAGIC);
+    bytes[2] = PIN_VERSION;
+    bytes[3..7].copy_from_slice(&pin.to_be_bytes());
+    let crc = crc16(&bytes[..7]);
+    bytes[7..9].copy_from_slice(&crc.to_be_bytes());
+    bytes
+}
+
+/// The console PIN, `Err(RecordError::Blank)` if none was ever set.
+pub fn read_pin(bytes: &[u8; PIN_SIZE]) -> Result<u32, RecordError> {
+    check_record(bytes, PIN_MAGIC, PIN_VERSION)?;
+    Ok(u32::from_be_bytes([bytes[3], bytes[4], bytes[5], bytes[6]]))
+}
+
+/// Checks a record that starts with `magic` and `version` and ends with the CRC of
+/// everything before it, like the paytable and the settings.
+pub fn check_record(record: &[u8], magic: [u8; 2], version: u8) -> Result<(), RecordError> {
+    let (data, crc) = record.split_at(record.len() - 2);
+    if record.iter().all(|&b| b == 0xff) {
+        Err(RecordError::Blank)
+    } else if data[0..2] != magic {
+        Err(RecordError::BadMagic)
+    } else if data[2] != version {
+        Err(RecordError::BadVersion(data[2]))
+    } else if crc16(data) != u16::from_be_bytes([crc[0], crc[1]]) {
+        Err(RecordError::BadCrc)
+    } else {
+        Ok(())
+    }
+}
+
+/// The cards of the old layout at the start of the EEPROM. A blank record, or one
+/// with a balance the firmware can't hold, is left out.
+pub fn legacy_cards(bytes: &[u8; LEGACY_SIZE]) -> impl Iterator<Item = Card> + '_ {
+    bytes
+        .chunks_exact(8)
+        .filter(|record| record.iter().any(|&b| b != 0xff))
+        .map(|record| Card {
+            uid: [record[0], record[1], record[2], record[3]],
+            balance: u32::from_be_bytes([record[4], record[5], record[6], record[7]]),
+        })
+        .filter(|card| card.balance <= MAX_BALANCE)
+}
+
+/// Whether `len` bytes at `addr` are inside the chip and in one page, so one write
+/// takes them.
+pub fn within_page(addr: u16, len: usize) -> bool {
+    let start = addr as usize;
+    len > 0 && start + len <= EEPROM_SIZE && start / PAGE_SIZE == (start + len - 1) / PAGE_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CARD: Card = Card {
+        uid: [80, 243, 109, 20],
+        balance: 80000,
+    };
+
+    #[test]
+    fn cards_read_back_the_way_they_are_written() {
+        assert_eq!(Card::from_bytes(&CARD.to_bytes()), Ok(CARD));
+        assert_eq!(read_header(&header_bytes(2)), Ok(2));
+        assert_eq!(read_header(&header_bytes(0)), Ok(0));
+        assert_eq!(read_pin(&pin_bytes(4711)), Ok(4711));
+    }
+
+    #[test]
+    fn spoilt_records_are_rejected() {
+        let mut bytes = CARD.to_bytes();
+        bytes[5] ^= 1;
+        assert_eq!(Card::from_bytes(&bytes), Err(RecordError::BadCrc));
+        assert_eq!(
+            Card::from_bytes(&[0xff; CARD_SIZE]),
+            Err(RecordError::Blank)
+        );
+        let too_much = Card {
+            balance: u32::MAX,
+            ..CARD
+        };
+        assert_eq!(
+            Card::from_bytes(&too_much.to_bytes()),
+            Err(RecordError::BadValue)
+        );
+
+        assert_eq!(read_header(&[0xff; HEADER_SIZE]), Err(RecordError::Blank));
+        assert_eq!(read_pin(&[0xff; PIN_SIZE]), Err(RecordError::Blank));
+        let mut pin = pin_bytes(4711);
+        pin[6] ^= 1;
+        assert_eq!(read_pin(&pin), Err(RecordError::BadCrc));
+        let mut header = header_bytes(2);
+        header[3] = 3;
+        assert_eq!(read_header(&header), Err(RecordError::BadCrc));
+        // the old layout starts with a UID
+        let legacy = [80, 243, 109, 20, 0, 1, 56, 128];
+        assert_eq!(
+            read_header(legacy[..HEADER_SIZE].try_into().unwrap()),
+            Err(RecordError::BadMagic)
+        );
+    }
+
+    #[test]
+    fn checks_records_with_a_magic_and_a_version() {
+        let mut record = [0u8; 7];
+        record[0..3].copy_from_slice(b"PT\x01");
+        record[3..5].copy_from_slice(&[1, 2]);
+        let crc = crc16(&record[..5]);
+        record[5..7].copy_from_slice(&crc.to_be_bytes());
+        assert_eq!(check_record(&record, *b"PT", 1), Ok(()));
+        assert_eq!(
+            check_record(&record, *b"PT", 2),
+            Err(RecordError::BadVersion(1))
+        );
+        assert_eq!(check_record(&record, *b"CF", 1), Err(RecordError::BadMagic));
+    }
+
+    #[test]
+    fn reads_the_old_layout() {
+        let mut bytes = [0xff; LEGACY_SIZE];
+        bytes[0..4].copy_from_slice(&CARD.uid);
+        bytes[4..8].copy_from_slice(&CARD.balance.to_be_bytes());
+        assert!(legacy_cards(&bytes).eq([CARD]));
+        assert_eq!(legacy_cards(&[0xff; LEGACY_SIZE]).count(), 0);
+    }
+
+    #[test]
+    fn slots_stay_in_their_pages() {
+        assert_eq!(card_addr(0), 0x0010);
+        assert_eq!(card_addr(3), 0x0040);
+        for index in 0..MAX_CARDS {
+            assert!(within_page(card_addr(index), CARD_SIZE));
+        }
+        assert!(within_page(CARDS_ADDR, HEADER_SIZE));
+        assert!(within_page(PIN_ADDR, PIN_SIZE));
+        assert!(within_page(PAYTABLE_ADDR, PAYTABLE_SIZE));
+        assert!(within_page(CONFIG_ADDR, CONFIG_SIZE));
+        assert!(!within_page(0x003c, 8));
+        assert!(!within_page(0x7ff8, 16));
+        assert!(!within_page(0x0000, 0));
+    }
+}
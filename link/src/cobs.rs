@@ -0,0 +1,116 @@
+//! Consistent overhead byte stuffing.
+//!
+//! An encoded frame never contains a zero byte, so zero marks the end of a frame and a
+//! receiver that lost track just waits for the next one.
+
+/// Encodes `data` into `out` and returns the encoded length, without the delimiter.
+/// `out` needs room for `data.len() + data.len() / 254 + 1` bytes.
+pub fn encode(data: &[u8], out: &mut [u8]) -> Option<usize> {
+    let mut code_at = 0;
+    let mut len = 1;
+    let mut code = 1u8;
+
+    for &byte in data {
+        if byte == 0 {
+            *out.get_mut(code_at)? = code;
+            code_at = len;
+            len += 1;
+            code = 1;
+            continue;
+        }
+        *out.get_mut(len)? = byte;
+        len += 1;
+        code += 1;
+        if code == 0xff {
+            *out.get_mut(code_at)? = code;
+            code_at = len;
+            len += 1;
+            code = 1;
+        }
+    }
+    *out.get_mut(code_at)? = code;
+    Some(len)
+}
+
+/// Decodes `data` in place and returns the decoded length, `None` when it isn't valid.
+pub fn decode(data: &mut [u8]) -> Option<usize> {
+    let mut read = 0;
+    let mut write = 0;
+
+    while read < data.len() {
+        let code = data[read];
+        if code == 0 || read + code as usize > data.len() {
+            return None;
+        }
+        read += 1;
+        for _ in 1..code {
+            data[write] = data[read];
+            write += 1;
+            read += 1;
+        }
+        // a full block has no zero after it, and neither has the last one
+        if code != 0xff && read < data.len() {
+            data[write] = 0;
+            write += 1;
+        }
+    }
+    Some(write)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Encodes and decodes `data`, checking there is no zero in between.
+    fn round_trip(data: &[u8]) {
+        let mut out = [0xaa; 600];
+        let len = encode(data, &mut out).unwrap();
+        assert!(len <= data.len() + data.len() / 254 + 1);
+        assert!(!out[..len].contains(&0));
+        let decoded = decode(&mut out[..len]).unwrap();
+        assert_eq!(&out[..decoded], data);
+    }
+
+    #[test]
+    fn encodes_the_empty_payload() {
+        let mut out = [0; 1];
+        assert_eq!(encode(&[], &mut out), Some(1));
+        assert_eq!(out, [1]);
+        assert_eq!(decode(&mut out), Some(0));
+    }
+
+    #[test]
+    fn encodes_zeros() {
+        let mut out = [0; 4];
+        assert_eq!(encode(&[0, 0, 0], &mut out), Some(4));
+        assert_eq!(out, [1, 1, 1, 1]);
+        round_trip(&[0]);
+        round_trip(&[0; 300]);
+        round_trip(&[0, 1, 0, 2, 0]);
+    }
+
+    #[test]
+    fn splits_long_runs() {
+        let mut data = [0u8; 520];
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = (i % 255) as u8 + 1;
+        }
+        for len in [253, 254, 255, 508, 509, 520] {
+            round_trip(&data[..len]);
+        }
+        let mut out = [0; 256];
+        assert_eq!(encode(&data[..254], &mut out), Some(256));
+        assert_eq!((out[0], out[255]), (0xff, 1));
+        // a zero right after a full block
+        data[254] = 0;
+        round_trip(&data[..300]);
+    }
+
+    #[test]
+    fn needs_room_and_valid_codes() {
+        assert_eq!(encode(&[1, 2, 3], &mut [0; 3]), None);
+        // a code that points past the end, and a zero inside the frame
+        assert_eq!(decode(&mut [5, 1, 2]), None);
+        assert_eq!(decode(&mut [2, 1, 0, 1]), None);
+    }
+}
@@ -0,0 +1,30 @@
+//! CRC-16/CCITT-FALSE over the frame contents.
+
+const POLYNOMIAL: u16 = 0x1021;
+const INIT: u16 = 0xffff;
+
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = INIT;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ POLYNOMIAL
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29b1);
+        assert_eq!(crc16(&[]), INIT);
+    }
+}
@@ -0,0 +1,549 @@
+//! Messages between the main Pico and the secondary display Pico.
+//!
+//! The secondary display sits on its own board, so the main firmware tells it what
+//! happens in the game over UART. Every message goes out as one frame:
+//!
+//! ```text
+//! COBS( seq | type | payload | crc16 ) 0x00
+//! ```
+//!
+//! `seq` counts up by one for every frame, `crc16` is CRC-16/CCITT-FALSE over
+//! everything before it and numbers are little endian. Zero only ever ends a frame,
+//! so a receiver that lost track drops bytes until the next zero. When a frame is
+//! corrupt or one went missing, the receiver asks for `Resync` and the sender answers
+//! with the whole state, see `Message::is_state`. Both sides send a `Heartbeat` every
+//! `HEARTBEAT_MS` and consider the other one gone after `LINK_TIMEOUT_MS` of silence.
+//!
+//! Only `core` is used, the codec builds for both firmwares and for the host.
+
+#![no_std]
+
+mod cobs;
+mod crc;
+
+pub use crc::crc16;
+
+pub const BAUD_RATE: u32 = 115_200;
+pub const HEARTBEAT_MS: u64 = 500;
+pub const LINK_TIMEOUT_MS: u64 = 2000;
+
+/// Longest payload of any message.
+const MAX_PAYLOAD: usize = 9;
+/// seq, type, payload and crc before stuffing.
+const MAX_RAW: usize = 2 + MAX_PAYLOAD + 2;
+/// Longest frame on the wire, with the COBS overhead and the delimiter.
+pub const MAX_FRAME: usize = MAX_RAW + 2;
+
+const DELIMITER: u8 = 0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Tier {
+    Small,
+    Big,
+    Mega,
+    Jackpot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Message {
+    Heartbeat {
+        uptime_ms: u32,
+    },
+    SpinStart {
+        bet: i32,
+    },
+    /// The symbol ids the reels stopped on and what they paid, zero for a loss.
+    Result {
+        reels: [u8; 3],
+        win: i32,
+    },
+    Win {
+        tier: Tier,
+        amount: i32,
+    },
+    /// The top award for the current bet and the last jackpot won.
+    Jackpot {
+        top_award: i32,
+        last_hit: i32,
+    },
+    /// Whether a card is inserted, with its balance and the bet.
+    Session {
+        card: bool,
+        balance: i32,
+        bet: i32,
+    },
+    /// Asks the other side to send its whole state again.
+    Resync,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The frame didn't fit the receive buffer.
+    Overflow,
+    /// Not valid COBS.
+    Stuffing,
+    Crc,
+    /// Too short, or the wrong length for its type.
+    Length,
+    UnknownType(u8),
+    /// A field holds something no sender writes.
+    Value,
+}
+
+impl Message {
+    /// Messages that make up the state a sender repeats after a `Resync`.
+    pub fn is_state(&self) -> bool {
+        matches!(self, Message::Jackpot { .. } | Message::Session { .. })
+    }
+
+    fn kind(&self) -> u8 {
+        match self {
+            Message::Heartbeat { .. } => 0x01,
+            Message::SpinStart { .. } => 0x02,
+            Message::Result { .. } => 0x03,
+            Message::Win { .. } => 0x04,
+            Message::Jackpot { .. } => 0x05,
+            Message::Session { .. } => 0x06,
+            Message::Resync => 0x07,
+        }
+    }
+
+    /// Writes the payload into `out` and returns its length.
+    fn write_payload(&self, out: &mut [u8; MAX_PAYLOAD]) -> usize {
+        match *self {
+            Message::Heartbeat { uptime_ms } => put(out, 0, &uptime_ms.to_le_bytes()),
+            Message::SpinStart { bet } => put(out, 0, &bet.to_le_bytes()),
+            Message::Result { reels, win } => {
+                let len = put(out, 0, &reels);
+                put(out, len, &win.to_le_bytes())
+            }
+            Message::Win { tier, amount } => {
+                out[0] = tier as u8;
+                put(out, 1, &amount.to_le_bytes())
+            }
+            Message::Jackpot {
+                top_award,
+                last_hit,
+            } => {
+                let len = put(out, 0, &top_award.to_le_bytes());
+                put(out, len, &last_hit.to_le_bytes())
+            }
+            Message::Session { card, balance, bet } => {
+                out[0] = card as u8;
+                let len = put(out, 1, &balance.to_le_bytes());
+                put(out, len, &bet.to_le_bytes())
+            }
+            Message::Resync => 0,
+        }
+    }
+
+    fn read(kind: u8, payload: &[u8]) -> Result<Self, Error> {
+        let message = match (kind, payload.len()) {
+            (0x01, 4) => Message::Heartbeat {
+                uptime_ms: u32::from_le_bytes(word(payload, 0)),
+            },
+            (0x02, 4) => Message::SpinStart {
+                bet: i32::from_le_bytes(word(payload, 0)),
+            },
+            (0x03, 7) => Message::Result {
+                reels: [payload[0], payload[1], payload[2]],
+                win: i32::from_le_bytes(word(payload, 3)),
+            },
+            (0x04, 5) => Message::Win {
+                tier: match payload[0] {
+                    0 => Tier::Small,
+                    1 => Tier::Big,
+                    2 => Tier::Mega,
+                    3 => Tier::Jackpot,
+                    _ => return Err(Error::Value),
+                },
+                amount: i32::from_le_bytes(word(payload, 1)),
+            },
+            (0x05, 8) => Message::Jackpot {
+                top_award: i32::from_le_bytes(word(payload, 0)),
+                last_hit: i32::from_le_bytes(word(payload, 4)),
+            },
+            (0x06, 9) => Message::Session {
+                card: payload[0] != 0,
+                balance: i32::from_le_bytes(word(payload, 1)),
+                bet: i32::from_le_bytes(word(payload, 5)),
+            },
+            (0x07, 0) => Message::Resync,
+            (0x01..=0x07, _) => return Err(Error::Length),
+            (kind, _) => return Err(Error::UnknownType(kind)),
+        };
+        Ok(message)
+    }
+}
+
+/// Builds frames, numbering them as it goes.
+#[derive(Default)]
+pub struct Sender {
+    seq: u8,
+}
+
+impl Sender {
+    pub fn new() -> Self {
+        Sender { seq: 0 }
+    }
+
+    /// Writes the frame for `message` into `out` and returns the bytes to send.
+    pub fn frame<'a>(&mut self, message: &Message, out: &'a mut [u8; MAX_FRAME]) -> &'a [u8] {
+        let len = encode(self.seq, message, out);
+        self.seq = self.seq.wrapping_add(1);
+        &out[..len]
+    }
+}
+
+/// Encodes one frame with sequence number `seq` into `out`, returns its length
+/// including the delimiter.
+pub fn encode(seq: u8, message: &Message, out: &mut [u8; MAX_FRAME]) -> usize {
+    let mut raw = [0u8; MAX_RAW];
+    raw[0] = seq;
+    raw[1] = message.kind();
+    let mut payload = [0u8; MAX_PAYLOAD];
+    let payload_len = message.write_payload(&mut payload);
+    raw[2..2 + payload_len].copy_from_slice(&payload[..payload_len]);
+
+    let crc_at = 2 + payload_len;
+    let crc = crc16(&raw[..crc_at]);
+    raw[crc_at..crc_at + 2].copy_from_slice(&crc.to_le_bytes());
+
+    // MAX_FRAME leaves room for the stuffing of the longest message
+    let len = cobs::encode(&raw[..crc_at + 2], out).unwrap_or(0);
+    out[len] = DELIMITER;
+    len + 1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Frame {
+    pub seq: u8,
+    pub message: Message,
+}
+
+/// Collects received bytes into frames.
+pub struct Decoder {
+    buffer: [u8; MAX_FRAME],
+    len: usize,
+    overflow: bool,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Decoder {
+            buffer: [0; MAX_FRAME],
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    /// Feeds one byte, returns the frame it completed.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Frame, Error>> {
+        if byte != DELIMITER {
+            if self.len < self.buffer.len() {
+                self.buffer[self.len] = byte;
+                self.len += 1;
+            } else {
+                self.overflow = true;
+            }
+            return None;
+        }
+
+        let (len, overflow) = (self.len, self.overflow);
+        self.len = 0;
+        self.overflow = false;
+        match (len, overflow) {
+            // two delimiters in a row, e.g. a sender flushing the line after a restart
+            (0, false) => None,
+            (_, true) => Some(Err(Error::Overflow)),
+            _ => Some(self.decode(len)),
+        }
+    }
+
+    fn decode(&mut self, len: usize) -> Result<Frame, Error> {
+        let raw = &mut self.buffer[..len];
+        let len = cobs::decode(raw).ok_or(Error::Stuffing)?;
+        if len < 4 {
+            return Err(Error::Length);
+        }
+
+        let crc_at = len - 2;
+        let crc = u16::from_le_bytes([raw[crc_at], raw[crc_at + 1]]);
+        if crc16(&raw[..crc_at]) != crc {
+            return Err(Error::Crc);
+        }
+        Ok(Frame {
+            seq: raw[0],
+            message: Message::read(raw[1], &raw[2..crc_at])?,
+        })
+    }
+}
+
+/// A `Decoder` that also notices frames that never arrived.
+#[derive(Default)]
+pub struct Receiver {
+    decoder: Decoder,
+    next_seq: Option<u8>,
+    lost: bool,
+}
+
+impl Receiver {
+    pub const fn new() -> Self {
+        Receiver {
+            decoder: Decoder::new(),
+            next_seq: None,
+            lost: false,
+        }
+    }
+
+    /// Feeds one byte, returns the message it completed.
+    pub fn push(&mut self, byte: u8) -> Option<Message> {
+        match self.decoder.push(byte)? {
+            Ok(frame) => {
+                if self.next_seq.is_some_and(|seq| seq != frame.seq) {
+                    self.lost = true;
+                }
+                self.next_seq = Some(frame.seq.wrapping_add(1));
+                Some(frame.message)
+            }
+            Err(_) => {
+                self.lost = true;
+                None
+            }
+        }
+    }
+
+    /// Whether a frame was lost or corrupt since the last call. The state has to be
+    /// asked for again with `Resync` then.
+    pub fn take_lost(&mut self) -> bool {
+        core::mem::take(&mut self.lost)
+    }
+}
+
+fn put(out: &mut [u8; MAX_PAYLOAD], at: usize, bytes: &[u8]) -> usize {
+    out[at..at + bytes.len()].copy_from_slice(bytes);
+    at + bytes.len()
+}
+
+fn word(payload: &[u8], at: usize) -> [u8; 4] {
+    [
+        payload[at],
+        payload[at + 1],
+        payload[at + 2],
+        payload[at + 3],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGES: [Message; 8] = [
+        Message::Heartbeat { uptime_ms: 123_456 },
+        Message::SpinStart { bet: 50 },
+        Message::Result {
+            reels: [0, 3, 0],
+            win: 0,
+        },
+        Message::Win {
+            tier: Tier::Jackpot,
+            amount: 500_000,
+        },
+        Message::Jackpot {
+            top_award: 250_000,
+            last_hit: -1,
+        },
+        Message::Session {
+            card: true,
+            balance: 80_000,
+            bet: 100,
+        },
+        Message::Session {
+            card: false,
+            balance: 0,
+            bet: 0,
+        },
+        Message::Resync,
+    ];
+
+    fn feed(decoder: &mut Decoder, bytes: &[u8]) -> Option<Result<Frame, Error>> {
+        let mut last = None;
+        for &byte in bytes {
+            if let Some(frame) = decoder.push(byte) {
+                last = Some(frame);
+            }
+        }
+        last
+    }
+
+    /// Stuffs `raw` with a good or a bad CRC into a frame the way `encode` does.
+    fn raw_frame(raw: &[u8], crc_ok: bool) -> Option<Result<Frame, Error>> {
+        let mut data = [0u8; 32];
+        data[..raw.len()].copy_from_slice(raw);
+        let crc = crc16(raw) ^ !crc_ok as u16;
+        data[raw.len()..raw.len() + 2].copy_from_slice(&crc.to_le_bytes());
+        let mut out = [0u8; 40];
+        let len = cobs::encode(&data[..raw.len() + 2], &mut out).unwrap();
+        out[len] = DELIMITER;
+        feed(&mut Decoder::new(), &out[..len + 1])
+    }
+
+    #[test]
+    fn messages_survive_the_round_trip() {
+        let mut out = [0u8; MAX_FRAME];
+        for (seq, message) in MESSAGES.iter().enumerate() {
+            let len = encode(seq as u8, message, &mut out);
+            assert_eq!(out[len - 1], DELIMITER);
+            assert!(!out[..len - 1].contains(&DELIMITER));
+            let frame = feed(&mut Decoder::new(), &out[..len]);
+            assert_eq!(
+                frame,
+                Some(Ok(Frame {
+                    seq: seq as u8,
+                    message: *message
+                }))
+            );
+        }
+    }
+
+    #[test]
+    fn the_sender_numbers_its_frames() {
+        let mut sender = Sender { seq: 254 };
+        let mut decoder = Decoder::new();
+        let mut out = [0u8; MAX_FRAME];
+        for seq in [254, 255, 0] {
+            let frame = feed(&mut decoder, sender.frame(&Message::Resync, &mut out));
+            assert_eq!(frame.unwrap().unwrap().seq, seq);
+        }
+    }
+
+    #[test]
+    fn rejects_a_bad_crc() {
+        assert!(raw_frame(&[0, 0x02, 50, 0, 0, 0], true).unwrap().is_ok());
+        assert_eq!(
+            raw_frame(&[0, 0x02, 50, 0, 0, 0], false),
+            Some(Err(Error::Crc))
+        );
+
+        let mut out = [0u8; MAX_FRAME];
+        let len = encode(7, &MESSAGES[5], &mut out);
+        // flip a bit of the balance, which doesn't make it zero
+        assert_eq!(out[4], 0x80);
+        out[4] ^= 0x01;
+        assert_eq!(
+            feed(&mut Decoder::new(), &out[..len]),
+            Some(Err(Error::Crc))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_lengths_and_values() {
+        // a heartbeat with three bytes of uptime
+        assert_eq!(
+            raw_frame(&[0, 0x01, 1, 2, 3], true),
+            Some(Err(Error::Length))
+        );
+        assert_eq!(raw_frame(&[0, 0x07, 1], true), Some(Err(Error::Length)));
+        // a seq and a crc only
+        assert_eq!(raw_frame(&[0], true), Some(Err(Error::Length)));
+        assert_eq!(
+            raw_frame(&[0, 0x08], true),
+            Some(Err(Error::UnknownType(0x08)))
+        );
+        assert_eq!(
+            raw_frame(&[0, 0x04, 4, 1, 0, 0, 0], true),
+            Some(Err(Error::Value))
+        );
+        assert_eq!(
+            feed(&mut Decoder::new(), &[5, 1, 2, 0]),
+            Some(Err(Error::Stuffing))
+        );
+        let mut decoder = Decoder::new();
+        assert_eq!(feed(&mut decoder, &[1; MAX_FRAME + 1]), None);
+        assert_eq!(decoder.push(DELIMITER), Some(Err(Error::Overflow)));
+        // nothing of the long frame is left over
+        assert_eq!(decoder.push(DELIMITER), None);
+    }
+
+    #[test]
+    fn the_receiver_resyncs_after_garbage() {
+        let mut receiver = Receiver::new();
+        let mut out = [0u8; MAX_FRAME];
+        let mut sender = Sender::new();
+
+        for &byte in &[0x13, 0x37, 0xff, 0x42, DELIMITER] {
+            assert_eq!(receiver.push(byte), None);
+        }
+        assert!(receiver.take_lost());
+        assert!(!receiver.take_lost());
+
+        let frame = sender.frame(&MESSAGES[0], &mut out);
+        let received: Option<Message> = frame.iter().filter_map(|&b| receiver.push(b)).last();
+        assert_eq!(received, Some(MESSAGES[0]));
+        assert!(!receiver.take_lost());
+    }
+
+    #[test]
+    fn the_receiver_resyncs_after_a_truncated_frame() {
+        let mut receiver = Receiver::new();
+        let mut sender = Sender::new();
+        // sends the first `keep` bytes of the next frame, returns what came out
+        let mut send = |receiver: &mut Receiver, message: &Message, keep: usize| {
+            let mut out = [0u8; MAX_FRAME];
+            let frame = sender.frame(message, &mut out);
+            let keep = keep.min(frame.len());
+            frame[..keep]
+                .iter()
+                .filter_map(|&b| receiver.push(b))
+                .last()
+        };
+
+        assert_eq!(
+            send(&mut receiver, &MESSAGES[1], MAX_FRAME),
+            Some(MESSAGES[1])
+        );
+        assert!(!receiver.take_lost());
+
+        // the end of a frame went missing, delimiter and all, so it runs into the next
+        // one and both are lost
+        assert_eq!(send(&mut receiver, &MESSAGES[2], 3), None);
+        assert_eq!(send(&mut receiver, &MESSAGES[3], MAX_FRAME), None);
+        assert!(receiver.take_lost());
+        assert_eq!(
+            send(&mut receiver, &MESSAGES[4], MAX_FRAME),
+            Some(MESSAGES[4])
+        );
+        // the gap in the sequence was noticed on the frame after
+        assert!(receiver.take_lost());
+        assert_eq!(
+            send(&mut receiver, &MESSAGES[5], MAX_FRAME),
+            Some(MESSAGES[5])
+        );
+        assert!(!receiver.take_lost());
+
+        // bytes went missing in the middle but the delimiter made it
+        assert_eq!(send(&mut receiver, &MESSAGES[0], 3), None);
+        assert_eq!(receiver.push(DELIMITER), None);
+        assert!(receiver.take_lost());
+        assert_eq!(
+            send(&mut receiver, &MESSAGES[6], MAX_FRAME),
+            Some(MESSAGES[6])
+        );
+        assert!(receiver.take_lost());
+        assert_eq!(
+            send(&mut receiver, &MESSAGES[7], MAX_FRAME),
+            Some(MESSAGES[7])
+        );
+        assert!(!receiver.take_lost());
+    }
+}
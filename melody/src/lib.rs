@@ -0,0 +1,335 @@
+//! Tunes for the buzzer, written as RTTTL.
+//!
+//! RTTTL is the Nokia ringtone format, e.g.
+//!
+//! ```text
+//! win:d=8,o=5,b=180:c,e,g,2c6
+//! ```
+//!
+//! a name, the default duration, octave and tempo, and the notes. A note is an
+//! optional duration (1, 2, 4, 8, 16 or 32th of a whole), the pitch `c`..`b` or `p`
+//! for a pause, an optional `#`, an optional dot that makes it half as long again
+//! and an optional octave. The buzzer task plays a tune by changing the PWM `top`
+//! for every note, see `pwm_top`.
+//!
+//! Only `core` is used, the parser builds for the firmware and for the host.
+
+#![no_std]
+
+/// One note, a `frequency` of 0 is a pause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Note {
+    pub frequency: u16,
+    pub duration_ms: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// Not `name:defaults:notes`.
+    Sections,
+    /// A default that isn't `d=`, `o=` or `b=` with a valid number.
+    Default,
+    /// The note at this index can't be read.
+    Note(usize),
+}
+
+const DURATIONS: [u8; 6] = [1, 2, 4, 8, 16, 32];
+const OCTAVES: core::ops::RangeInclusive<u8> = 1..=8;
+
+/// C8 up to B8 in Hz, lower octaves are halved from here.
+const OCTAVE_8: [u16; 12] = [
+    4186, 4435, 4699, 4978, 5274, 5588, 5920, 6272, 6645, 7040, 7459, 7902,
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct Melody<'a> {
+    pub name: &'a str,
+    duration: u8,
+    octave: u8,
+    bpm: u16,
+    notes: &'a str,
+}
+
+impl<'a> Melody<'a> {
+    /// Reads the header and checks every note, so playing the melody can't fail later.
+    pub fn parse(text: &'a str) -> Result<Self, Error> {
+        let mut sections = text.trim().splitn(3, ':');
+        let (Some(name), Some(defaults), Some(notes)) =
+            (sections.next(), sections.next(), sections.next())
+        else {
+            return Err(Error::Sections);
+        };
+
+        let mut melody = Melody {
+            name: name.trim(),
+            duration: 4,
+            octave: 6,
+            bpm: 63,
+            notes,
+        };
+        for default in defaults.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let (key, value) = default.split_once('=').ok_or(Error::Default)?;
+            let value: u16 = value.trim().parse().map_err(|_| Error::Default)?;
+            match key.trim() {
+                "d" if value <= 32 && DURATIONS.contains(&(value as u8)) => {
+                    melody.duration = value as u8
+                }
+                "o" if value <= 8 && OCTAVES.contains(&(value as u8)) => {
+                    melody.octave = value as u8
+                }
+                "b" if value > 0 => melody.bpm = value,
+                _ => return Err(Error::Default),
+            }
+        }
+
+        for (index, note) in melody.tokens().enumerate() {
+            melody.note(note).ok_or(Error::Note(index))?;
+        }
+        Ok(melody)
+    }
+
+    pub fn notes(&self) -> impl Iterator<Item = Note> + use<'a> {
+        let melody = *self;
+        self.tokens().filter_map(move |note| melody.note(note))
+    }
+
+    /// How long playing all the notes takes.
+    pub fn duration_ms(&self) -> u32 {
+        self.notes().map(|note| note.duration_ms).sum()
+    }
+
+    fn tokens(&self) -> impl Iterator<Item = &'a str> + use<'a> {
+        self.notes
+            .split(',')
+            .map(str::trim)
+            .filter(|note| !note.is_empty())
+    }
+
+    fn note(&self, text: &str) -> Option<Note> {
+        let mut rest = text.as_bytes();
+
+        let (duration, digits) = number(rest);
+        rest = &rest[digits..];
+        let duration = match duration {
+            Some(duration) if duration <= 32 && DURATIONS.contains(&(duration as u8)) => {
+                duration as u8
+            }
+            Some(_) => return None,
+            None => self.duration,
+        };
+
+        // `h` is what some phones call `b`
+        let semitone: Option<usize> = match rest.first()?.to_ascii_lowercase() {
+            b'c' => Some(0),
+            b'd' => Some(2),
+            b'e' => Some(4),
+            b'f' => Some(5),
+            b'g' => Some(7),
+            b'a' => Some(9),
+            b'b' | b'h' => Some(11),
+            b'p' => None,
+            _ => return None,
+        };
+        rest = &rest[1..];
+
+        let mut sharp = false;
+        if rest.first() == Some(&b'#') {
+            sharp = true;
+            rest = &rest[1..];
+        }
+        // the dot goes before or after the octave
+        let mut dotted = false;
+        if rest.first() == Some(&b'.') {
+            dotted = true;
+            rest = &rest[1..];
+        }
+        let (octave, digits) = number(rest);
+        rest = &rest[digits..];
+        let octave = match octave {
+            Some(octave) if octave <= 8 && OCTAVES.contains(&(octave as u8)) => octave as u8,
+            Some(_) => return None,
+            None => self.octave,
+        };
+        if rest.first() == Some(&b'.') {
+            dotted = true;
+            rest = &rest[1..];
+        }
+        if !rest.is_empty() {
+            return None;
+        }
+
+        // a whole note is four beats
+        let mut duration_ms = 240_000 / (self.bpm as u32 * duration as u32);
+        if dotted {
+            duration_ms += duration_ms / 2;
+        }
+        let frequency = match semitone {
+            Some(semitone) => {
+                let semitone = semitone + sharp as usize;
+                // b# is the c of the next octave
+                let (semitone, octave) = if semitone == 12 {
+                    (0, octave + 1)
+                } else {
+                    (semitone, octave)
+                };
+                // only b#8 goes above octave 8
+                match octave {
+                    9 => OCTAVE_8[semitone] << 1,
+                    _ => OCTAVE_8[semitone] >> (8 - octave),
+                }
+            }
+            None => 0,
+        };
+        Some(Note {
+            frequency,
+            duration_ms,
+        })
+    }
+}
+
+/// The PWM `top` that makes `frequency` from a counter running at `counter_hz`,
+/// clamped to what the 16 bit counter can do.
+pub fn pwm_top(frequency: u16, counter_hz: u32) -> u16 {
+    if frequency == 0 {
+        return 0;
+    }
+    (counter_hz / frequency as u32)
+        .saturating_sub(1)
+        .clamp(1, u16::MAX as u32) as u16
+}
+
+/// Reads the digits at the start of `text`, returns the number and how many there were.
+fn number(text: &[u8]) -> (Option<u32>, usize) {
+    let digits = text.iter().take_while(|c| c.is_ascii_digit()).count();
+    let value = text[..digits].iter().try_fold(0u32, |n, &c| {
+        n.checked_mul(10)?.checked_add((c - b'0') as u32)
+    });
+    match digits {
+        0 => (None, 0),
+        _ => (value.or(Some(u32::MAX)), digits),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notes(text: &str) -> impl Iterator<Item = Note> + '_ {
+        Melody::parse(text).unwrap().notes()
+    }
+
+    fn note(frequency: u16, duration_ms: u32) -> Note {
+        Note {
+            frequency,
+            duration_ms,
+        }
+    }
+
+    #[test]
+    fn uses_the_defaults_section() {
+        let melody = Melody::parse(" tune : d=8, o=4, b=120 : a, 4a ").unwrap();
+        assert_eq!(melody.name, "tune");
+        assert!(melody.notes().eq([note(440, 250), note(440, 500)]));
+        // without defaults a quarter note at 63 bpm in octave 6
+        assert!(notes("tune::c").eq([note(1046, 952)]));
+    }
+
+    #[test]
+    fn reads_octaves_and_sharps() {
+        assert!(
+            notes("tune:d=4,o=5,b=60:a4,a,a6,c8,c#,h,b#")
+                .map(|note| note.frequency)
+                .eq([440, 880, 1760, 4186, 554, 987, 1046])
+        );
+        // b# is the c of the next octave, also above the top one
+        assert!(
+            notes("tune::b#7,b#8,c8")
+                .map(|note| note.frequency)
+                .eq([4186, 8372, 4186])
+        );
+    }
+
+    #[test]
+    fn a_dot_makes_a_note_half_as_long_again() {
+        assert!(
+            notes("tune:d=4,o=5,b=120:c,c.,8c.,c.6,c6.")
+                .map(|note| note.duration_ms)
+                .eq([500, 750, 375, 750, 750])
+        );
+    }
+
+    #[test]
+    fn pauses_are_silent() {
+        assert!(notes("tune:d=4,o=5,b=120:p,2p,8p.").eq([
+            note(0, 500),
+            note(0, 1000),
+            note(0, 375)
+        ]));
+        assert_eq!(pwm_top(0, 1_000_000), 0);
+    }
+
+    #[test]
+    fn the_tempo_sets_the_note_lengths() {
+        // a whole note is four beats
+        let melody = Melody::parse("tune:d=1,o=5,b=60:c,2c,4c,8c,16c,32c").unwrap();
+        assert!(
+            melody
+                .notes()
+                .map(|note| note.duration_ms)
+                .eq([4000, 2000, 1000, 500, 250, 125])
+        );
+        assert_eq!(melody.duration_ms(), 7875);
+        assert_eq!(Melody::parse("tune:b=200:8c").unwrap().duration_ms(), 150);
+    }
+
+    #[test]
+    fn rejects_malformed_tunes() {
+        assert_eq!(Melody::parse("tune").err(), Some(Error::Sections));
+        assert_eq!(Melody::parse("tune:c,e").err(), Some(Error::Sections));
+        for defaults in ["d=3", "o=9", "b=0", "x=4", "d", "d=four"] {
+            let text = ["tune:", defaults, ":c"].concat();
+            assert_eq!(
+                Melody::parse(&text).err(),
+                Some(Error::Default),
+                "{}",
+                defaults
+            );
+        }
+        assert_eq!(Melody::parse("tune::c,x").err(), Some(Error::Note(1)));
+        assert_eq!(Melody::parse("tune::c,e,3g").err(), Some(Error::Note(2)));
+        assert_eq!(Melody::parse("tune::c9").err(), Some(Error::Note(0)));
+        assert_eq!(Melody::parse("tune::c5x").err(), Some(Error::Note(0)));
+        assert_eq!(
+            Melody::parse("tune::99999999999c").err(),
+            Some(Error::Note(0))
+        );
+    }
+
+    #[test]
+    fn the_shipped_jingles_parse() {
+        for text in [
+            include_str!("../../assets/jingles/spin.rtttl"),
+            include_str!("../../assets/jingles/win.rtttl"),
+            include_str!("../../assets/jingles/jackpot.rtttl"),
+            include_str!("../../assets/jingles/cashout.rtttl"),
+        ] {
+            let melody = Melody::parse(text).unwrap();
+            assert!(melody.duration_ms() > 0, "{}", melody.name);
+        }
+        assert_eq!(
+            Melody::parse(include_str!("../../assets/jingles/spin.rtttl"))
+                .unwrap()
+                .duration_ms(),
+            4 * 37
+        );
+    }
+
+    #[test]
+    fn pwm_top_stays_in_the_counter() {
+        assert_eq!(pwm_top(440, 1_000_000), 2271);
+        assert_eq!(pwm_top(1, 150_000_000), u16::MAX);
+        assert_eq!(pwm_top(u16::MAX, 1_000), 1);
+    }
+}
@@ -0,0 +1,67 @@
+//! Frame animations for the symbols.
+//!
+//! `build.rs` cuts the sprite atlases in `assets/animations` into frames and
+//! attaches the result to the symbol, see `Symbol::animation`.
+
+use embassy_time::{Duration, Instant};
+
+use crate::compressed::CompressedImage;
+use crate::symbols::SYMBOL_SIZE;
+
+pub struct Animation {
+    pub frames: &'static [AnimationFrame],
+}
+
+pub struct AnimationFrame {
+    pub(crate) data: &'static [u8],
+    pub duration_ms: u32,
+}
+
+impl AnimationFrame {
+    pub fn image(&self) -> CompressedImage<'static> {
+        CompressedImage::new(self.data, SYMBOL_SIZE)
+    }
+}
+
+/// Plays an animation in a loop.
+pub struct AnimationPlayer {
+    animation: &'static Animation,
+    frame: usize,
+    next_frame_at: Instant,
+}
+
+impl AnimationPlayer {
+    pub fn new(animation: &'static Animation, now: Instant) -> Self {
+        AnimationPlayer {
+            animation,
+            frame: 0,
+            next_frame_at: now + Self::duration(&animation.frames[0]),
+        }
+    }
+
+    pub fn image(&self) -> CompressedImage<'static> {
+        self.animation.frames[self.frame].image()
+    }
+
+    /// When the frame changes next.
+    pub fn next_frame_at(&self) -> Instant {
+        self.next_frame_at
+    }
+
+    /// Moves to the frame that should be shown at `now`, returns whether it changed.
+    pub fn update(&mut self, now: Instant) -> bool {
+        let mut changed = false;
+        // skip frames when we were late instead of slowing the animation down
+        while now >= self.next_frame_at {
+            self.frame = (self.frame + 1) % self.animation.frames.len();
+            self.next_frame_at += Self::duration(&self.animation.frames[self.frame]);
+            changed = true;
+        }
+        changed
+    }
+
+    fn duration(frame: &AnimationFrame) -> Duration {
+        // a zero duration would never let `update` return
+        Duration::from_millis(frame.duration_ms.max(1) as u64)
+    }
+}
@@ -1,37 +1,91 @@
 #![no_std]
 #![no_main]
 
-use embassy_executor::Spawner;
+use embassy_executor::{InterruptExecutor, Spawner};
 use embassy_rp::{
     gpio::{Input, Level, Output, Pull},
-    peripherals::{SPI0, SPI1},
+    peripherals::{PIO0, SPI0, SPI1, USB},
+    pio::{InterruptHandler as PioInterruptHandler, Pio},
+    pio_programs::ws2812::{PioWs2812, PioWs2812Program},
     pwm::{Config as ConfigPwm, Pwm, SetDutyCycle},
-    spi::{Blocking, Config as ConfigSpi, Spi},
+    spi::{Async, Blocking, Config as ConfigSpi, Spi},
+    usb::{Driver as UsbDriver, InterruptHandler as UsbInterruptHandler},
 };
 use embassy_sync::{blocking_mutex::NoopMutex, channel, pubsub::publisher};
-use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, channel::Channel};
-use embassy_time::{Delay, Duration, Timer};
-use core::cell::RefCell;
+use embassy_sync::{blocking_mutex::raw::{CriticalSectionRawMutex, ThreadModeRawMutex}, channel::Channel};
+use embassy_time::{Delay, Duration, Ticker, Timer};
+use core::cell::{Cell, RefCell};
 use core::fmt::Write; // Import for core formatting
 use defmt::info;
-use display_interface_spi::SPIInterface;
-use embassy_embedded_hal::shared_bus::blocking::spi::SpiDevice;
+use embassy_embedded_hal::shared_bus::asynch::spi::SpiDeviceWithConfig;
+#[cfg(feature = "blocking-spi")]
+use embassy_embedded_hal::adapter::BlockingAsync;
+use embassy_sync::mutex::Mutex;
 use embedded_graphics::{
-    mono_font::{MonoTextStyle, ascii::FONT_10X20},
+    mono_font::ascii::FONT_6X10,
     pixelcolor::Rgb565,
     prelude::*,
     primitives::{Circle, PrimitiveStyle, Rectangle},
-    text::Text,
+    text::{Alignment, Text},
 };
 use fixed::traits::ToFixed;
 use heapless::String; // Import for no_std string handling
-use ili9341::{DisplaySize240x320, DisplaySize320x480, Ili9341, ModeState, Orientation};
-use static_cell::StaticCell;
+use ili9341::{DisplaySize, DisplaySize240x320, DisplaySize320x480, ModeState, Orientation};
+use arcade_game::compressed::CompressedImage;
+use arcade_game::config::{Config, CONFIG_ADDR, CONFIG_SIZE};
+use arcade_game::display::{Controller, Ili9341Async};
+use arcade_game::framebuffer::FrameBuffer;
+use arcade_game::help::HelpPages;
+use arcade_game::melody;
+use arcade_game::paytable;
+use arcade_game::power::{self, Power};
+use arcade_game::pcm;
+use arcade_game::sound::{self, Event, SoundEngine, Voice};
+use arcade_game::st7735::St7735Async;
+use arcade_game::symbols::{SYMBOLS, SYMBOL_COUNT, SYMBOL_HEIGHT};
+use arcade_game::theme::{self, Layout, Theme};
+use arcade_game::timeline::{self, Cue, Timed};
+use arcade_game::ui::{CoinShower, Frame, MessageBanner, NumberField, SlotWindow, Widget};
+use arcade_game::win::{count_up, WinTier};
+use static_cell::{ConstStaticCell, StaticCell};
 use {defmt_rtt as _, panic_probe as _};
 
-static SPI_BUS: StaticCell<NoopMutex<RefCell<Spi<'static, SPI0, Blocking>>>> = StaticCell::new(); // for borrowing to a task
-
-use embassy_futures::select::select;
+static SPI_BUS: StaticCell<Mutex<ThreadModeRawMutex, DisplayBus>> = StaticCell::new(); // for borrowing to a task
+// SPI0 with both panels, the `blocking-spi` feature puts it back on blocking transfers
+// so the input latency they cause can be measured against the DMA ones
+#[cfg(not(feature = "blocking-spi"))]
+type DisplayBus = Spi<'static, SPI0, Async>;
+#[cfg(feature = "blocking-spi")]
+type DisplayBus = BlockingAsync<Spi<'static, SPI0, Blocking>>;
+
+// the panel that is wired up, the HUD lays itself out for its size and orientation
+// (DisplaySize320x480 with Controller::Ili9488 for the 3.5" panel)
+type Panel = DisplaySize240x320;
+const CONTROLLER: Controller = Controller::Ili9341;
+const ORIENTATION: Orientation = Orientation::LandscapeFlipped;
+const THEME: Theme<'static> = theme::CLASSIC;
+
+const FRAME_PIXELS: usize = <Panel as DisplaySize>::WIDTH * <Panel as DisplaySize>::HEIGHT;
+static FRAME: ConstStaticCell<[u16; FRAME_PIXELS]> = ConstStaticCell::new([0; FRAME_PIXELS]); // 150 KB for 320x240, 300 KB for 320x480, lives in .bss
+const MAIN_SPI_HZ: u32 = 32_000_000;
+const BACKLIGHT_TOP: u16 = 7_500; // 20 kHz backlight PWM, too fast to flicker
+const ATTRACT_LED_BRIGHTNESS: u8 = 40; // percent, the strip is dimmed while nobody plays
+const REEL_STOP_FLASH_MS: u64 = 80; // every reel stop flashes the strip white for this long
+
+// the WS2812 strip around the cabinet, its data line on PIN_2 is driven by PIO0
+type CabinetStrip = PioWs2812<'static, PIO0, 0, { arcade_strip::STRIP_LEN }>;
+
+// the 1.44" ST7735 shares SPI0 with the main panel, it can't keep up with the main panel's clock
+const SIDE_SIZE: Size = Size::new(128, 128);
+const SIDE_OFFSET: Point = Point::new(2, 3);
+const SIDE_SPI_HZ: u32 = 15_000_000;
+const SIDE_THEME: Theme<'static> = Theme { font: &FONT_6X10, ..theme::CLASSIC };
+const SIDE_PAGE_MS: u64 = 4000; // the paytable pages on the small display turn this often
+const SIDE_PIXELS: usize = (SIDE_SIZE.width * SIDE_SIZE.height) as usize;
+static SIDE_FRAME: ConstStaticCell<[u16; SIDE_PIXELS]> = ConstStaticCell::new([0; SIDE_PIXELS]);
+
+use embassy_futures::join::join;
+use embassy_futures::select::{select, select3, select4, Either, Either3, Either4};
 use embassy_sync::pubsub::{
     PubSubChannel, Publisher, Subscriber,
     WaitResult::{Lagged, Message as wrm},
@@ -42,210 +96,616 @@ use rand::{Rng, SeedableRng};
 use mfrc522::{Mfrc522};
 use embassy_rp::i2c::{I2c, InterruptHandler as I2CInterruptHandler, Config as I2cConfig, Async as I2cAsync};
 use embedded_hal_async::i2c::{Error, I2c as _};
-use embassy_rp::peripherals::I2C1;
+use embassy_rp::peripherals::{I2C1, UART0};
+use embassy_rp::uart::{BufferedInterruptHandler, BufferedUart, Config as UartConfig};
+use embedded_io_async::{Read, Write as _};
+use arcade_strip::{Effect, Rgb, Show};
+use arcade_console::{audit::Audit, parse, Auth, AuthError, Command, Hex, LineBuffer, UidHex, EEPROM_READ_MAX, EEPROM_WRITE_MAX, MAX_LINE};
+use arcade_eeprom::{card_addr, header_bytes, legacy_cards, pin_bytes, read_header, read_pin, within_page, Card, RecordError, CARDS_ADDR, CARD_SIZE, EEPROM_SIZE, HEADER_SIZE, LEGACY_SIZE, MAX_CARDS, PAGE_SIZE, PIN_ADDR, PIN_SIZE};
+use embassy_sync::blocking_mutex::Mutex as BlockingMutex;
+use embassy_usb::class::cdc_acm::{CdcAcmClass, State as CdcState};
+use embassy_usb::UsbDevice;
+use smart_leds::RGB8;
+use arcade_link::{Message, Receiver, Sender, HEARTBEAT_MS, LINK_TIMEOUT_MS, MAX_FRAME};
 use embassy_rp::bind_interrupts;
+use embassy_rp::interrupt;
+use embassy_rp::interrupt::{InterruptExt, Priority};
 use embassy_sync::signal::Signal;
-use embedded_graphics::image::{Image, ImageRawLE};
 
 bind_interrupts!(struct Irqs {
     I2C1_IRQ => I2CInterruptHandler<I2C1>;
+    UART0_IRQ => BufferedInterruptHandler<UART0>;
+    PIO0_IRQ_0 => PioInterruptHandler<PIO0>;
+    USBCTRL_IRQ => UsbInterruptHandler<USB>;
 });
 
+static LINK_TX_BUFFER: ConstStaticCell<[u8; 64]> = ConstStaticCell::new([0; 64]);
+static LINK_RX_BUFFER: ConstStaticCell<[u8; 64]> = ConstStaticCell::new([0; 64]);
+
 static CHANNEL: PubSubChannel<ThreadModeRawMutex, State, 1000, 5, 5> = PubSubChannel::new();
 
 #[derive(Clone, Copy, PartialEq, defmt::Format)]
 enum State {
-    SPIN,
-    WIN,
+    CUE(Timed), // a step of the spin sequence, the lights and the buzzer follow these
     BET,
     ADDBALANCE,
     CASHOUT,
+    ATTRACT,
+    ATTRACTEND, // somebody pressed a button or showed a card
 }
 
-use core::sync::atomic::{AtomicI32, Ordering};
+#[derive(Clone, Copy, PartialEq, defmt::Format)]
+enum Button {
+    SPIN,
+    INCREASEBET,
+    MAXBET,
+    CASHOUT,
+    HELP, // both bet buttons together
+}
 
-static BALANCE: AtomicI32 = AtomicI32::new(0);
+// presses are timestamped by the input task so the display task can report the latency
+static BUTTONS: Channel<ThreadModeRawMutex, (Button, Instant), 8> = Channel::new();
+// the card reader saw a card during attract mode
+static CARD_SHOWN: Signal<ThreadModeRawMutex, ()> = Signal::new();
+// attract mode should end, it stops between two frames so no flush is cut off on the shared bus
+static ATTRACT_EXIT: Signal<ThreadModeRawMutex, ()> = Signal::new();
+// game events for the secondary display Pico, the link task frames and sends them
+static LINK: Channel<ThreadModeRawMutex, Message, 8> = Channel::new();
+
+// the meters and the last games, for the console
+static AUDIT: BlockingMutex<ThreadModeRawMutex, RefCell<Audit>> = BlockingMutex::new(RefCell::new(Audit::new()));
+// what the game's rng was seeded with, for the console
+static RNG_SEED: BlockingMutex<ThreadModeRawMutex, Cell<u64>> = BlockingMutex::new(Cell::new(0));
+// console jobs for the rfid task, which owns the EEPROM and the card reader
+static SERVICE: Channel<ThreadModeRawMutex, Service, 1> = Channel::new();
+static SELF_TEST: Signal<ThreadModeRawMutex, SelfTest> = Signal::new();
+// what an EEPROM job read, nothing for a save, None if the I2C transfer failed
+static EEPROM_IO: Signal<ThreadModeRawMutex, Option<heapless::Vec<u8, EEPROM_READ_MAX>>> = Signal::new();
 
-static BALANCE1: AtomicI32 = AtomicI32::new(80000);
-static BALANCE2: AtomicI32 = AtomicI32::new(100000);
+#[derive(Clone, Copy, PartialEq, defmt::Format)]
+enum Service {
+    SAVECARD(usize), // the console changed this card's balance, store it
+    SAVEPAYTABLE, // the console changed a payout
+    SELFTEST,
+    EEPROMREAD { addr: u16, len: u8 },
+    EEPROMWRITE { addr: u16, len: u8, data: [u8; EEPROM_WRITE_MAX] },
+}
 
-const UID1: [u8; 4] = [80, 243, 109, 20];
-const UID2: [u8; 4] = [10, 85, 52, 0];
+#[derive(Clone, Copy, PartialEq, defmt::Format)]
+struct SelfTest {
+    eeprom: bool,
+    // the card records read back the same as the balances in RAM
+    cards: bool,
+    // the MFRC522 version register, 0x91 or 0x92 for a genuine chip
+    reader: Option<u8>,
+}
 
-const EEPROM_ADDR: u8 = 0x50;
-const CARD_SIZE: usize = 8; // 4 bytes UID + 4 bytes counter
-const EEPROM_START_ADDR: u16 = 0x0000; // start of EEPROM
-const NUM_CARDS: usize = 2;
-type CardEntry = ([u8; 4], u32);
+const DEBOUNCE_MS: u64 = 150;
+const COMBO_MS: u64 = 100; // how long a bet button waits for the other one to open the help pages
+const HELP_TIMEOUT: Duration = Duration::from_secs(30); // the help pages go back to the game after this
 
-async fn write_card_data(i2c: &mut I2c<'_, I2C1, I2cAsync>) -> Result<(), embedded_hal_async::i2c::ErrorKind> {
-    let cards = [
-        (UID1, BALANCE1.load(Ordering::SeqCst) as u32),
-        (UID2, BALANCE2.load(Ordering::SeqCst) as u32),
-    ];
+use core::sync::atomic::{AtomicBool, AtomicI32, Ordering};
 
-    for (index, (uid, balance)) in cards.iter().enumerate() {
-        let mem_addr = EEPROM_START_ADDR + (index as u16) * (CARD_SIZE as u16);
-        let mem_addr_bytes = mem_addr.to_be_bytes();
-        let mut buffer = [0u8; 2 + CARD_SIZE];
+static BALANCE: AtomicI32 = AtomicI32::new(0);
+// for the small display, the main display owns the bet
+static BET: AtomicI32 = AtomicI32::new(500);
+static LAST_JACKPOT: AtomicI32 = AtomicI32::new(0);
+// a known card was read in and not cashed out yet
+static CARD_IN: AtomicBool = AtomicBool::new(false);
+// a cashed out balance is only in RAM because the EEPROM write failed, the display warns until it is stored
+static CARD_UNSAVED: AtomicBool = AtomicBool::new(false);
+// the reels spin, the latency task reports the worst stall of every spin on its own
+static SPINNING: AtomicBool = AtomicBool::new(false);
+
+// the card table from the EEPROM, by slot. None is a slot that didn't read back, it is
+// left alone until a new image is written
+static CARDS: BlockingMutex<ThreadModeRawMutex, RefCell<heapless::Vec<Option<Card>, MAX_CARDS>>> =
+    BlockingMutex::new(RefCell::new(heapless::Vec::new()));
+
+const EEPROM_ADDR: u8 = 0x50;
 
-        buffer[0..2].copy_from_slice(&mem_addr_bytes);
-        buffer[2..6].copy_from_slice(uid);
-        buffer[6..10].copy_from_slice(&balance.to_be_bytes());
+/// Reads the settings, or stores and returns the defaults when there are none yet.
+async fn load_config(i2c: &mut I2c<'_, I2C1, I2cAsync>) -> Config {
+    let mut bytes = [0u8; CONFIG_SIZE];
+    let read = i2c.write_read(EEPROM_ADDR, &CONFIG_ADDR.to_be_bytes(), &mut bytes).await;
 
-        i2c.write(EEPROM_ADDR, &buffer).await.map_err(|_| embedded_hal_async::i2c::ErrorKind::Other)?;
-        Timer::after_millis(10).await; // EEPROM write delay
+    match read.ok().and_then(|_| Config::from_bytes(&bytes)) {
+        Some(config) => {
+            info!("Settings loaded: {:?}", config);
+            config
+        }
+        None => {
+            let config = Config::default();
+            info!("No settings in the EEPROM, storing the defaults");
+            if save_config(i2c, &config).await.is_err() {
+                info!("Could not store the settings");
+            }
+            config
+        }
     }
+}
 
-    info!("EEPROM initialized with known cards{},{},{},{}.", UID1, BALANCE1.load(Ordering::SeqCst), UID2, BALANCE2.load(Ordering::SeqCst));
+async fn save_config(i2c: &mut I2c<'_, I2C1, I2cAsync>, config: &Config) -> Result<(), embedded_hal_async::i2c::ErrorKind> {
+    let mut buffer = [0u8; 2 + CONFIG_SIZE];
+    buffer[0..2].copy_from_slice(&CONFIG_ADDR.to_be_bytes());
+    buffer[2..].copy_from_slice(&config.to_bytes());
 
+    i2c.write(EEPROM_ADDR, &buffer).await.map_err(|_| embedded_hal_async::i2c::ErrorKind::Other)?;
+    Timer::after_millis(10).await; // EEPROM write delay
     Ok(())
 }
 
+/// The console PIN, `None` until one is set with `pin set` or in an EEPROM image.
+async fn load_pin(i2c: &mut I2c<'_, I2C1, I2cAsync>) -> Option<u32> {
+    let mut bytes = [0u8; PIN_SIZE];
+    let read = i2c.write_read(EEPROM_ADDR, &PIN_ADDR.to_be_bytes(), &mut bytes).await;
+    match read.map_err(|_| RecordError::Blank).and_then(|_| read_pin(&bytes)) {
+        Ok(pin) => Some(pin),
+        Err(e) => {
+            info!("No console PIN ({}), the console takes no login until one is set", e);
+            None
+        }
+    }
+}
 
-async fn load_card_data(i2c: &mut I2c<'_, I2C1, I2cAsync>) -> Result<(), embedded_hal_async::i2c::ErrorKind> {
-    let mut read_buffer = [0u8; CARD_SIZE];
+/// Takes the payouts stored from the console, the built in ones stay when there are none.
+async fn load_paytable(i2c: &mut I2c<'_, I2C1, I2cAsync>) {
+    let mut bytes = [0u8; paytable::PAYTABLE_SIZE];
+    let read = i2c.write_read(EEPROM_ADDR, &paytable::PAYTABLE_ADDR.to_be_bytes(), &mut bytes).await;
+    if read.is_ok() && paytable::load(&bytes) {
+        info!("Paytable loaded from the EEPROM");
+    }
+}
 
-    for index in 0..NUM_CARDS {
-        let mem_addr = EEPROM_START_ADDR + (index as u16) * (CARD_SIZE as u16);
-        let mem_addr_bytes = mem_addr.to_be_bytes();
+async fn save_paytable(i2c: &mut I2c<'_, I2C1, I2cAsync>) -> Result<(), embedded_hal_async::i2c::ErrorKind> {
+    let mut buffer = [0u8; 2 + paytable::PAYTABLE_SIZE];
+    buffer[0..2].copy_from_slice(&paytable::PAYTABLE_ADDR.to_be_bytes());
+    buffer[2..].copy_from_slice(&paytable::to_bytes());
 
-        i2c.write_read(EEPROM_ADDR, &mem_addr_bytes, &mut read_buffer)
-            .await
-            .map_err(|_| embedded_hal_async::i2c::ErrorKind::Other)?;
+    i2c.write(EEPROM_ADDR, &buffer).await.map_err(|_| embedded_hal_async::i2c::ErrorKind::Other)?;
+    Timer::after_millis(10).await; // EEPROM write delay
+    Ok(())
+}
 
-        let uid: [u8; 4] = read_buffer[0..4].try_into().unwrap();
-        let balance = u32::from_be_bytes(read_buffer[4..8].try_into().unwrap());
+/// Reads the card table. Cards in the layout from before the table, two records at the
+/// start without a CRC, are moved into it.
+async fn load_cards(i2c: &mut I2c<'_, I2C1, I2cAsync>) -> Result<(), embedded_hal_async::i2c::ErrorKind> {
+    let mut header = [0u8; HEADER_SIZE];
+    i2c.write_read(EEPROM_ADDR, &CARDS_ADDR.to_be_bytes(), &mut header)
+        .await
+        .map_err(|_| embedded_hal_async::i2c::ErrorKind::Other)?;
+
+    match read_header(&header) {
+        Ok(count) => {
+            let mut record = [0u8; CARD_SIZE];
+            for index in 0..count {
+                i2c.write_read(EEPROM_ADDR, &card_addr(index).to_be_bytes(), &mut record)
+                    .await
+                    .map_err(|_| embedded_hal_async::i2c::ErrorKind::Other)?;
+                let card = match Card::from_bytes(&record) {
+                    Ok(card) => Some(card),
+                    Err(e) => {
+                        info!("Card {} in the EEPROM: {}", index, e);
+                        None
+                    }
+                };
+                CARDS.lock(|cards| {
+                    let _ = cards.borrow_mut().push(card);
+                });
+            }
+            info!("{} cards loaded", count);
+        }
+        Err(RecordError::Blank | RecordError::BadMagic) => {
+            let mut legacy = [0u8; LEGACY_SIZE];
+            i2c.write_read(EEPROM_ADDR, &CARDS_ADDR.to_be_bytes(), &mut legacy)
+                .await
+                .map_err(|_| embedded_hal_async::i2c::ErrorKind::Other)?;
+            let count = CARDS.lock(|cards| {
+                let mut cards = cards.borrow_mut();
+                for card in legacy_cards(&legacy) {
+                    let _ = cards.push(Some(card));
+                }
+                cards.len()
+            });
+            if count == 0 {
+                info!("No cards in the EEPROM, write an image made with tools/eeprom_image");
+            } else {
+                info!("Moving {} cards into the card table", count);
+                save_cards(i2c).await?;
+            }
+        }
+        // the table is there but spoilt, keep it for tools/eeprom_image to look at
+        Err(e) => info!("Card table in the EEPROM: {}", e),
+    }
+    Ok(())
+}
 
-        if index == 0 {
-            UID1.copy_from_slice(&uid);
-            BALANCE1.store(balance as i32, Ordering::SeqCst);
-        } else if index == 1 {
-            UID2.copy_from_slice(&uid);
-            BALANCE2.store(balance as i32, Ordering::SeqCst);
+/// Writes every card and then the header, so a table cut short keeps the old count.
+async fn save_cards(i2c: &mut I2c<'_, I2C1, I2cAsync>) -> Result<(), embedded_hal_async::i2c::ErrorKind> {
+    let cards = CARDS.lock(|cards| cards.borrow().clone());
+    for (index, card) in cards.iter().enumerate() {
+        if let Some(card) = card {
+            save_card(i2c, index, card).await?;
         }
     }
+    write_eeprom(i2c, CARDS_ADDR, &header_bytes(cards.len())).await
+}
 
-    info!("EEPROM loaded into globals{},{},{},{}.", UID1, BALANCE1.load(Ordering::SeqCst), UID2, BALANCE2.load(Ordering::SeqCst));
+async fn save_card(i2c: &mut I2c<'_, I2C1, I2cAsync>, index: usize, card: &Card) -> Result<(), embedded_hal_async::i2c::ErrorKind> {
+    write_eeprom(i2c, card_addr(index), &card.to_bytes()).await
+}
 
+/// Writes `bytes` at `addr`, they have to be in one page.
+async fn write_eeprom(i2c: &mut I2c<'_, I2C1, I2cAsync>, addr: u16, bytes: &[u8]) -> Result<(), embedded_hal_async::i2c::ErrorKind> {
+    let mut buffer = [0u8; 2 + PAGE_SIZE];
+    buffer[0..2].copy_from_slice(&addr.to_be_bytes());
+    buffer[2..2 + bytes.len()].copy_from_slice(bytes);
+
+    i2c.write(EEPROM_ADDR, &buffer[..2 + bytes.len()]).await.map_err(|_| embedded_hal_async::i2c::ErrorKind::Other)?;
+    Timer::after_millis(10).await; // EEPROM write delay
     Ok(())
 }
 
 
 
 
-#[embassy_executor::task]
-async fn display_task(
-    spi_bus: &'static NoopMutex<RefCell<Spi<'static, SPI0, Blocking>>>,
-    mut cs: Output<'static>,
-    mut dc: Output<'static>,
-    mut reset: Output<'static>,
-    mut increase_bet: Input<'static>,
-    mut max_bet: Input<'static>,
-    mut spin_button: Input<'static>,
-    mut cashout_button: Input<'static>,
-) {
-    let spi_dev = SpiDevice::new(&spi_bus, cs);
-    let iface = SPIInterface::new(spi_dev, dc);
+const REEL_SYMBOLS: usize = SYMBOL_COUNT;
+const SLOT_SIZE: u32 = SYMBOL_HEIGHT;
+const STRIP_LEN: u32 = SLOT_SIZE * REEL_SYMBOLS as u32;
+const REEL_FRAME_MS: u64 = 25; // 40 fps, every reel redraw is a single 80x80 blit
+const REEL_MAX_SPEED: u32 = 20; // px per frame at full speed
+const REEL_MIN_SPEED: u32 = 2; // px per frame right before the reel locks
+const REEL_BRAKE_SYMBOLS: u32 = 2; // a braking reel travels at least this many symbols
+const REEL_BRAKE_MS: [u64; 3] = [1200, 1600, 2000]; // when each reel starts braking, counted from the spin
+const ANTICIPATION_MS: u64 = 1500;
+const WIN_FLASH_MS: u64 = 150; // winning reel frames blink at this rate
+const ATTRACT_SCREEN_MS: u64 = 2500; // every paytable line stays up this long in attract mode
+const DEMO_SPINS: usize = 3;
+
+struct Reel {
+    position: u32, // px along the strip, a multiple of SLOT_SIZE means a symbol sits in the window
+    target: usize,
+    brake_at: Instant,
+    brake_left: u32,
+    braking: bool,
+    stopped: bool,
+}
 
-    let mut delay = Delay;
+impl Reel {
+    fn new(position: u32, target: usize, brake_at: Instant) -> Self {
+        Reel {
+            position,
+            target,
+            brake_at,
+            brake_left: 0,
+            braking: false,
+            stopped: false,
+        }
+    }
 
-    let mut display = Ili9341::new(
-        iface,
-        reset,
-        &mut delay,
-        Orientation::LandscapeFlipped,
-        DisplaySize240x320,
-    )
-    .unwrap();
+    fn symbol(&self) -> usize {
+        (self.position / SLOT_SIZE) as usize % REEL_SYMBOLS
+    }
 
-    display.idle_mode(ModeState::Off).unwrap();
-    display.invert_mode(ModeState::On).unwrap();
-    let _ = display.normal_mode_frame_rate(
-        ili9341::FrameRateClockDivision::Fosc,
-        ili9341::FrameRate::FrameRate100,
-    );
-    display.clear(Rgb565::BLACK).unwrap();
+    fn offset(&self) -> i32 {
+        (self.position % SLOT_SIZE) as i32
+    }
 
-    let rect_style = PrimitiveStyle::with_fill(Rgb565::RED); //linie sus
-    Rectangle::new(Point::new(0, 0), Size::new(320, 5))
-        .into_styled(rect_style)
-        .draw(&mut display)
-        .unwrap();
+    /// Scrolls the reel by one frame. Returns true when the reel just stopped.
+    fn step(&mut self, now: Instant, can_brake: bool) -> bool {
+        if !self.braking && can_brake && now >= self.brake_at {
+            // travel a couple of symbols more and land exactly on the target
+            let target = self.target as u32 * SLOT_SIZE;
+            let min_stop = (self.position + REEL_BRAKE_SYMBOLS * SLOT_SIZE) % STRIP_LEN;
+            self.brake_left = REEL_BRAKE_SYMBOLS * SLOT_SIZE + (target + STRIP_LEN - min_stop) % STRIP_LEN;
+            self.braking = true;
+        }
 
-    let rect_style = PrimitiveStyle::with_fill(Rgb565::RED); //linie jos
-    Rectangle::new(Point::new(0, 235), Size::new(320, 5))
-        .into_styled(rect_style)
-        .draw(&mut display)
-        .unwrap();
+        let speed = if self.braking {
+            (self.brake_left / 6).clamp(REEL_MIN_SPEED, REEL_MAX_SPEED).min(self.brake_left)
+        } else {
+            REEL_MAX_SPEED
+        };
+        self.position = (self.position + speed) % STRIP_LEN;
+
+        if self.braking {
+            self.brake_left -= speed;
+            if self.brake_left == 0 {
+                self.stopped = true;
+                return true;
+            }
+        }
+        false
+    }
 
-    let rect_style = PrimitiveStyle::with_fill(Rgb565::RED); // linie stanga
-    Rectangle::new(Point::new(0, 0), Size::new(5, 240))
-        .into_styled(rect_style)
-        .draw(&mut display)
-        .unwrap();
+    fn anticipate(&mut self, now: Instant) {
+        if !self.braking {
+            self.brake_at = self.brake_at.max(now + Duration::from_millis(ANTICIPATION_MS));
+        }
+    }
+}
 
-    let rect_style = PrimitiveStyle::with_fill(Rgb565::RED); //linie dreapta
-    Rectangle::new(Point::new(315, 0), Size::new(5, 240))
-        .into_styled(rect_style)
-        .draw(&mut display)
-        .unwrap();
+/// Everything on the main screen, laid out in one place.
+struct Hud<'a> {
+    frames: [Frame; 6],
+    slots: [SlotWindow<'a, CompressedImage<'a>>; 3],
+    balance: NumberField<'a>,
+    bet: NumberField<'a>,
+    last_win: NumberField<'a>,
+    banner: MessageBanner<'a>,
+    coins: CoinShower,
+    background: Rgb565,
+    frame_color: Rgb565,
+    highlight: Rgb565,
+}
 
-    let rect_style = PrimitiveStyle::with_fill(Rgb565::RED); //linie bet sus
-    Rectangle::new(Point::new(0, 200), Size::new(320, 5))
-        .into_styled(rect_style)
-        .draw(&mut display)
-        .unwrap();
+impl<'a> Hud<'a> {
+    fn new(symbols: &'a [CompressedImage<'a>], layout: &Layout, theme: &Theme<'a>) -> Self {
+        let text_style = theme.text_style();
+        // a zero sized frame draws nothing
+        let divider = layout.status_divider.unwrap_or(Rectangle::zero());
+
+        Hud {
+            frames: [
+                Frame::outline(layout.screen, layout.border, theme.frame), // chenar
+                Frame::filled(layout.status_line, theme.frame), // linie bet sus
+                Frame::filled(divider, theme.frame), // mijloc bet
+                // slot frames, the windows cover the inside
+                Frame::filled(layout.slot_frames[0], theme.frame),
+                Frame::filled(layout.slot_frames[1], theme.frame),
+                Frame::filled(layout.slot_frames[2], theme.frame),
+            ],
+            slots: layout.slots.map(|position| SlotWindow::new(position, symbols)),
+            balance: NumberField::new("BALANCE: ", layout.balance, text_style),
+            bet: NumberField::new("BET: ", layout.bet, text_style),
+            last_win: NumberField::new("LAST WIN: ", layout.last_win, text_style)
+                .with_alignment(Alignment::Center),
+            banner: MessageBanner::new(layout.banner, text_style),
+            coins: CoinShower::new(layout.play_area, theme.highlight),
+            background: theme.background,
+            frame_color: theme.frame,
+            highlight: theme.highlight,
+        }
+    }
 
-    let rect_style = PrimitiveStyle::with_fill(Rgb565::RED); //mijloc bet
-    Rectangle::new(Point::new(210, 200), Size::new(5, 40))
-        .into_styled(rect_style)
-        .draw(&mut display)
-        .unwrap();
+    /// Flashes the frames of the winning reels, `on` alternates every call.
+    fn flash_reels(&mut self, winning: [bool; 3], on: bool) {
+        for i in 0..3 {
+            let color = if winning[i] && on { self.highlight } else { self.frame_color };
+            self.frames[3 + i].set_color(color);
+        }
+    }
 
-    let text_style = MonoTextStyle::new(&FONT_10X20, Rgb565::GREEN);
+    /// Redraws everything on the next `draw`, after something covered the whole screen.
+    fn invalidate(&mut self) {
+        for frame in self.frames.iter_mut() {
+            frame.invalidate();
+        }
+        for slot in self.slots.iter_mut() {
+            slot.invalidate();
+        }
+        self.balance.invalidate();
+        self.bet.invalidate();
+        self.last_win.invalidate();
+        self.banner.invalidate();
+    }
 
-    // Slot number 1
-    let rect_style = PrimitiveStyle::with_fill(Rgb565::RED);
-    Rectangle::new(Point::new(28, 58), Size::new(84, 84))
-        .into_styled(rect_style)
-        .draw(&mut display)
-        .unwrap();
+    fn draw(&mut self, fb: &mut FrameBuffer) {
+        // the coins moved, wipe them and redraw whatever they covered
+        for area in self.coins.take_stale() {
+            fb.fill_solid(&area, self.background).unwrap();
 
-    let rect_style = PrimitiveStyle::with_fill(Rgb565::BLACK);
-    Rectangle::new(Point::new(30, 60), Size::new(80, 80))
-        .into_styled(rect_style)
-        .draw(&mut display)
-        .unwrap();
+            // the outer border is outside the coin area, everything else may be under a coin
+            let under = |bounds: Rectangle| !bounds.intersection(&area).is_zero_sized();
+            for frame in self.frames[1..].iter_mut().filter(|frame| under(frame.bounds())) {
+                frame.invalidate();
+            }
+            for slot in self.slots.iter_mut().filter(|slot| under(slot.bounds())) {
+                slot.invalidate();
+            }
+            if under(self.last_win.bounds()) {
+                self.last_win.invalidate();
+            }
+            if under(self.banner.bounds()) {
+                self.banner.invalidate();
+            }
+        }
 
-    // Slot number 2
-    let rect_style = PrimitiveStyle::with_fill(Rgb565::RED);
-    Rectangle::new(Point::new(118, 58), Size::new(84, 84))
-        .into_styled(rect_style)
-        .draw(&mut display)
-        .unwrap();
+        for frame in self.frames.iter_mut() {
+            frame.draw(fb).unwrap();
+        }
+        for slot in self.slots.iter_mut() {
+            slot.draw(fb).unwrap();
+        }
+        self.balance.draw(fb).unwrap();
+        self.bet.draw(fb).unwrap();
+        self.last_win.draw(fb).unwrap();
+        self.banner.draw(fb).unwrap();
+        self.coins.draw(fb).unwrap();
+    }
+}
 
-    let rect_style = PrimitiveStyle::with_fill(Rgb565::BLACK);
-    Rectangle::new(Point::new(120, 60), Size::new(80, 80))
-        .into_styled(rect_style)
-        .draw(&mut display)
-        .unwrap();
+type Display = Ili9341Async<SpiDeviceWithConfig<'static, ThreadModeRawMutex, DisplayBus, Output<'static>>, Output<'static>>;
+type StatePublisher = Publisher<'static, ThreadModeRawMutex, State, 1000, 5, 5>;
+
+/// Spins the reels from `positions` until they land on `targets` and returns the symbols
+/// they show. Reel stop cues are only published with a publisher, demo spins stay quiet
+/// and give up between two frames once `ATTRACT_EXIT` is signaled.
+async fn spin_reels(
+    hud: &mut Hud<'_>,
+    fb: &mut FrameBuffer<'_>,
+    display: &mut Display,
+    positions: &mut [u32; 3],
+    targets: [usize; 3],
+    mut publ: Option<&mut StatePublisher>,
+) -> [usize; 3] {
+    let spin_start = Instant::now();
+    let mut reels: [Reel; 3] = core::array::from_fn(|i| {
+        Reel::new(
+            positions[i],
+            targets[i],
+            spin_start + Duration::from_millis(REEL_BRAKE_MS[i]),
+        )
+    });
+
+    let mut ticker = Ticker::every(Duration::from_millis(REEL_FRAME_MS));
+    let mut frames: u64 = 0;
 
-    // Slot number 3
-    let rect_style = PrimitiveStyle::with_fill(Rgb565::RED);
-    Rectangle::new(Point::new(208, 58), Size::new(84, 84))
-        .into_styled(rect_style)
-        .draw(&mut display)
-        .unwrap();
+    loop {
+        let now = Instant::now();
 
-    let rect_style = PrimitiveStyle::with_fill(Rgb565::BLACK);
-    Rectangle::new(Point::new(210, 60), Size::new(80, 80))
-        .into_styled(rect_style)
-        .draw(&mut display)
-        .unwrap();
+        for i in 0..3 {
+            if reels[i].stopped {
+                continue;
+            }
+            // reels stop strictly left to right
+            let can_brake = i == 0 || reels[i - 1].stopped;
+
+            let stopped = reels[i].step(now, can_brake);
+            hud.slots[i].set_position(reels[i].position);
+
+            if stopped {
+                hud.draw(fb);
+                fb.flush(display).await.unwrap();
+                if let Some(publ) = publ.as_mut() {
+                    publ.publish(State::CUE(Cue::ReelStopped(i as u8).at(now.as_millis()))).await;
+                }
+                info!("Reel {} stopped on {}", i, reels[i].symbol());
+
+                // two matching symbols on the first reels, make the player wait for the last one
+                if i == 1 && reels[0].symbol() == reels[1].symbol() {
+                    reels[2].anticipate(now);
+                    info!("Anticipation on the third reel");
+                }
+            }
+        }
+        hud.draw(fb);
+        fb.flush(display).await.unwrap();
+        frames += 1;
+
+        if reels.iter().all(|reel| reel.stopped) || (publ.is_none() && ATTRACT_EXIT.signaled()) {
+            break;
+        }
+
+        ticker.next().await;
+    }
+
+    let elapsed = (Instant::now() - spin_start).as_millis().max(1);
+    info!("Reel animation: {} frames in {} ms ({} fps)", frames, elapsed, frames * 1000 / elapsed);
+
+    for i in 0..3 {
+        positions[i] = reels[i].position;
+    }
+    reels.map(|reel| reel.symbol())
+}
+
+/// Runs until `ATTRACT_EXIT` is signaled: shows the paytable one line at a time at the
+/// current bet, does a few demo spins and asks for a card. It only returns between two
+/// frames, dropping it in the middle of a flush would cut off the DMA on the SPI bus.
+/// Nothing in here touches the balance.
+async fn attract(
+    hud: &mut Hud<'_>,
+    fb: &mut FrameBuffer<'_>,
+    display: &mut Display,
+    positions: &mut [u32; 3],
+    rng: &mut SmallRng,
+    bet: i32,
+) {
+    loop {
+        for pay in paytable::PAYTABLE.iter() {
+            let example = pay.combo.example();
+            for i in 0..3 {
+                positions[i] = example[i] as u32 * SLOT_SIZE;
+                hud.slots[i].set_position(positions[i]);
+            }
+            let mut text: String<32> = String::new();
+            let _ = write!(text, "{} {}: {}", pay.combo.count(), SYMBOLS[pay.combo.symbol()].name, pay.amount(bet));
+            hud.banner.show(&text, THEME.text_style());
+            hud.draw(fb);
+            fb.flush(display).await.unwrap();
+            if !attract_pause(ATTRACT_SCREEN_MS).await {
+                return;
+            }
+        }
+
+        hud.banner.show("DEMO", THEME.text_style());
+        for _ in 0..DEMO_SPINS {
+            let targets = core::array::from_fn(|_| rng.gen_range(0..REEL_SYMBOLS));
+            spin_reels(hud, fb, display, positions, targets, None).await;
+            if !attract_pause(1000).await {
+                return;
+            }
+        }
+
+        for blink in 0..8 {
+            if blink % 2 == 0 {
+                hud.banner.show("INSERT CARD", THEME.warning_style());
+            } else {
+                hud.banner.clear();
+            }
+            hud.draw(fb);
+            fb.flush(display).await.unwrap();
+            if !attract_pause(500).await {
+                return;
+            }
+        }
+    }
+}
+
+/// Waits `ms` in attract mode, false when it ended meanwhile.
+async fn attract_pause(ms: u64) -> bool {
+    if ATTRACT_EXIT.signaled() {
+        return false;
+    }
+    matches!(select(Timer::after_millis(ms), ATTRACT_EXIT.wait()).await, Either::First(_))
+}
+
+#[embassy_executor::task]
+async fn display_task(
+    spi_bus: &'static Mutex<ThreadModeRawMutex, DisplayBus>,
+    mut cs: Output<'static>,
+    mut dc: Output<'static>,
+    mut reset: Output<'static>,
+    mut backlight: Pwm<'static>,
+    settings: Config,
+) {
+    // the backlight comes on with the panel and follows how long nobody played, see `Power`
+    let mut backlight_config: ConfigPwm = Default::default();
+    backlight_config.top = BACKLIGHT_TOP;
+    backlight_config.compare_b = power::duty(settings.brightness, BACKLIGHT_TOP);
+    let mut power = Power::On;
+
+    // every device brings its own clock, the bus is shared with the small display
+    let mut config = ConfigSpi::default();
+    config.frequency = MAIN_SPI_HZ;
+    let spi_dev = SpiDeviceWithConfig::new(spi_bus, cs, config);
+
+    let mut display = Ili9341Async::new(
+        spi_dev,
+        dc,
+        &mut reset,
+        CONTROLLER,
+        ORIENTATION,
+        Panel {},
+    )
+    .await
+    .unwrap();
+    backlight.set_config(&backlight_config);
+
+    display.idle_mode(ModeState::Off).await.unwrap();
+    display.invert_mode(ModeState::On).await.unwrap();
+    let _ = display
+        .normal_mode_frame_rate(
+            ili9341::FrameRateClockDivision::Fosc,
+            ili9341::FrameRate::FrameRate100,
+        )
+        .await;
+    let mut fb = FrameBuffer::new(FRAME.take(), display.size());
+    fb.clear(THEME.background).unwrap();
 
     let seed = Instant::now().as_ticks() as u64;
     let mut rng = SmallRng::seed_from_u64(seed);
+    RNG_SEED.lock(|cell| cell.set(seed));
 
     // let slot_positions = [Point::new(30, 60), Point::new(120, 60), Point::new(210, 60)];
     // let slot_size = Size::new(80, 80);
@@ -256,81 +716,145 @@ async fn display_task(
     // let mut balance = 10000;
     let mut cashout=1;
     let mut publ = CHANNEL.publisher().unwrap();
+    // a press that ended the win presentation early, handled on the next iteration
+    let mut pending: Option<Button> = None;
+    // the last button press or balance change, attract mode starts after `attract_after_s`
+    // of the settings without one
+    let mut last_activity = Instant::now();
+    let attract_after = Duration::from_secs(settings.attract_after_s as u64);
+    // the "card not saved" warning is up
+    let mut save_warning = false;
 
 
 
-    let raw_rusty_crab = ImageRawLE::new(include_bytes!("../../assets/rusty_crab1.raw"), 80);
-    let mut rusty_crab1 = Image::new(&raw_rusty_crab, Point::new(30, 60));
-    let mut rusty_crab2 = Image::new(&raw_rusty_crab, Point::new(120, 60));
-    let mut rusty_crab3 = Image::new(&raw_rusty_crab, Point::new(210, 60));
-    // rusty_crab1.draw(&mut display).unwrap();
-    // rusty_crab2.draw(&mut display).unwrap();
-    // rusty_crab3.draw(&mut display).unwrap();
-    
-
-    let raspberry = ImageRawLE::new(include_bytes!("../../assets/raspberry1.raw"), 80);
-    let mut raspberry1 = Image::new(&raspberry, Point::new(30, 60));
-    let mut raspberry2 = Image::new(&raspberry, Point::new(120, 60));
-    let mut raspberry3 = Image::new(&raspberry, Point::new(210, 60));
-
-    // raspberry1.draw(&mut display).unwrap();
+    // one image per symbol from the generated table, in reel strip order, decoded while drawn
+    let symbols: [CompressedImage; SYMBOL_COUNT] = core::array::from_fn(|i| SYMBOLS[i].image());
+    let layout = Layout::new(display.size(), symbols[0].size(), &THEME);
+    let mut hud = Hud::new(&symbols, &layout, &THEME);
+    let help = HelpPages::new(display.size(), &symbols, THEME);
 
-
-    let defaults = [rusty_crab1, raspberry2, rusty_crab3];
-    
+    // where the reels stopped last time, starts on rusty_crab, raspberry, rusty_crab
+    let mut reel_positions = [0, SLOT_SIZE, 0];
     for i in 0..3 {
-        let mut default = defaults[i];
-        default.draw(&mut display).unwrap();
-        Timer::after_millis(100).await;
+        hud.slots[i].set_position(reel_positions[i]);
     }
 
 
 
-    let nodejs = ImageRawLE::new(include_bytes!("../../assets/nodejs1.raw"), 80);
-    let mut nodejs1 = Image::new(&nodejs, Point::new(30, 60));
-    let mut nodejs2 = Image::new(&nodejs, Point::new(120, 60));
-    let mut nodejs3 = Image::new(&nodejs, Point::new(210, 60));
-
-    // nodejs1.draw(&mut display).unwrap();
-
-    let javascript = ImageRawLE::new(include_bytes!("../../assets/javascript1.raw"), 80);
-    let mut javascript1 = Image::new(&javascript, Point::new(30, 60));
-    let mut javascript2 = Image::new(&javascript, Point::new(120, 60));
-    let mut javascript3 = Image::new(&javascript, Point::new(210, 60));
-
-    // javascript2.draw(&mut display).unwrap();
-
-    let python = ImageRawLE::new(include_bytes!("../../assets/python1.raw"), 80);
-    let mut python1 = Image::new(&python, Point::new(30, 60));
-    let mut python2 = Image::new(&python, Point::new(120, 60));
-    let mut python3 = Image::new(&python, Point::new(210, 60));
-
-    // python3.draw(&mut display).unwrap();
+    loop{
+        
+        let balance = BALANCE.load(Ordering::SeqCst);
+        if hud.balance.value() != Some(balance) {
+            last_activity = Instant::now();
+        }
+        hud.balance.set(balance);
+        hud.bet.set(bet);
+        BET.store(bet, Ordering::SeqCst);
+        let unsaved = CARD_UNSAVED.load(Ordering::SeqCst);
+        if unsaved != save_warning {
+            if unsaved {
+                hud.banner.show("CARD NOT SAVED", THEME.warning_style());
+            } else {
+                hud.banner.clear();
+            }
+            save_warning = unsaved;
+        }
+        hud.draw(&mut fb);
+        fb.flush(&mut display).await.unwrap();
+
+        // the balance is still refreshed while idle because the card reader changes it,
+        // highlighted symbols wake up the loop for their next frame
+        let wake_at = hud
+            .slots
+            .iter()
+            .filter_map(|slot| slot.next_frame_at())
+            .fold(Instant::now() + Duration::from_millis(100), |a, b| a.min(b));
+        let pressed = match pending.take() {
+            Some(button) => Some(button),
+            None => match select(BUTTONS.receive(), Timer::at(wake_at)).await {
+                Either::First((button, pressed_at)) => {
+                    info!("{:?} handled {} us after the press", button, (Instant::now() - pressed_at).as_micros());
+                    Some(button)
+                }
+                Either::Second(_) => None,
+            },
+        };
+        if pressed.is_some() {
+            last_activity = Instant::now();
+        }
 
-    let csharp = ImageRawLE::new(include_bytes!("../../assets/c#1.raw"), 80);
-    let mut csharp1 = Image::new(&csharp, Point::new(30, 60));
-    let mut csharp2 = Image::new(&csharp, Point::new(120, 60));
-    let mut csharp3 = Image::new(&csharp, Point::new(210, 60));
+        let next_power = Power::after(Instant::now() - last_activity, &settings);
+        if next_power != power {
+            info!("Screen {:?} -> {:?}", power, next_power);
+            let woke_up = power == Power::Asleep;
+            if woke_up {
+                display.sleep_mode(ModeState::Off).await.unwrap();
+                fb.invalidate();
+            }
+            backlight_config.compare_b = power::duty(next_power.brightness(&settings), BACKLIGHT_TOP);
+            backlight.set_config(&backlight_config);
+            if next_power == Power::Asleep {
+                display.sleep_mode(ModeState::On).await.unwrap();
+            }
+            power = next_power;
 
-    // csharp1.draw(&mut display).unwrap();
-    
+            // the press that woke the screen up is used up
+            if woke_up {
+                continue;
+            }
+        }
 
+        if pressed.is_none() && power != Power::Asleep && settings.attract_after_s != 0 && Instant::now() - last_activity >= attract_after {
+            info!("Nobody played for {} s, starting attract mode", settings.attract_after_s);
+            publ.publish(State::ATTRACT).await;
+            for slot in hud.slots.iter_mut() {
+                slot.clear_highlight();
+            }
+            CARD_SHOWN.reset();
+            ATTRACT_EXIT.reset();
+
+            // the press or the card ends attract mode at the next frame, and so does the
+            // screen dimming or going to sleep
+            let step_at = power
+                .next_step(&settings)
+                .map_or(Instant::MAX, |after| last_activity + after);
+            let (_, ended_by) = join(
+                attract(&mut hud, &mut fb, &mut display, &mut reel_positions, &mut rng, bet),
+                async {
+                    let ended_by = match select3(BUTTONS.receive(), CARD_SHOWN.wait(), Timer::at(step_at)).await {
+                        Either3::First((button, _)) => {
+                            info!("{:?} ended attract mode", button);
+                            Some(false)
+                        }
+                        Either3::Second(_) => {
+                            info!("Card ended attract mode");
+                            Some(true)
+                        }
+                        Either3::Third(_) => None,
+                    };
+                    ATTRACT_EXIT.signal(());
+                    ended_by
+                },
+            )
+            .await;
+            publ.publish(State::ATTRACTEND).await;
+
+            hud.banner.clear();
+            if ended_by.is_some() {
+                last_activity = Instant::now();
+            }
 
+            // a card shown while attract mode ran is inserted like with the cashout button
+            if ended_by == Some(true) && cashout % 2 == 1 {
+                cashout += 1;
+                publ.publish(State::ADDBALANCE).await;
+                info!("Adding balance");
+                Timer::after_millis(50).await;
+            }
+            continue;
+        }
 
-    loop{
-        
-        let mut buffer: String<32> = String::new();
-        write!(&mut buffer, "BALANCE: {}", BALANCE.load(Ordering::SeqCst)).unwrap();
-        // write!(&mut buffer, "BALANCE: {}", balance).unwrap();
-        Text::new(&buffer, Point::new(10, 225), text_style)
-            .draw(&mut display)
-            .unwrap();
-
-        if increase_bet.is_low() {
-            Rectangle::new(Point::new(260, 210), Size::new(50, 20))
-                .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
-                .draw(&mut display)
-                .unwrap();
+        if pressed == Some(Button::INCREASEBET) {
             if bet < 2500 {
                 bet += 500;
             }
@@ -339,414 +863,559 @@ async fn display_task(
             }
             publ.publish(State::BET).await;
         }
-        if max_bet.is_low() {
-            Rectangle::new(Point::new(260, 210), Size::new(50, 20))
-                .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
-                .draw(&mut display)
-                .unwrap();
+        if pressed == Some(Button::MAXBET) {
             bet=2500;
             publ.publish(State::BET).await;
         }
-        let mut buffer: String<32> = String::new();
-        write!(&mut buffer, "BET: {}", bet).unwrap();
-        Text::new(&buffer, Point::new(220, 225), text_style)
-            .draw(&mut display)
-            .unwrap();
 
-        if cashout_button.is_low() {
+        if pressed == Some(Button::HELP) {
+            // spin pages through the paytable, any other button goes back to the game
+            info!("Showing the paytable");
+            let mut page = 0;
+            loop {
+                help.draw(page, bet, &mut fb).unwrap();
+                fb.flush(&mut display).await.unwrap();
+
+                match select(BUTTONS.receive(), Timer::after(HELP_TIMEOUT)).await {
+                    Either::First((Button::SPIN, _)) => page = (page + 1) % help.len(),
+                    _ => break,
+                }
+            }
+
+            fb.clear(THEME.background).unwrap();
+            hud.invalidate();
+            last_activity = Instant::now();
+        }
+
+        if pressed == Some(Button::CASHOUT) {
 
             cashout+=1;
     
             if cashout%2==0{
-
                 publ.publish(State::ADDBALANCE).await;
-
                 info!("Adding balance");
-                Rectangle::new(Point::new(90, 210), Size::new(120, 20))
-                    .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
-                    .draw(&mut display)
-                    .unwrap();
-                Timer::after_millis(50).await;
-                let mut buffer: String<32> = String::new();
-                write!(&mut buffer, "BALANCE: {}", BALANCE.load(Ordering::SeqCst)).unwrap();
-                Text::new(&buffer, Point::new(10, 225), text_style)
-                    .draw(&mut display)
-                    .unwrap();
             }
             else{
-
                 publ.publish(State::CASHOUT).await;
-
                 info!("Cashout");
-                Rectangle::new(Point::new(90, 210), Size::new(120, 20))
-                    .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
-                    .draw(&mut display)
-                    .unwrap();
-                let mut buffer: String<32> = String::new();
-                // BALANCE.store(0, Ordering::SeqCst);
-                Timer::after_millis(50).await;
-                write!(&mut buffer, "BALANCE: {}", BALANCE.load(Ordering::SeqCst)).unwrap();
-                Text::new(&buffer, Point::new(10, 225), text_style)
-                    .draw(&mut display)
-                    .unwrap();
             }
+            // give the card reader a moment, the balance is redrawn on the next iteration
+            Timer::after_millis(50).await;
         }
 
 
-        if spin_button.is_low() {
+        if pressed == Some(Button::SPIN) {
                     
             if bet>BALANCE.load(Ordering::SeqCst){
                 info!("Not enough money");
-                Rectangle::new(Point::new(40, 15), Size::new(250, 40))
-                    .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
-                    .draw(&mut display)
-                    .unwrap();
-                let text_style = MonoTextStyle::new(&FONT_10X20, Rgb565::RED);
-                Text::new("Not enough money!", Point::new(80, 40), text_style)
-                    .draw(&mut display)
-                    .unwrap();
-                Timer::after_millis(2000).await;
-                Rectangle::new(Point::new(40, 15), Size::new(250, 40))
-                    .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
-                    .draw(&mut display)
-                    .unwrap();
+                hud.banner.show_for("Not enough money!", THEME.warning_style(), Duration::from_millis(2000));
             }
             else{
 
-                publ.publish(State::SPIN).await;
+                SPINNING.store(true, Ordering::SeqCst);
+                publ.publish(State::CUE(Cue::SpinStart.at(Instant::now().as_millis()))).await;
+                link(Message::SpinStart { bet });
 
                 BALANCE.fetch_sub(bet, Ordering::SeqCst);
+                for slot in hud.slots.iter_mut() {
+                    slot.clear_highlight();
+                }
 
-                Rectangle::new(Point::new(90, 210), Size::new(120, 20))
-                    .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
-                    .draw(&mut display)
-                    .unwrap();
-
-                let mut buffer: String<32> = String::new();
-                write!(&mut buffer, "BALANCE: {}", BALANCE.load(Ordering::SeqCst)).unwrap();
-                Text::new(&buffer, Point::new(10, 225), text_style)
-                    .draw(&mut display)
-                    .unwrap();
-
-                let mut buffer: String<32> = String::new();
-                write!(&mut buffer, "LAST WIN: {}", win_amount).unwrap();
-                Text::new(&buffer, Point::new(80, 175), text_style)
-                    .draw(&mut display)
-                    .unwrap();
-
-                    //sterge mesajul de dupa fiecare rotire
-                // Rectangle::new(Point::new(60, 15), Size::new(230, 40))
-                //     .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
-                //     .draw(&mut display)
-                //     .unwrap();
-
-                let mut last_slots = [0, 6, 12];
-
-
-                for _ in 0..10 {
-                    let predefined_slots = [
-                        rusty_crab1,
-                        raspberry1,
-                        nodejs1,
-                        javascript1,
-                        python1,
-                        csharp1,
-                        rusty_crab2,
-                        raspberry2,
-                        nodejs2,
-                        javascript2,
-                        python2,
-                        csharp2,
-                        rusty_crab3,
-                        raspberry3,
-                        nodejs3,
-                        javascript3,
-                        python3,
-                        csharp3,
-                    ];
-
-                    let predifined_indexes = [
-                //rusty_crab  raspberry  nodejs  javascript  python  csharp
-                        0, 1, 2, 3, 4, 5,
-                        6, 7, 8, 9, 10, 11,
-                        12, 13, 14, 15, 16, 17,
-                    ];
-
-                    let mut slots = [rusty_crab1, rusty_crab2, rusty_crab3];
-                    let mut slots_indexes = [0, 6, 12];
-
-                    let mut start=0;
-                    let mut end=6;
+                hud.balance.set(BALANCE.load(Ordering::SeqCst));
+                hud.last_win.set(win_amount);
 
-                    for i in 0..3 {
-                        let slot_index = rng.gen_range(start..end);
-                        start+=6;
-                        end+=6;
-                        slots[i] = predefined_slots[slot_index];
-                        slots_indexes[i] = predifined_indexes[slot_index];
+                // the result is decided before the reels start moving, the animation only lands on it
+                let targets = core::array::from_fn(|_| rng.gen_range(0..REEL_SYMBOLS));
+                let result = spin_reels(&mut hud, &mut fb, &mut display, &mut reel_positions, targets, Some(&mut publ)).await;
 
-                        slots[i].draw(&mut display).unwrap();
+                let win = paytable::evaluate(result, bet);
+                if let Some(win) = &win {
+                    win_amount = win.amount;
+                    BALANCE.fetch_add(win_amount, Ordering::SeqCst);
+                }
+                let tier = win.as_ref().map(|win| WinTier::for_win(win.amount, bet));
+                AUDIT.lock(|audit| {
+                    audit.borrow_mut().game(
+                        bet,
+                        result.map(|symbol| symbol as u8),
+                        win.as_ref().map_or(0, |win| win.amount),
+                        tier == Some(WinTier::Jackpot),
+                    )
+                });
+                SPINNING.store(false, Ordering::SeqCst);
+                publ.publish(State::CUE(Cue::ResultShown(tier).at(Instant::now().as_millis()))).await;
+                link(Message::Result {
+                    reels: result.map(|symbol| symbol as u8),
+                    win: win.as_ref().map_or(0, |win| win.amount),
+                });
+
+                if let (Some(win), Some(tier)) = (win, tier) {
+                    // reels whose symbol is part of the win play the symbol's animation
+                    let winning = win.reels;
+                    link(Message::Win { tier: tier.into(), amount: win_amount });
+                    if tier == WinTier::Jackpot {
+                        LAST_JACKPOT.store(win_amount, Ordering::SeqCst);
                     }
+                    info!("You won {} ({:?})", win_amount, tier);
 
-                    last_slots = slots_indexes;
+                    for i in 0..3 {
+                        if let (true, Some(animation)) = (winning[i], SYMBOLS[result[i]].animation) {
+                            hud.slots[i].highlight(animation);
+                        }
+                    }
+                    hud.banner.show(tier.title(), THEME.text_style());
+                    hud.coins.start(tier.coins_per_second());
+
+                    // count the meter up while the winning reels flash, any button skips to the end
+                    let start = Instant::now();
+                    let ends_ms = (start + tier.duration()).as_millis();
+                    publ.publish(State::CUE(Cue::CelebrationStart { tier, ends_ms }.at(start.as_millis()))).await;
+                    let mut ticker = Ticker::every(Duration::from_millis(REEL_FRAME_MS));
+                    loop {
+                        let elapsed = Instant::now() - start;
+                        if elapsed >= tier.duration() {
+                            break;
+                        }
+                        hud.last_win.set(count_up(win_amount, elapsed, tier.duration()));
+                        hud.flash_reels(winning, elapsed.as_millis() / WIN_FLASH_MS % 2 == 0);
+                        hud.draw(&mut fb);
+                        fb.flush(&mut display).await.unwrap();
+
+                        if let Either::First((button, _)) = select(BUTTONS.receive(), ticker.next()).await {
+                            info!("Win presentation skipped");
+                            // the skipping spin press is used up, anything else still counts
+                            if button != Button::SPIN {
+                                pending = Some(button);
+                            }
+                            break;
+                        }
+                    }
 
-                    Timer::after_millis(250).await;
+                    hud.last_win.set(win_amount);
+                    hud.flash_reels(winning, false);
+                    hud.coins.stop();
+                    publ.publish(State::CUE(Cue::CelebrationEnd.at(Instant::now().as_millis()))).await;
                 }
+                else{
+                    let messages = ["Strapped for cash!", "That hurts!", "Keep spinning!", "Almost there!", "Spent!", "Ruined!", "Bankrupt!", "Broke!", "Worthless!", "Soup line!"];
+                    let message_index = rng.gen_range(0..messages.len());
 
-                let mut you_won = false;
-                if last_slots[0] == 0 && last_slots[1] == 6 && last_slots[2] == 12 {         //3 rusty_crab
-                    you_won = true;
-                    win_amount = 500000*(bet/500);
-                    BALANCE.fetch_add(win_amount, Ordering::SeqCst);
-                }
-                else if last_slots[0]==0 && last_slots[1]==6 || last_slots[1]==6 && last_slots[2]==12{       //2 rusty_crab
-                    you_won = true;
-                    win_amount = 7500*(bet/500);
-                    BALANCE.fetch_add(win_amount, Ordering::SeqCst);
-                }
-                else if last_slots[0]==0 || last_slots[1]==6 || last_slots[2]==12{           //1 rusty_crab
-                    you_won = true;
-                    win_amount = 100*(bet/500);
-                    BALANCE.fetch_add(win_amount, Ordering::SeqCst);
-                }
-                else if last_slots[0]==1 && last_slots[1]==7 || last_slots[1]==7 && last_slots[2]==13{       //2 raspberry
-                    you_won = true;
-                    win_amount = 5000*(bet/500);
-                    BALANCE.fetch_add(win_amount, Ordering::SeqCst);
-                }
-                if last_slots[0] == 1 && last_slots[1] == 7 && last_slots[2] == 13 {          //3 raspberry
-                    you_won = true;
-                    win_amount = 250000*(bet/500);
-                    BALANCE.fetch_add(win_amount, Ordering::SeqCst);
-                }
-                if last_slots[0] == 2 && last_slots[1] == 8 && last_slots[2] == 14 {          //3 nodejs
-                    you_won = true;
-                    win_amount = 50000*(bet/500);
-                    BALANCE.fetch_add(win_amount, Ordering::SeqCst);
-                }
-                if last_slots[0] == 3 && last_slots[1] == 9 && last_slots[2] == 15 {          //3 javascript
-                    you_won = true;
-                    win_amount = 37500*(bet/500);
-                    BALANCE.fetch_add(win_amount, Ordering::SeqCst);
-                }
-                if last_slots[0] == 4 && last_slots[1] == 10 && last_slots[2] == 16 {          //3 python
-                    you_won = true;
-                    win_amount = 25000*(bet/500);
-                    BALANCE.fetch_add(win_amount, Ordering::SeqCst);
-                }
-                if last_slots[0] == 5 && last_slots[1] == 11 && last_slots[2] == 17 {          //3 csharp
-                    you_won = true;
-                    win_amount = 12500*(bet/500);
-                    BALANCE.fetch_add(win_amount, Ordering::SeqCst);
+                    hud.banner.show(messages[message_index], THEME.text_style());
                 }
-                
 
-                if you_won {
-
-                    publ.publish(State::WIN).await;
+                info!("Slot animation finished");
+            }
+        }
+    }
+}
 
-                        //modificarea balantei
-                    Rectangle::new(Point::new(90, 210), Size::new(120, 20))
-                        .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
-                        .draw(&mut display)
-                        .unwrap();
+/// The paytable for the current bet on the small display, turning pages by itself above
+/// the jackpot meters.
+#[embassy_executor::task]
+async fn side_display_task(
+    spi_bus: &'static Mutex<ThreadModeRawMutex, DisplayBus>,
+    cs: Output<'static>,
+    dc: Output<'static>,
+    mut reset: Output<'static>,
+) {
+    let mut config = ConfigSpi::default();
+    config.frequency = SIDE_SPI_HZ;
+    let spi_dev = SpiDeviceWithConfig::new(spi_bus, cs, config);
+    let mut display = St7735Async::new(spi_dev, dc, &mut reset, SIDE_SIZE, SIDE_OFFSET, Orientation::Portrait)
+        .await
+        .unwrap();
+    let mut fb = FrameBuffer::new(SIDE_FRAME.take(), display.size());
+    fb.clear(SIDE_THEME.background).unwrap();
+
+    let size = display.size();
+    let line_height = SIDE_THEME.font.character_size.height as i32;
+    let baseline = SIDE_THEME.font.baseline as i32;
+    let meters_top = size.height as i32 - 2 * line_height - 6;
+    let pages_area = Rectangle::new(Point::zero(), Size::new(size.width, meters_top as u32));
+
+    let symbols: [CompressedImage; SYMBOL_COUNT] = core::array::from_fn(|i| SYMBOLS[i].image());
+    // 16 pixel symbols, three lines to a page
+    let help = HelpPages::new(pages_area.size, &symbols, SIDE_THEME)
+        .with_scale(5)
+        .without_footer();
+    let mut divider = Frame::filled(Rectangle::new(Point::new(0, meters_top), Size::new(size.width, 2)), SIDE_THEME.frame);
+    let mut jackpot = NumberField::new("JACKPOT ", Point::new(4, meters_top + 4 + baseline), SIDE_THEME.text_style());
+    let mut last_jackpot = NumberField::new("LAST HIT ", Point::new(4, meters_top + 4 + line_height + baseline), SIDE_THEME.text_style());
+
+    let mut page = 0;
+    let mut shown = None;
+    let mut turn_at = Instant::now() + Duration::from_millis(SIDE_PAGE_MS);
 
-                    Rectangle::new(Point::new(90, 15), Size::new(180, 40))
-                        .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
-                        .draw(&mut display)
-                        .unwrap();
+    loop {
+        let bet = BET.load(Ordering::SeqCst);
+        if shown != Some((page, bet)) {
+            help.draw(page, bet, &mut fb.clipped(&pages_area)).unwrap();
+            shown = Some((page, bet));
+        }
+        jackpot.set(paytable::top_award(bet));
+        last_jackpot.set(LAST_JACKPOT.load(Ordering::SeqCst));
+
+        divider.draw(&mut fb).unwrap();
+        jackpot.draw(&mut fb).unwrap();
+        last_jackpot.draw(&mut fb).unwrap();
+        fb.flush(&mut display).await.unwrap();
+
+        Timer::after_millis(250).await;
+        if Instant::now() >= turn_at {
+            page = (page + 1) % help.len();
+            turn_at += Duration::from_millis(SIDE_PAGE_MS);
+        }
+    }
+}
 
-                    let text_style = MonoTextStyle::new(&FONT_10X20, Rgb565::GREEN);
-                    Text::new("THAT'S A WIN!!!", Point::new(85, 40), text_style)
-                        .draw(&mut display)
-                        .unwrap();
+/// Queues a message for the secondary display Pico.
+fn link(message: Message) {
+    if LINK.try_send(message).is_err() {
+        info!("Link queue full, dropping {:?}", message);
+    }
+}
 
-                    Rectangle::new(Point::new(180, 155), Size::new(70, 30))
-                        .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
-                        .draw(&mut display)
-                        .unwrap();
+/// What the secondary display has to know at any time, sent when it changes and
+/// all of it again when the other side asks for a resync.
+fn link_state() -> [Message; 2] {
+    let bet = BET.load(Ordering::SeqCst);
+    [
+        Message::Session {
+            card: CARD_IN.load(Ordering::SeqCst),
+            balance: BALANCE.load(Ordering::SeqCst),
+            bet,
+        },
+        Message::Jackpot {
+            top_award: paytable::top_award(bet),
+            last_hit: LAST_JACKPOT.load(Ordering::SeqCst),
+        },
+    ]
+}
 
-                    let mut buffer: String<32> = String::new();
-                    write!(&mut buffer, "LAST WIN: {}", win_amount).unwrap();
-                    Text::new(&buffer, Point::new(80, 175), text_style)
-                        .draw(&mut display)
-                        .unwrap();
+/// Talks to the secondary display Pico over UART0, the frames are described in `arcade_link`.
+#[embassy_executor::task]
+async fn link_task(uart: BufferedUart<'static, UART0>) {
+    let (mut tx, mut rx) = uart.split();
+    let mut sender = Sender::new();
+    let mut receiver = Receiver::new();
+    let mut frame = [0u8; MAX_FRAME];
+    let mut received = [0u8; 32];
+
+    let mut heartbeat = Ticker::every(Duration::from_millis(HEARTBEAT_MS));
+    let mut last_heard = Instant::now();
+    let mut peer_up = false;
+    let mut sent_state: Option<[Message; 2]> = None;
 
-                    info!("You won!");
+    loop {
+        let mut outgoing: heapless::Vec<Message, 4> = heapless::Vec::new();
+
+        match select3(rx.read(&mut received), LINK.receive(), heartbeat.next()).await {
+            Either3::First(Ok(count)) => {
+                for &byte in &received[..count] {
+                    let Some(message) = receiver.push(byte) else {
+                        continue;
+                    };
+                    last_heard = Instant::now();
+                    if !peer_up {
+                        info!("Link up");
+                        peer_up = true;
+                    }
+                    if message == Message::Resync {
+                        info!("The secondary display asked for a resync");
+                        sent_state = None;
+                    }
                 }
-                else{
-                    let messages = ["Strapped for cash!", "That hurts!", "Keep spinning!", "Almost there!", "Spent!", "Ruined!", "Bankrupt!", "Broke!", "Worthless!", "Soup line!"];
-                    let message_index = rng.gen_range(0..messages.len());
-                    let mut buffer: String<32> = String::new();
-
-                    Rectangle::new(Point::new(80, 15), Size::new(200, 40))
-                        .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
-                        .draw(&mut display)
-                        .unwrap();
-
-                    write!(&mut buffer, "{}", messages[message_index]).unwrap();
-                    Text::new(&buffer, Point::new(90, 40), text_style)
-                        .draw(&mut display)
-                        .unwrap();
+                if receiver.take_lost() {
+                    info!("Link frame lost, asking for a resync");
+                    outgoing.push(Message::Resync).ok();
+                }
+            }
+            Either3::First(Err(e)) => info!("Link read error: {:?}", e),
+            Either3::Second(message) => {
+                outgoing.push(message).ok();
+            }
+            Either3::Third(_) => {
+                outgoing.push(Message::Heartbeat { uptime_ms: Instant::now().as_millis() as u32 }).ok();
+                if peer_up && Instant::now() - last_heard > Duration::from_millis(LINK_TIMEOUT_MS) {
+                    info!("Link down, nothing heard for {} ms", LINK_TIMEOUT_MS);
+                    peer_up = false;
                 }
+            }
+        }
 
-                info!("Slot animation finished");
+        let state = link_state();
+        for (i, message) in state.iter().enumerate() {
+            if sent_state.map(|sent| sent[i]) != Some(*message) {
+                outgoing.push(*message).ok();
             }
         }
+        sent_state = Some(state);
 
-        Timer::after_millis(100).await;
+        for message in outgoing {
+            if let Err(e) = tx.write_all(sender.frame(&message, &mut frame)).await {
+                info!("Link write error: {:?}", e);
+            }
+        }
     }
 }
 
 #[embassy_executor::task]
-async fn led_task(
-    mut yellow: Output<'static>,
-    mut green: Output<'static>,
-    mut blue: Output<'static>,
-    mut red: Output<'static>,
+async fn input_task(
+    mut spin_button: Input<'static>,
+    mut increase_bet: Input<'static>,
+    mut max_bet: Input<'static>,
+    mut cashout_button: Input<'static>,
 ) {
-    info!("LED task started.");
-    yellow.set_high();
-    green.set_high();
-    blue.set_high();
-    red.set_high();
+    info!("Input task started.");
 
-    let mut subs = CHANNEL.subscriber().unwrap();
-    let mut subs_info = CHANNEL.subscriber().unwrap();
+    loop {
+        let button = match select4(
+            spin_button.wait_for_falling_edge(),
+            increase_bet.wait_for_falling_edge(),
+            max_bet.wait_for_falling_edge(),
+            cashout_button.wait_for_falling_edge(),
+        )
+        .await
+        {
+            Either4::First(_) => Button::SPIN,
+            Either4::Second(_) => Button::INCREASEBET,
+            Either4::Third(_) => Button::MAXBET,
+            Either4::Fourth(_) => Button::CASHOUT,
+        };
+        let pressed_at = Instant::now();
+
+        // the two bet buttons pressed together open the help pages, so a bet button
+        // waits a moment for the other one before it counts on its own
+        let button = match button {
+            Button::INCREASEBET | Button::MAXBET => {
+                let other = if button == Button::INCREASEBET { &mut max_bet } else { &mut increase_bet };
+                if other.is_low() {
+                    Button::HELP
+                } else {
+                    match select(other.wait_for_falling_edge(), Timer::after_millis(COMBO_MS)).await {
+                        Either::First(_) => Button::HELP,
+                        Either::Second(_) => button,
+                    }
+                }
+            }
+            _ => button,
+        };
+
+        if BUTTONS.try_send((button, pressed_at)).is_err() {
+            info!("Input queue full, dropping {:?}", button);
+        }
+        Timer::after_millis(DEBOUNCE_MS).await;
+    }
+}
+
+/// Measures how late a 1 ms timer fires, which is how long the executor was kept busy
+/// by another task. That is the worst delay a button press sees before the input task runs.
+/// The worst of every spin is logged when the result shows, build with `blocking-spi` for
+/// the numbers without DMA.
+#[embassy_executor::task]
+async fn latency_task() {
+    let mut worst = Duration::from_ticks(0);
+    let mut window_start = Instant::now();
+    let mut worst_spin: Option<Duration> = None;
 
     loop {
+        let before = Instant::now();
+        Timer::after_millis(1).await;
+        let late = Duration::from_ticks((Instant::now() - before).as_ticks().saturating_sub(Duration::from_millis(1).as_ticks()));
+        worst = worst.max(late);
+
+        match (SPINNING.load(Ordering::SeqCst), worst_spin) {
+            (true, _) => worst_spin = Some(worst_spin.map_or(late, |spin| spin.max(late))),
+            (false, Some(spin)) => {
+                info!("Worst executor stall during the spin: {} us", spin.as_micros());
+                worst_spin = None;
+            }
+            (false, None) => {}
+        }
 
-        info!("Received value: {:?}", subs_info.next_message().await);
-
-        match subs.next_message().await {
-            wrm(State::SPIN) => {
-                let start_time = embassy_time::Instant::now();
-                info!("LED sequence started.");
-
-                while embassy_time::Instant::now() - start_time < embassy_time::Duration::from_millis(5000){
-                    yellow.set_high();
-                    green.set_low();
-                    blue.set_low();
-                    red.set_low();
-                    Timer::after_millis(120).await;
-
-                    yellow.set_low();
-                    green.set_high();
-                    blue.set_low();
-                    red.set_low();
-                    Timer::after_millis(120).await;
-
-                    yellow.set_low();
-                    green.set_low();
-                    blue.set_high();
-                    red.set_low();
-                    Timer::after_millis(120).await;
-
-                    yellow.set_low();
-                    green.set_low();
-                    blue.set_low();
-                    red.set_high();
-                    Timer::after_millis(120).await;
-                }
+        if Instant::now() - window_start >= Duration::from_secs(5) {
+            info!("Worst executor stall in the last 5 s: {} us", worst.as_micros());
+            worst = Duration::from_ticks(0);
+            window_start = Instant::now();
+        }
+    }
+}
 
-                info!("LED sequence finished, turning LEDs back on.");
-
-                yellow.set_high();
-                green.set_high();
-                blue.set_high();
-                red.set_high();
-            }
-            wrm(State::WIN) => {
-                let start_time = embassy_time::Instant::now();
-
-                while embassy_time::Instant::now() - start_time
-                    < embassy_time::Duration::from_millis(2000)
-                {
-                    yellow.set_high();
-                    green.set_high();
-                    blue.set_high();
-                    red.set_high();
-                    Timer::after_millis(250).await;
-
-                    yellow.set_low();
-                    green.set_low();
-                    blue.set_low();
-                    red.set_low();
-                    Timer::after_millis(250).await;
-                }
+#[embassy_executor::task]
+async fn strip_task(mut strip: CabinetStrip) {
+    info!("Strip task started.");
 
-                info!("LED sequence finished, turning LEDs back on.");
+    let mut show = Show::new(Effect::Comet);
+    let mut leds = [Rgb::OFF; arcade_strip::STRIP_LEN];
+    let mut subs = CHANNEL.subscriber().unwrap();
 
-                yellow.set_high();
-                green.set_high();
-                blue.set_high();
-                red.set_high();
+    loop {
+        let next = show.render(Instant::now().as_millis(), &mut leds);
+        strip.write(&leds.map(|led| RGB8::new(led.r, led.g, led.b))).await;
+
+        // sleep until the next frame or until something happens in the game
+        let until = next.map_or(Instant::MAX, Instant::from_millis);
+        let message = match select(subs.next_message(), Timer::at(until)).await {
+            Either::First(message) => message,
+            Either::Second(_) => continue,
+        };
+        info!("Received value: {:?}", message);
+
+        let now = Instant::now().as_millis();
+        match message {
+            // effects start when the cue happened, not when it got here
+            wrm(State::CUE(timed)) => match timed.cue {
+                Cue::SpinStart => show.play_for(Effect::Rainbow, timed.at_ms, timeline::SPIN_TIMEOUT.as_millis()),
+                Cue::ReelStopped(_) => show.flash(Effect::Solid(Rgb::WHITE), timed.at_ms, REEL_STOP_FLASH_MS),
+                Cue::ResultShown(_) => show.idle(timed.at_ms),
+                Cue::CelebrationStart { tier, ends_ms } => {
+                    show.play_for(Effect::Win(tier.into()), timed.at_ms, timed.ms_until(ends_ms))
+                }
+                Cue::CelebrationEnd => show.idle(timed.at_ms),
+            },
+            wrm(State::ATTRACT) => {
+                show.set_brightness(ATTRACT_LED_BRIGHTNESS);
+                show.play(Effect::Attract, now);
+            }
+            wrm(State::ATTRACTEND) => {
+                show.set_brightness(100);
+                show.idle(now);
             }
             wrm(State::BET) =>{}
             wrm(State::ADDBALANCE) =>{}
             wrm(State::CASHOUT) =>{}
             Lagged(_) => {}
         }
-        Timer::after(Duration::from_millis(50)).await;
     }
 }
 
-#[embassy_executor::task]
-async fn buzzer_task(mut buzzer: Pwm<'static>) {
-    info!("Buzzer task started.");
+/// Buzzer PWM clock divider for square waves, the counter runs at clk_sys / 125.
+const BUZZER_DIVIDER: u8 = 125;
 
-    let mut config: ConfigPwm = Default::default();
-    config.top = 5000;
-    config.divider = 125_i32.to_fixed();
-    config.compare_a = config.top / 2;
+// what the audio task plays, with the volume, set by the buzzer task
+static AUDIO: Signal<CriticalSectionRawMutex, (Voice, u8)> = Signal::new();
+// runs the audio task above everything else so samples come out on time
+static AUDIO_EXECUTOR: InterruptExecutor = InterruptExecutor::new();
+
+#[interrupt]
+unsafe fn SWI_IRQ_1() {
+    AUDIO_EXECUTOR.on_interrupt()
+}
 
+/// Owns the buzzer PWM and plays what the buzzer task decided on: square waves by
+/// setting `top` to the frequency, samples by setting the duty cycle of a fast carrier
+/// to every sample in turn. A new voice cuts a sample off.
+#[embassy_executor::task]
+async fn audio_task(mut buzzer: Pwm<'static>) {
+    let counter_hz = embassy_rp::clocks::clk_sys_freq() / BUZZER_DIVIDER as u32;
+    let mut config: ConfigPwm = Default::default();
+    config.divider = BUZZER_DIVIDER.to_fixed();
+    config.compare_a = 0;
     buzzer.set_config(&config);
-    buzzer.set_duty_cycle(0);
 
+    loop {
+        let (voice, volume) = AUDIO.wait().await;
+        match voice {
+            Voice::Square(frequency) if volume > 0 => {
+                config.divider = BUZZER_DIVIDER.to_fixed();
+                config.top = melody::pwm_top(frequency, counter_hz);
+                config.compare_a = sound::compare(config.top, volume);
+                buzzer.set_config(&config);
+            }
+            Voice::Pcm { sample, started_ms } if volume > 0 => {
+                config.divider = 1.to_fixed();
+                config.top = pcm::CARRIER_TOP;
+                config.compare_a = pcm::level(128, volume);
+                buzzer.set_config(&config);
+
+                let from = sample.position(Instant::now().as_millis().saturating_sub(started_ms));
+                let mut ticker = Ticker::every(Duration::from_hz(pcm::SAMPLE_RATE as u64));
+                for &value in &sample.data[from..] {
+                    if AUDIO.signaled() {
+                        break;
+                    }
+                    buzzer.set_duty_cycle(pcm::level(value, volume));
+                    ticker.next().await;
+                }
+                buzzer.set_duty_cycle(0);
+            }
+            _ => {
+                config.compare_a = 0;
+                buzzer.set_config(&config);
+            }
+        }
+    }
+}
+
+/// Decides what the buzzer plays, see `sound`, and hands it to the audio task.
+#[embassy_executor::task]
+async fn buzzer_task(settings: Config) {
+    info!("Buzzer task started.");
+
+    let volume = if settings.muted { 0 } else { settings.volume };
+    let mut engine = SoundEngine::new();
     let mut subs = CHANNEL.subscriber().unwrap();
-    let mut subs_info = CHANNEL.subscriber().unwrap();
+    let mut voice = Voice::Silent;
+    // the celebration jingles repeat, they stop here if the end cue is lost
+    let mut celebration_ends: Option<u64> = None;
 
     loop {
+        let now = Instant::now().as_millis();
+        if celebration_ends.is_some_and(|ends| now >= ends) {
+            engine.stop(Event::Win(WinTier::Small));
+            celebration_ends = None;
+        }
+        let tone = engine.tone(now);
+        if tone.voice != voice {
+            voice = tone.voice;
+            AUDIO.signal((voice, volume));
+        }
 
-        match subs.next_message().await {
-            wrm(State::SPIN) => {
-                for _ in 0..25 {
-                    buzzer.set_duty_cycle(config.top / 2);
-                    Timer::after(Duration::from_millis(50)).await;
-                    buzzer.set_duty_cycle(0);
-                    Timer::after(Duration::from_millis(70)).await;
+        // sleep until the tone changes, the celebration ends or something happens in the game
+        let until = match (tone.until_ms, celebration_ends) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        let until = until.map_or(Instant::MAX, Instant::from_millis);
+        let message = match select(subs.next_message(), Timer::at(until)).await {
+            Either::First(message) => message,
+            Either::Second(_) => continue,
+        };
+
+        let now = Instant::now().as_millis();
+        match message {
+            // sounds start when the cue happened, so they line up with the reels
+            wrm(State::CUE(timed)) => match timed.cue {
+                Cue::SpinStart => {
+                    engine.play(Event::Spin, timed.at_ms);
+                    engine.play(Event::SpinStart, timed.at_ms);
                 }
-            }
-            wrm(State::WIN) => {
-                buzzer.set_duty_cycle(config.top / 2);
-                Timer::after(Duration::from_millis(80)).await;
-                buzzer.set_duty_cycle(0);
-                Timer::after(Duration::from_millis(50)).await;
-                buzzer.set_duty_cycle(config.top / 2);
-                Timer::after(Duration::from_millis(1000)).await;
-                buzzer.set_duty_cycle(0);
-            }
+                Cue::ReelStopped(_) => {
+                    engine.play(Event::ReelStop, timed.at_ms);
+                }
+                Cue::ResultShown(_) => engine.stop(Event::Spin),
+                Cue::CelebrationStart { tier, ends_ms } => {
+                    engine.play(Event::Win(tier), timed.at_ms);
+                    celebration_ends = Some(ends_ms);
+                }
+                Cue::CelebrationEnd => {
+                    engine.stop(Event::Win(WinTier::Small));
+                    celebration_ends = None;
+                }
+            },
             wrm(State::BET) => {
-                buzzer.set_duty_cycle(config.top / 2);
-                Timer::after(Duration::from_millis(50)).await;
-                buzzer.set_duty_cycle(0);
-                Timer::after(Duration::from_millis(50)).await;
+                engine.play(Event::Bet, now);
             }
             wrm(State::ADDBALANCE) => {
-                buzzer.set_duty_cycle(config.top / 2);
-                Timer::after(Duration::from_millis(50)).await;
-                buzzer.set_duty_cycle(0);
-                Timer::after(Duration::from_millis(50)).await;
+                engine.play(Event::AddBalance, now);
             }
             wrm(State::CASHOUT) => {
-                buzzer.set_duty_cycle(config.top / 2);
-                Timer::after(Duration::from_millis(50)).await;
-                buzzer.set_duty_cycle(0);
-                Timer::after(Duration::from_millis(50)).await;
+                engine.play(Event::CashOut, now);
             }
+            wrm(State::ATTRACT) => {}
+            wrm(State::ATTRACTEND) => {}
             Lagged(_) => {}
         }
-        Timer::after(Duration::from_millis(50)).await;
     }
 }
 
@@ -760,36 +1429,95 @@ async fn rfid_task(
 ) {
     let mut mfrc = Mfrc522::new(spi).with_nss(cs).init().unwrap();
 
-    let mut known_uids: [([u8; 4], u32); 2] = [
-        // ([0xAA, 0xBB, 0xCC, 0xDD], 1), // Existing example UID with associated number
-        (UID1, BALANCE1.load(Ordering::SeqCst) as u32),   // New known UID with associated number
-        (UID2, BALANCE2.load(Ordering::SeqCst) as u32),      // Another known UID with associated number
-    ];
-
     let mut subs = CHANNEL.subscriber().unwrap();
+    // a card seen during attract mode, the display answers with ADDBALANCE
+    let mut detected = None;
+    // when to write the cards again after a cashout couldn't store a balance
+    let mut retry_at: Option<Instant> = None;
 
     loop {
+        let message = match select3(subs.next_message(), SERVICE.receive(), Timer::at(retry_at.unwrap_or(Instant::MAX))).await {
+            Either3::First(message) => message,
+            Either3::Second(Service::SAVECARD(index)) => {
+                let card = CARDS.lock(|cards| cards.borrow().get(index).copied().flatten());
+                let saved = match card {
+                    Some(card) => save_card(&mut i2c, index, &card).await.is_ok(),
+                    None => false,
+                };
+                if !saved {
+                    info!("Could not store the card");
+                }
+                EEPROM_IO.signal(saved.then(heapless::Vec::new));
+                continue;
+            }
+            Either3::Second(Service::SAVEPAYTABLE) => {
+                let saved = save_paytable(&mut i2c).await.is_ok();
+                if !saved {
+                    info!("Could not store the paytable");
+                }
+                EEPROM_IO.signal(saved.then(heapless::Vec::new));
+                continue;
+            }
+            Either3::Second(Service::SELFTEST) => {
+                let mut record = [0u8; CARD_SIZE];
+                let mut eeprom = true;
+                let mut cards = true;
+                let known = CARDS.lock(|cards| cards.borrow().clone());
+                for (index, card) in known.iter().enumerate() {
+                    if i2c.write_read(EEPROM_ADDR, &card_addr(index).to_be_bytes(), &mut record).await.is_err() {
+                        eeprom = false;
+                        cards = false;
+                        break;
+                    }
+                    // a slot that didn't load fails too
+                    cards &= card.is_some() && Card::from_bytes(&record).ok() == *card;
+                }
+                SELF_TEST.signal(SelfTest { eeprom, cards, reader: mfrc.version().ok() });
+                continue;
+            }
+            Either3::Second(Service::EEPROMREAD { addr, len }) => {
+                let mut data = heapless::Vec::<u8, EEPROM_READ_MAX>::new();
+                let _ = data.resize(len as usize, 0);
+                let read = i2c.write_read(EEPROM_ADDR, &addr.to_be_bytes(), &mut data).await;
+                EEPROM_IO.signal(read.ok().map(|_| data));
+                continue;
+            }
+            Either3::Second(Service::EEPROMWRITE { addr, len, data }) => {
+                let written = write_eeprom(&mut i2c, addr, &data[..len as usize]).await;
+                EEPROM_IO.signal(written.ok().map(|_| heapless::Vec::new()));
+                continue;
+            }
+            Either3::Third(_) => {
+                if save_cards(&mut i2c).await.is_ok() {
+                    info!("Stored the cards after all");
+                    CARD_UNSAVED.store(false, Ordering::SeqCst);
+                    retry_at = None;
+                } else {
+                    retry_at = Some(Instant::now() + SAVE_RETRY);
+                }
+                continue;
+            }
+        };
 
-        match subs.next_message().await {
+        match message {
             wrm(State::ADDBALANCE) => {
                 let mut uid_bytes = [0u8; 4]; // Initialize uid_bytes with a default value
 
-                match mfrc.new_card_present() {
+                match detected.take().map(Ok).unwrap_or_else(|| mfrc.new_card_present()) {
                     Ok(atqa) => {
                         if let Ok(uid) = mfrc.select(&atqa) {
                             let uid_bytes = uid.as_bytes();
                             info!("Card UID: {:?}", uid_bytes);
 
-                            let is_known = known_uids.iter().any(|&(k, _)| k == uid_bytes);
-                            if is_known {
+                            let known = CARDS.lock(|cards| {
+                                cards.borrow().iter().flatten().find(|card| card.uid == uid_bytes).copied()
+                            });
+                            if let Some(card) = known {
                                 info!("Known card detected!");
-                                for &mut (ref known_uid, ref mut number) in &mut known_uids {
-                                    if known_uid == uid_bytes {
-                                        BALANCE.store(*number as i32, Ordering::SeqCst);
-                                        info!("Updated associated number: {}", BALANCE.load(Ordering::SeqCst));
-                                        break;
-                                    }
-                                }
+                                BALANCE.store(card.balance as i32, Ordering::SeqCst);
+                                CARD_IN.store(true, Ordering::SeqCst);
+                                AUDIT.lock(|audit| audit.borrow_mut().meters.cards_in += 1);
+                                info!("Updated associated number: {}", BALANCE.load(Ordering::SeqCst));
                             } else {
                                 info!("Unknown card detected!");
                             }
@@ -802,6 +1530,8 @@ async fn rfid_task(
             }
             wrm(State::CASHOUT) => {
                 let mut uid_bytes = [0u8; 4]; // Initialize uid_bytes with a default value
+                // a card from attract mode only ever inserts, it never cashes out
+                detected = None;
 
                 match mfrc.new_card_present() {
                     Ok(atqa) => {
@@ -809,28 +1539,26 @@ async fn rfid_task(
                             let uid_bytes = uid.as_bytes();
                             info!("Card UID: {:?}", uid_bytes);
 
-                            let is_known = known_uids.iter().any(|&(k, _)| k == uid_bytes);
-                            if is_known {
+                            let balance = BALANCE.load(Ordering::SeqCst);
+                            let known = CARDS.lock(|cards| {
+                                let mut cards = cards.borrow_mut();
+                                let index = cards.iter().position(|card| card.is_some_and(|card| card.uid == uid_bytes))?;
+                                let card = cards[index].as_mut()?;
+                                card.balance = balance as u32;
+                                Some((index, *card))
+                            });
+                            if let Some((index, card)) = known {
                                 info!("Known card detected!");
-                                for &mut (ref known_uid, ref mut number) in &mut known_uids {
-                                    if known_uid == uid_bytes {
-                                        *number = BALANCE.load(Ordering::SeqCst) as u32;
-
-                                        if uid_bytes == UID1 {
-                                            BALANCE1.store(BALANCE.load(Ordering::SeqCst), Ordering::SeqCst);
-                                            info!("UID1 matched and BALANCE1 updated.");
-                                        }
-                                        else if uid_bytes == UID2 {
-                                            BALANCE2.store(BALANCE.load(Ordering::SeqCst), Ordering::SeqCst);
-                                            info!("UID2 matched and BALANCE2 updated.");
-                                        }
-
-                                        info!("Updated associated number: {}", BALANCE.load(Ordering::SeqCst));
-                                        BALANCE.store(0, Ordering::SeqCst);
-
-                                        write_card_data(&mut i2c).await.unwrap();
-                                        break;
-                                    }
+                                info!("Updated associated number: {}", balance);
+                                BALANCE.store(0, Ordering::SeqCst);
+                                CARD_IN.store(false, Ordering::SeqCst);
+                                AUDIT.lock(|audit| audit.borrow_mut().meters.cash_outs += 1);
+
+                                // the balance stays in RAM and goes out with the next try
+                                if save_card(&mut i2c, index, &card).await.is_err() {
+                                    info!("Could not store the balance of card {}, trying again", index);
+                                    CARD_UNSAVED.store(true, Ordering::SeqCst);
+                                    retry_at = Some(Instant::now() + SAVE_RETRY);
                                 }
                             } else {
                                 info!("Unknown card detected!");
@@ -842,9 +1570,26 @@ async fn rfid_task(
                     }
                 }
             }
-            wrm(State::SPIN) => {}
-            wrm(State::WIN) => {}
+            wrm(State::ATTRACT) => {
+                // look for a card while attract mode runs, it ends attract mode
+                loop {
+                    match select(subs.next_message(), Timer::after_millis(200)).await {
+                        Either::First(wrm(State::ATTRACTEND)) => break,
+                        Either::First(_) => {}
+                        Either::Second(_) => {
+                            if let Ok(atqa) = mfrc.new_card_present() {
+                                info!("Card shown during attract mode");
+                                detected = Some(atqa);
+                                CARD_SHOWN.signal(());
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+            wrm(State::CUE(_)) => {}
             wrm(State::BET) => {}
+            wrm(State::ATTRACTEND) => {}
             Lagged(_) => {}
         }
 
@@ -853,34 +1598,295 @@ async fn rfid_task(
 }
 
 
+const CONSOLE_OUT: usize = 2048; // the longest answer, a full log dump
+const SERVICE_TIMEOUT: Duration = Duration::from_secs(2); // the rfid task doesn't take jobs during attract mode
+const SAVE_RETRY: Duration = Duration::from_secs(5); // a balance that didn't reach the EEPROM is written again this often
+
+static USB_CONFIG_DESCRIPTOR: ConstStaticCell<[u8; 256]> = ConstStaticCell::new([0; 256]);
+static USB_BOS_DESCRIPTOR: ConstStaticCell<[u8; 256]> = ConstStaticCell::new([0; 256]);
+static USB_CONTROL_BUF: ConstStaticCell<[u8; 64]> = ConstStaticCell::new([0; 64]);
+static CDC_STATE: StaticCell<CdcState> = StaticCell::new();
+
+type Console = CdcAcmClass<'static, UsbDriver<'static, USB>>;
+
+#[embassy_executor::task]
+async fn usb_task(mut usb: UsbDevice<'static, UsbDriver<'static, USB>>) -> ! {
+    usb.run().await
+}
+
+/// The operator console on the USB serial port, see `arcade_console` for the commands.
+/// Every answer ends with `OK` or `ERR <reason>`.
+#[embassy_executor::task]
+async fn console_task(mut class: Console, pin: Option<u32>) {
+    let mut auth = Auth::new(pin);
+    let mut packet = [0u8; 64];
+    let mut out: String<CONSOLE_OUT> = String::new();
+
+    loop {
+        class.wait_connection().await;
+        info!("Console connected");
+        let mut line = LineBuffer::<MAX_LINE>::new();
+
+        'connected: loop {
+            let Ok(count) = class.read_packet(&mut packet).await else {
+                break;
+            };
+            for &byte in &packet[..count] {
+                out.clear();
+                let ran = match line.push(byte) {
+                    None => continue,
+                    Some(Ok(text)) if text.trim().is_empty() => continue,
+                    Some(Ok(text)) => match parse(text) {
+                        Ok(command) => {
+                            info!("Console: {:?}", command);
+                            match run_command(command, &mut auth, &mut out).await {
+                                Ok(()) => {
+                                    let _ = write!(out, "OK\r\n");
+                                    Some(command)
+                                }
+                                Err(reason) => {
+                                    let _ = write!(out, "ERR {}\r\n", reason);
+                                    None
+                                }
+                            }
+                        }
+                        Err(error) => {
+                            let _ = write!(out, "ERR {}\r\n", error);
+                            None
+                        }
+                    },
+                    Some(Err(error)) => {
+                        let _ = write!(out, "ERR {}\r\n", error);
+                        None
+                    }
+                };
+                if send(&mut class, out.as_bytes()).await.is_err() {
+                    break 'connected;
+                }
+
+                if ran == Some(Command::Reboot) {
+                    info!("Rebooting from the console");
+                    Timer::after_millis(100).await; // let the OK go out
+                    cortex_m::peripheral::SCB::sys_reset();
+                }
+            }
+        }
+
+        info!("Console disconnected");
+        auth.logout();
+    }
+}
+
+/// Writes the output of `command` to `out`, `Err` with the reason if it failed.
+async fn run_command(command: Command, auth: &mut Auth, out: &mut String<CONSOLE_OUT>) -> Result<(), &'static str> {
+    let now = Instant::now().as_millis();
+    if !auth.allows(&command, now) {
+        return Err("login first");
+    }
+
+    match command {
+        Command::Help => {
+            for usage in [
+                "balance", "cards list", "card set <uid> <amount>", "meters", "log dump",
+                "paytable", "paytable set <line> <pays>", "rng seed", "selftest",
+                "eeprom read <addr> <len>", "eeprom write <addr> <hex>", "reboot", "pin set <pin>", "login <pin>", "logout",
+            ] {
+                let _ = write!(out, "{}\r\n", usage);
+            }
+        }
+        Command::Balance => {
+            let _ = write!(out, "balance {}\r\n", BALANCE.load(Ordering::SeqCst));
+            let card = if CARD_IN.load(Ordering::SeqCst) { "in" } else { "out" };
+            let _ = write!(out, "card {}\r\n", card);
+        }
+        Command::CardsList => {
+            CARDS.lock(|cards| {
+                for card in cards.borrow().iter().flatten() {
+                    let _ = write!(out, "{} {}\r\n", UidHex(card.uid), card.balance);
+                }
+            });
+        }
+        Command::CardSet { uid, amount } => {
+            let index = CARDS.lock(|cards| cards.borrow().iter().position(|card| card.is_some_and(|card| card.uid == uid)));
+            let Some(index) = index else {
+                return Err("unknown card");
+            };
+            // the balance in the machine is written back to the card on cashout
+            if CARD_IN.load(Ordering::SeqCst) {
+                return Err("a card is in the machine, cash out first");
+            }
+            let old = CARDS.lock(|cards| core::mem::replace(&mut cards.borrow_mut()[index], Some(Card { uid, balance: amount })));
+            if let Err(e) = eeprom_job(Service::SAVECARD(index)).await {
+                CARDS.lock(|cards| cards.borrow_mut()[index] = old);
+                return Err(e);
+            }
+            info!("Console set {} to {}", uid, amount);
+        }
+        Command::Meters => {
+            let meters = AUDIT.lock(|audit| audit.borrow().meters);
+            for (name, value) in meters.entries() {
+                let _ = write!(out, "{} {}\r\n", name, value);
+            }
+        }
+        Command::LogDump => AUDIT.lock(|audit| {
+            for game in audit.borrow().history.iter() {
+                let _ = write!(out, "{}\r\n", game);
+            }
+        }),
+        Command::Paytable => {
+            for (line, pay) in paytable::PAYTABLE.iter().enumerate() {
+                let combo = match pay.combo {
+                    paytable::Combo::Three(_) => "three",
+                    paytable::Combo::TwoAdjacent(_) => "two",
+                    paytable::Combo::Any(_) => "any",
+                };
+                let symbol = SYMBOLS[pay.combo.symbol()].name;
+                let _ = write!(out, "{} {} {} {}\r\n", line, combo, symbol, pay.pays());
+            }
+        }
+        Command::PaytableSet { line, pays } => {
+            let old = paytable::PAYTABLE.get(line as usize).map_or(0, |pay| pay.pays());
+            if pays > paytable::MAX_PAYS as u32 || !paytable::set_pays(line as usize, pays as i32) {
+                return Err("no such line or too much");
+            }
+            if let Err(e) = eeprom_job(Service::SAVEPAYTABLE).await {
+                paytable::set_pays(line as usize, old);
+                return Err(e);
+            }
+            info!("Console set paytable line {} to {}", line, pays);
+        }
+        Command::RngSeed => {
+            let _ = write!(out, "seed {}\r\n", RNG_SEED.lock(|cell| cell.get()));
+        }
+        Command::SelfTest => {
+            let report = service_job(Service::SELFTEST, &SELF_TEST).await?;
+            let verdict = |ok: bool| if ok { "ok" } else { "fail" };
+            let _ = write!(out, "eeprom {}\r\n", verdict(report.eeprom));
+            let _ = write!(out, "cards {}\r\n", verdict(report.cards));
+            match report.reader {
+                Some(version) => {
+                    let _ = write!(out, "reader ok 0x{:02x}\r\n", version);
+                }
+                None => {
+                    let _ = write!(out, "reader fail\r\n");
+                }
+            }
+            if !(report.eeprom && report.cards && report.reader.is_some()) {
+                return Err("self test failed");
+            }
+        }
+        Command::EepromRead { addr, len } => {
+            if addr as usize + len as usize > EEPROM_SIZE {
+                return Err("outside the EEPROM");
+            }
+            let data = eeprom_job(Service::EEPROMREAD { addr, len }).await?;
+            let _ = write!(out, "{}\r\n", Hex(&data));
+        }
+        Command::EepromWrite { addr, len, data } => {
+            if !within_page(addr, len as usize) {
+                return Err("outside the EEPROM or across a page");
+            }
+            // a cashout would write the card back over what was written
+            if CARD_IN.load(Ordering::SeqCst) {
+                return Err("a card is in the machine, cash out first");
+            }
+            eeprom_job(Service::EEPROMWRITE { addr, len, data }).await?;
+            info!("Console wrote {} bytes at {:x}, it is read at the next boot", len, addr);
+        }
+        Command::Reboot => {}
+        Command::PinSet { pin } => {
+            let mut data = [0u8; EEPROM_WRITE_MAX];
+            data[..PIN_SIZE].copy_from_slice(&pin_bytes(pin));
+            eeprom_job(Service::EEPROMWRITE { addr: PIN_ADDR, len: PIN_SIZE as u8, data }).await?;
+            auth.set_pin(pin);
+            info!("Console PIN changed");
+        }
+        Command::Login { pin } => match auth.login(pin, now) {
+            Ok(()) => {}
+            Err(AuthError::NoPin) => return Err("no pin set, use pin set first"),
+            Err(AuthError::WrongPin) => return Err("wrong pin"),
+            Err(AuthError::Locked { until_ms }) => {
+                let _ = write!(out, "locked for {} s\r\n", until_ms.saturating_sub(now).div_ceil(1000));
+                return Err("too many wrong pins");
+            }
+        },
+        Command::Logout => auth.logout(),
+    }
+    Ok(())
+}
+
+/// Hands an EEPROM read, write or save to the rfid task and waits for what it read.
+async fn eeprom_job(job: Service) -> Result<heapless::Vec<u8, EEPROM_READ_MAX>, &'static str> {
+    service_job(job, &EEPROM_IO).await?.ok_or("EEPROM error")
+}
+
+/// Hands `job` to the rfid task and waits for it to signal `done`. A job the task
+/// doesn't start within `SERVICE_TIMEOUT` is taken back off the channel, so a busy
+/// answer means it never runs. One the task already started is waited for.
+async fn service_job<T>(job: Service, done: &Signal<ThreadModeRawMutex, T>) -> Result<T, &'static str> {
+    const BUSY: &str = "the card reader task is busy, try again";
+    done.reset();
+    SERVICE.try_send(job).map_err(|_| BUSY)?;
+    match embassy_time::with_timeout(SERVICE_TIMEOUT, done.wait()).await {
+        Ok(result) => Ok(result),
+        Err(_) if SERVICE.try_receive().is_ok() => Err(BUSY),
+        // the I2C transfers of a job don't take long once it runs
+        Err(_) => Ok(done.wait().await),
+    }
+}
+
+/// Writes `bytes` in full packets, a transfer that ends on a full packet needs an empty one.
+async fn send(class: &mut Console, bytes: &[u8]) -> Result<(), embassy_usb::driver::EndpointError> {
+    let size = class.max_packet_size() as usize;
+    for chunk in bytes.chunks(size) {
+        class.write_packet(chunk).await?;
+    }
+    if bytes.len() % size == 0 {
+        class.write_packet(&[]).await?;
+    }
+    Ok(())
+}
+
+
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
     let p = embassy_rp::init(Default::default());
 
-    let yellow = Output::new(p.PIN_2, Level::Low);
-    let green = Output::new(p.PIN_3, Level::Low);
-    let blue = Output::new(p.PIN_4, Level::Low);
-    let red = Output::new(p.PIN_5, Level::Low);
+    // the cabinet strip, on PIO0 with its own DMA channel since SPI0 has the first two
+    let Pio { mut common, sm0, .. } = Pio::new(p.PIO0, Irqs);
+    let ws2812 = PioWs2812Program::new(&mut common);
+    let strip = PioWs2812::new(&mut common, sm0, p.DMA_CH2, p.PIN_2, &ws2812);
     let spin_button = Input::new(p.PIN_6, Pull::Up);
     let increase_bet = Input::new(p.PIN_7, Pull::Up);
     let max_bet = Input::new(p.PIN_8, Pull::Up);
     let cashout_button = Input::new(p.PIN_9, Pull::Up);
 
     let mut spiconfig1 = ConfigSpi::default();
-    spiconfig1.frequency = 32_000_000;
+    spiconfig1.frequency = MAIN_SPI_HZ;
 
     let miso1 = p.PIN_16;
     let mosi1 = p.PIN_19;
     let clk1 = p.PIN_18;
 
-    let mut spi = Spi::new_blocking(p.SPI0, clk1, mosi1, miso1, spiconfig1);
-    let spi_bus = NoopMutex::new(RefCell::new(spi));
+    // DMA backed, so a frame transfer awaits instead of blocking the executor
+    #[cfg(not(feature = "blocking-spi"))]
+    let spi = Spi::new(p.SPI0, clk1, mosi1, miso1, p.DMA_CH0, p.DMA_CH1, spiconfig1);
+    // the baseline for the latency logs, every transfer holds the executor until it is done
+    #[cfg(feature = "blocking-spi")]
+    let spi = BlockingAsync::new(Spi::new_blocking(p.SPI0, clk1, mosi1, miso1, spiconfig1));
+    let spi_bus = Mutex::new(spi);
     let spi_bus = SPI_BUS.init(spi_bus); // for sending to task
 
     let mut cs = Output::new(p.PIN_17, Level::High);
     let mut dc = Output::new(p.PIN_14, Level::Low);
     let mut reset = Output::new(p.PIN_15, Level::High);
 
+    // secondary display, DC and RST move to pins that are free here
+    // (PIN_21 and PIN_22 from project.rs are the RFID reset and the buzzer here)
+    let side_cs = Output::new(p.PIN_20, Level::High);
+    let side_dc = Output::new(p.PIN_28, Level::Low);
+    let side_reset = Output::new(p.PIN_3, Level::High);
+
 
     //rfid
     let miso2 = p.PIN_12;
@@ -907,15 +1913,61 @@ async fn main(spawner: Spawner) {
     let scl3 = p.PIN_27;
     let mut i2c = I2c::new_async(p.I2C1, scl3, sda3, Irqs, I2cConfig::default());
 
-    // write_card_data(&mut i2c).await.unwrap();
+    if load_cards(&mut i2c).await.is_err() {
+        info!("Could not read the cards");
+    }
+    let settings = load_config(&mut i2c).await;
+    load_paytable(&mut i2c).await;
+    let pin = load_pin(&mut i2c).await;
+
+    // ILI9341 LED pin, PWM2 B on the pin the red LED had before the strip
+    let backlight = Pwm::new_output_b(p.PWM_SLICE2, p.PIN_5, ConfigPwm::default());
+
+    // link to the secondary display Pico
+    let mut uart_config = UartConfig::default();
+    uart_config.baudrate = arcade_link::BAUD_RATE;
+    let uart = BufferedUart::new(
+        p.UART0,
+        p.PIN_0,
+        p.PIN_1,
+        Irqs,
+        LINK_TX_BUFFER.take(),
+        LINK_RX_BUFFER.take(),
+        uart_config,
+    );
 
-    load_card_data(&mut i2c).await.unwrap();
 
+    spawner.spawn(display_task(spi_bus, cs, dc, reset, backlight, settings)).unwrap();
+    spawner.spawn(side_display_task(spi_bus, side_cs, side_dc, side_reset)).unwrap();
+    spawner.spawn(input_task(spin_button, increase_bet, max_bet, cashout_button)).unwrap();
+    spawner.spawn(latency_task()).unwrap();
+    spawner.spawn(strip_task(strip)).unwrap();
+    spawner.spawn(buzzer_task(settings)).unwrap();
 
-    spawner.spawn(display_task(spi_bus, cs, dc, reset, increase_bet, max_bet, spin_button, cashout_button)).unwrap();
-    spawner.spawn(led_task(yellow, green, blue, red)).unwrap();
-    spawner.spawn(buzzer_task(Pwm::new_output_a(p.PWM_SLICE3, p.PIN_22, ConfigPwm::default()))).unwrap();
+    interrupt::SWI_IRQ_1.set_priority(Priority::P1);
+    let audio_spawner = AUDIO_EXECUTOR.start(interrupt::SWI_IRQ_1);
+    audio_spawner.spawn(audio_task(Pwm::new_output_a(p.PWM_SLICE3, p.PIN_22, ConfigPwm::default()))).unwrap();
     spawner.spawn(rfid_task(spi2, cs2, i2c)).unwrap();
+    spawner.spawn(link_task(uart)).unwrap();
+
+    // the operator console, a CDC ACM serial port on the Pico's USB
+    let mut usb_config = embassy_usb::Config::new(0xc0de, 0xcafe);
+    usb_config.manufacturer = Some("Arcade Game");
+    usb_config.product = Some("Arcade Game console");
+    usb_config.serial_number = Some("00000001");
+    usb_config.max_power = 100;
+    usb_config.max_packet_size_0 = 64;
+    let mut builder = embassy_usb::Builder::new(
+        UsbDriver::new(p.USB, Irqs),
+        usb_config,
+        USB_CONFIG_DESCRIPTOR.take(),
+        USB_BOS_DESCRIPTOR.take(),
+        &mut [],
+        USB_CONTROL_BUF.take(),
+    );
+    let console = CdcAcmClass::new(&mut builder, CDC_STATE.init(CdcState::new()), 64);
+    spawner.spawn(usb_task(builder.build())).unwrap();
+    spawner.spawn(console_task(console, pin)).unwrap();
 
 
 } 
@@ -0,0 +1,130 @@
+#![no_std]
+#![no_main]
+
+//! Compares drawing the compressed symbols with drawing the raw RGB565 ones.
+//!
+//! Every symbol is drawn `ROUNDS` times into a RAM framebuffer, first from the raw
+//! `ImageRawLE` data and then decoded from the compressed data, and the average time
+//! per draw is logged. A second pass also sends each draw to the panel, which is what
+//! the game does for every reel frame.
+
+use arcade_game::display::{Controller, Ili9341Async};
+use arcade_game::framebuffer::FrameBuffer;
+use arcade_game::symbols::{RAW_SYMBOLS, SYMBOLS, SYMBOL_SIZE, SYMBOL_WIDTH};
+use defmt::info;
+use embassy_embedded_hal::shared_bus::asynch::spi::SpiDevice;
+use embassy_executor::Spawner;
+use embassy_rp::{
+    gpio::{Level, Output},
+    spi::{Config as ConfigSpi, Spi},
+};
+use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex};
+use embassy_time::Instant;
+use embedded_graphics::{
+    image::{Image, ImageDrawable, ImageRawLE},
+    pixelcolor::Rgb565,
+    prelude::*,
+};
+use ili9341::{DisplaySize240x320, ModeState, Orientation};
+use static_cell::ConstStaticCell;
+use {defmt_rtt as _, panic_probe as _};
+
+const ROUNDS: u64 = 50;
+
+static FRAME: ConstStaticCell<[u16; 320 * 240]> = ConstStaticCell::new([0; 320 * 240]);
+
+const POSITION: Point = Point::new(120, 60);
+
+/// Average microseconds for drawing `image` into the framebuffer.
+fn draw_time<I>(image: &I, fb: &mut FrameBuffer) -> u64
+where
+    I: ImageDrawable<Color = Rgb565>,
+{
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        Image::new(image, POSITION).draw(fb).unwrap();
+    }
+    (Instant::now() - start).as_micros() / ROUNDS
+}
+
+/// Average microseconds for drawing `image` and sending it to the panel.
+async fn flush_time<I, SPI, DC>(
+    image: &I,
+    fb: &mut FrameBuffer<'_>,
+    display: &mut Ili9341Async<SPI, DC>,
+) -> u64
+where
+    I: ImageDrawable<Color = Rgb565>,
+    SPI: embedded_hal_async::spi::SpiDevice,
+    DC: embedded_hal_1::digital::OutputPin,
+{
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        Image::new(image, POSITION).draw(fb).unwrap();
+        fb.flush(display).await.unwrap();
+    }
+    (Instant::now() - start).as_micros() / ROUNDS
+}
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_rp::init(Default::default());
+
+    let mut config = ConfigSpi::default();
+    config.frequency = 32_000_000;
+    let spi = Spi::new(
+        p.SPI0, p.PIN_18, p.PIN_19, p.PIN_16, p.DMA_CH0, p.DMA_CH1, config,
+    );
+    let spi_bus: Mutex<ThreadModeRawMutex, _> = Mutex::new(spi);
+
+    // the same pins as image.rs, the backlight just stays on at full brightness
+    let cs = Output::new(p.PIN_17, Level::High);
+    let dc = Output::new(p.PIN_14, Level::Low);
+    let mut reset = Output::new(p.PIN_15, Level::High);
+    let _backlight = Output::new(p.PIN_5, Level::High);
+
+    let mut display = Ili9341Async::new(
+        SpiDevice::new(&spi_bus, cs),
+        dc,
+        &mut reset,
+        Controller::Ili9341,
+        Orientation::LandscapeFlipped,
+        DisplaySize240x320,
+    )
+    .await
+    .unwrap();
+    display.idle_mode(ModeState::Off).await.unwrap();
+
+    let mut fb = FrameBuffer::new(FRAME.take(), display.size());
+    fb.clear(Rgb565::BLACK).unwrap();
+    fb.flush(&mut display).await.unwrap();
+
+    let (mut raw_flash, mut compressed_flash) = (0, 0);
+    for (symbol, raw) in SYMBOLS.iter().zip(RAW_SYMBOLS.iter()) {
+        let raw_image: ImageRawLE<Rgb565> = ImageRawLE::new(raw, SYMBOL_WIDTH);
+        let compressed = symbol.image();
+        assert_eq!(raw_image.size(), SYMBOL_SIZE);
+        raw_flash += raw.len();
+        compressed_flash += compressed.len();
+
+        let raw_ram = draw_time(&raw_image, &mut fb);
+        let compressed_ram = draw_time(&compressed, &mut fb);
+        let raw_panel = flush_time(&raw_image, &mut fb, &mut display).await;
+        let compressed_panel = flush_time(&compressed, &mut fb, &mut display).await;
+
+        info!(
+            "{}: {} -> {} bytes, draw {} us raw / {} us compressed, draw and flush {} us raw / {} us compressed",
+            symbol.name,
+            raw.len(),
+            compressed.len(),
+            raw_ram,
+            compressed_ram,
+            raw_panel,
+            compressed_panel
+        );
+    }
+    info!(
+        "All symbols: {} bytes raw, {} bytes compressed",
+        raw_flash, compressed_flash
+    );
+}
@@ -0,0 +1,155 @@
+//! Compressed RGB565 images, decoded while they are drawn.
+//!
+//! `build.rs` compresses the symbols with a QOI style encoder for RGB565. Every
+//! op is one byte, followed by two for a literal:
+//!
+//! | byte          | op      |                                                      |
+//! |---------------|---------|------------------------------------------------------|
+//! | `00iiiiii`    | index   | the color at `i` in the table of recently seen colors |
+//! | `01rrggbb`    | diff    | previous color plus -2..=1 on each field, biased by 2 |
+//! | `0x80..=0xfe` | run     | previous color repeated `byte - 0x7f` times           |
+//! | `0xff`        | literal | the next two bytes, little endian                     |
+//!
+//! Decoding starts from black with an all black table and puts every pixel it
+//! produces in the table at `hash(color)`. Pixels come out row by row, so the
+//! decoder feeds `DrawTarget::fill_contiguous` without a buffer.
+
+use embedded_graphics::{
+    image::ImageDrawable,
+    pixelcolor::{raw::RawU16, Rgb565},
+    prelude::*,
+    primitives::Rectangle,
+};
+
+const INDEX: u8 = 0x00;
+const DIFF: u8 = 0x40;
+const RUN: u8 = 0x80;
+const LITERAL: u8 = 0xff;
+const MASK: u8 = 0xc0;
+
+/// Slot of `color` in the table of recently seen colors.
+fn hash(color: u16) -> usize {
+    let (r, g, b) = fields(color);
+    (r as usize * 3 + g as usize * 5 + b as usize * 7) % 64
+}
+
+/// The 5, 6 and 5 bit fields of an RGB565 value.
+fn fields(color: u16) -> (u16, u16, u16) {
+    (color >> 11, (color >> 5) & 0x3f, color & 0x1f)
+}
+
+/// A compressed image in flash.
+#[derive(Clone, Copy)]
+pub struct CompressedImage<'a> {
+    data: &'a [u8],
+    size: Size,
+}
+
+impl<'a> CompressedImage<'a> {
+    pub const fn new(data: &'a [u8], size: Size) -> Self {
+        CompressedImage { data, size }
+    }
+
+    /// Compressed size in bytes.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn pixels(&self) -> Decoder<'a> {
+        Decoder::new(self.data)
+    }
+}
+
+impl OriginDimensions for CompressedImage<'_> {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl ImageDrawable for CompressedImage<'_> {
+    type Color = Rgb565;
+
+    fn draw<D>(&self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        target.fill_contiguous(&self.bounding_box(), self.pixels())
+    }
+
+    fn draw_sub_image<D>(&self, target: &mut D, area: &Rectangle) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        // the stream can't seek, decode everything and let the clipping drop the rest
+        let mut translated = target.translated(-area.top_left);
+        self.draw(&mut translated.clipped(&Rectangle::new(Point::zero(), area.size)))
+    }
+}
+
+/// Streams the pixels of a compressed image. Stops early on truncated data.
+pub struct Decoder<'a> {
+    data: &'a [u8],
+    previous: u16,
+    run: u8,
+    index: [u16; 64],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Decoder {
+            data,
+            previous: 0,
+            run: 0,
+            index: [0; 64],
+        }
+    }
+
+    fn next_raw(&mut self) -> Option<u16> {
+        if self.run > 0 {
+            self.run -= 1;
+            return Some(self.previous);
+        }
+
+        let (&op, rest) = self.data.split_first()?;
+        self.data = rest;
+
+        let color = match op {
+            LITERAL => {
+                let (bytes, rest) = self.data.split_first_chunk::<2>()?;
+                self.data = rest;
+                u16::from_le_bytes(*bytes)
+            }
+            _ if op & MASK == INDEX => self.index[op as usize],
+            _ if op & MASK == DIFF => {
+                let (r, g, b) = fields(self.previous);
+                let dr = u16::from((op >> 4) & 3).wrapping_sub(2);
+                let dg = u16::from((op >> 2) & 3).wrapping_sub(2);
+                let db = u16::from(op & 3).wrapping_sub(2);
+                (r.wrapping_add(dr) & 0x1f) << 11
+                    | (g.wrapping_add(dg) & 0x3f) << 5
+                    | (b.wrapping_add(db) & 0x1f)
+            }
+            _ => {
+                // the first pixel of the run is returned right away
+                self.run = op - RUN;
+                self.previous
+            }
+        };
+
+        self.index[hash(color)] = color;
+        self.previous = color;
+        Some(color)
+    }
+}
+
+impl Iterator for Decoder<'_> {
+    type Item = Rgb565;
+
+    fn next(&mut self) -> Option<Rgb565> {
+        self.next_raw().map(|raw| Rgb565::from(RawU16::new(raw)))
+    }
+}
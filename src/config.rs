@@ -0,0 +1,74 @@
+//! Settings that survive a power cycle.
+//!
+//! They live in the last page of the AT24C256, away from the cards at the start of
+//! it. The record starts with a magic and a version and ends with a CRC, so a blank
+//! or half written EEPROM reads as "no settings" and the defaults are used.
+
+use arcade_eeprom::{CONFIG_MAGIC as MAGIC, CONFIG_VERSION as VERSION, check_record, crc16};
+
+/// Start of the last 64 byte page, see `arcade_eeprom` for the whole layout.
+pub use arcade_eeprom::{CONFIG_ADDR, CONFIG_SIZE};
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub struct Config {
+    /// Backlight while somebody plays, in percent.
+    pub brightness: u8,
+    /// Backlight after `dim_after_s` without input, in percent.
+    pub dim_brightness: u8,
+    pub dim_after_s: u16,
+    /// The panel sleeps and the backlight goes off after this long without input.
+    pub sleep_after_s: u16,
+    /// Buzzer loudness in percent.
+    pub volume: u8,
+    /// No sound at all, `volume` is kept for when it is turned back on.
+    pub muted: bool,
+    /// Attract mode starts after this long without a button press or a card, 0 turns
+    /// it off.
+    pub attract_after_s: u16,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            brightness: 100,
+            dim_brightness: 30,
+            dim_after_s: 300,
+            sleep_after_s: 900,
+            volume: 100,
+            muted: false,
+            attract_after_s: 60,
+        }
+    }
+}
+
+impl Config {
+    pub fn to_bytes(&self) -> [u8; CONFIG_SIZE] {
+        let mut bytes = [0u8; CONFIG_SIZE];
+        bytes[0..2].copy_from_slice(&MAGIC);
+        bytes[2] = VERSION;
+        bytes[3] = self.brightness;
+        bytes[4] = self.dim_brightness;
+        bytes[5..7].copy_from_slice(&self.dim_after_s.to_be_bytes());
+        bytes[7..9].copy_from_slice(&self.sleep_after_s.to_be_bytes());
+        bytes[9] = self.volume;
+        bytes[10] = self.muted as u8;
+        bytes[11..13].copy_from_slice(&self.attract_after_s.to_be_bytes());
+        let crc = crc16(&bytes[..13]);
+        bytes[13..15].copy_from_slice(&crc.to_be_bytes());
+        bytes
+    }
+
+    /// `None` for anything that isn't a record written by `to_bytes`.
+    pub fn from_bytes(bytes: &[u8; CONFIG_SIZE]) -> Option<Self> {
+        check_record(bytes, MAGIC, VERSION).ok()?;
+        Some(Config {
+            brightness: bytes[3].min(100),
+            dim_brightness: bytes[4].min(100),
+            dim_after_s: u16::from_be_bytes([bytes[5], bytes[6]]),
+            sleep_after_s: u16::from_be_bytes([bytes[7], bytes[8]]),
+            volume: bytes[9].min(100),
+            muted: bytes[10] != 0,
+            attract_after_s: u16::from_be_bytes([bytes[11], bytes[12]]),
+        })
+    }
+}
@@ -0,0 +1,353 @@
+//! Async ILI9341 driver.
+//!
+//! The `ili9341` crate only talks to blocking SPI, so every frame used to stall the
+//! whole executor. This driver sends pixels through an async `SpiDevice` in DMA sized
+//! chunks, which lets the buttons, LEDs and buzzer keep running while the panel is fed.
+//! The 320x480 ILI9488 speaks the same command set and is supported too.
+
+use embassy_time::Timer;
+use embedded_graphics::{
+    pixelcolor::{IntoStorage, Rgb565, RgbColor},
+    prelude::*,
+    primitives::Rectangle,
+};
+use embedded_hal_1::digital::OutputPin;
+use embedded_hal_async::spi::SpiDevice;
+use ili9341::{DisplaySize, FrameRate, FrameRateClockDivision, ModeState, Orientation};
+
+const CHUNK_SIZE: usize = 1020; // bytes per SPI transfer, whole pixels for 2 and 3 byte formats
+
+/// The controller behind the SPI pins.
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum Controller {
+    Ili9341,
+    /// Only accepts 18 bit pixels over SPI, every pixel is sent as 3 bytes.
+    Ili9488,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, defmt::Format)]
+pub enum Error {
+    Spi,
+    Pin,
+}
+
+/// A panel that `FrameBuffer::flush` can send pixels to.
+#[allow(async_fn_in_trait)]
+pub trait FlushTarget {
+    /// Streams `colors` row by row into `area`. The area has to be inside the screen.
+    async fn set_pixels<I>(&mut self, area: Rectangle, colors: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = Rgb565>;
+}
+
+#[derive(Clone, Copy)]
+#[repr(u8)]
+enum Command {
+    SoftwareReset = 0x01,
+    SleepModeOn = 0x10,
+    SleepModeOff = 0x11,
+    InvertOff = 0x20,
+    InvertOn = 0x21,
+    GammaSet = 0x26,
+    DisplayOff = 0x28,
+    DisplayOn = 0x29,
+    ColumnAddressSet = 0x2a,
+    PageAddressSet = 0x2b,
+    MemoryWrite = 0x2c,
+    MemoryAccessControl = 0x36,
+    IdleModeOff = 0x38,
+    IdleModeOn = 0x39,
+    PixelFormatSet = 0x3a,
+    NormalModeFrameRate = 0xb1,
+    DisplayInversionControl = 0xb4,
+    DisplayFunctionControl = 0xb6,
+    PowerControl1 = 0xc0,
+    PowerControl2 = 0xc1,
+    VcomControl1 = 0xc5,
+    VcomControl2 = 0xc7,
+    AdjustControl3 = 0xf7,
+}
+
+pub struct Ili9341Async<SPI, DC> {
+    spi: SPI,
+    dc: DC,
+    controller: Controller,
+    width: u16,
+    height: u16,
+    landscape: bool,
+    buffer: [u8; CHUNK_SIZE],
+}
+
+impl<SPI, DC> Ili9341Async<SPI, DC>
+where
+    SPI: SpiDevice,
+    DC: OutputPin,
+{
+    /// Resets the panel and runs the init sequence for the controller, the ILI9341 one
+    /// is the same as in the `ili9341` crate.
+    pub async fn new<RST, SIZE>(
+        spi: SPI,
+        dc: DC,
+        reset: &mut RST,
+        controller: Controller,
+        orientation: Orientation,
+        _size: SIZE,
+    ) -> Result<Self, Error>
+    where
+        RST: OutputPin,
+        SIZE: DisplaySize,
+    {
+        let mut display = Ili9341Async {
+            spi,
+            dc,
+            controller,
+            width: SIZE::WIDTH as u16,
+            height: SIZE::HEIGHT as u16,
+            landscape: false,
+            buffer: [0; CHUNK_SIZE],
+        };
+
+        reset.set_high().map_err(|_| Error::Pin)?;
+        Timer::after_millis(1).await;
+        reset.set_low().map_err(|_| Error::Pin)?;
+        Timer::after_millis(10).await;
+        reset.set_high().map_err(|_| Error::Pin)?;
+        Timer::after_millis(120).await;
+
+        display.command(Command::SoftwareReset, &[]).await?;
+        Timer::after_millis(120).await;
+
+        display.command(Command::DisplayOff, &[]).await?;
+        match controller {
+            Controller::Ili9341 => {
+                display.command(Command::PowerControl1, &[0x23]).await?;
+                display.command(Command::PowerControl2, &[0x10]).await?;
+                display
+                    .command(Command::VcomControl1, &[0x2b, 0x2b])
+                    .await?;
+                display.command(Command::VcomControl2, &[0xc0]).await?;
+                display.command(Command::PixelFormatSet, &[0x55]).await?; // 16 bit RGB565
+                display
+                    .command(Command::NormalModeFrameRate, &[0x00, 0x1b])
+                    .await?;
+                display.command(Command::GammaSet, &[0x01]).await?;
+            }
+            Controller::Ili9488 => {
+                display
+                    .command(Command::PowerControl1, &[0x17, 0x15])
+                    .await?;
+                display.command(Command::PowerControl2, &[0x41]).await?;
+                display
+                    .command(Command::VcomControl1, &[0x00, 0x12, 0x80])
+                    .await?;
+                display.command(Command::PixelFormatSet, &[0x66]).await?; // 18 bit RGB666
+                display
+                    .command(Command::NormalModeFrameRate, &[0xa0])
+                    .await?;
+                display
+                    .command(Command::DisplayInversionControl, &[0x02])
+                    .await?;
+                display
+                    .command(Command::DisplayFunctionControl, &[0x02, 0x02, 0x3b])
+                    .await?;
+                display
+                    .command(Command::AdjustControl3, &[0xa9, 0x51, 0x2c, 0x82])
+                    .await?;
+            }
+        }
+        display.set_orientation(orientation).await?;
+
+        display.command(Command::SleepModeOff, &[]).await?;
+        Timer::after_millis(120).await;
+        display.command(Command::DisplayOn, &[]).await?;
+
+        Ok(display)
+    }
+
+    async fn command(&mut self, command: Command, args: &[u8]) -> Result<(), Error> {
+        self.dc.set_low().map_err(|_| Error::Pin)?;
+        self.spi
+            .write(&[command as u8])
+            .await
+            .map_err(|_| Error::Spi)?;
+
+        if !args.is_empty() {
+            self.dc.set_high().map_err(|_| Error::Pin)?;
+            self.spi.write(args).await.map_err(|_| Error::Spi)?;
+        }
+        Ok(())
+    }
+
+    pub async fn set_orientation(&mut self, orientation: Orientation) -> Result<(), Error> {
+        let (madctl, landscape) = match orientation {
+            Orientation::Portrait => (0x40 | 0x08, false),
+            Orientation::Landscape => (0x20 | 0x08, true),
+            Orientation::PortraitFlipped => (0x80 | 0x08, false),
+            Orientation::LandscapeFlipped => (0x40 | 0x80 | 0x20 | 0x08, true),
+        };
+        self.command(Command::MemoryAccessControl, &[madctl])
+            .await?;
+
+        if self.landscape != landscape {
+            core::mem::swap(&mut self.width, &mut self.height);
+            self.landscape = landscape;
+        }
+        Ok(())
+    }
+
+    pub async fn idle_mode(&mut self, mode: ModeState) -> Result<(), Error> {
+        match mode {
+            ModeState::On => self.command(Command::IdleModeOn, &[]).await,
+            ModeState::Off => self.command(Command::IdleModeOff, &[]).await,
+        }
+    }
+
+    pub async fn invert_mode(&mut self, mode: ModeState) -> Result<(), Error> {
+        match mode {
+            ModeState::On => self.command(Command::InvertOn, &[]).await,
+            ModeState::Off => self.command(Command::InvertOff, &[]).await,
+        }
+    }
+
+    pub async fn sleep_mode(&mut self, mode: ModeState) -> Result<(), Error> {
+        match mode {
+            ModeState::On => self.command(Command::SleepModeOn, &[]).await?,
+            ModeState::Off => self.command(Command::SleepModeOff, &[]).await?,
+        }
+        // the panel ignores commands for a while after leaving or entering sleep
+        Timer::after_millis(120).await;
+        Ok(())
+    }
+
+    pub async fn normal_mode_frame_rate(
+        &mut self,
+        clk_div: FrameRateClockDivision,
+        frame_rate: FrameRate,
+    ) -> Result<(), Error> {
+        self.command(
+            Command::NormalModeFrameRate,
+            &[clk_div as u8, frame_rate as u8],
+        )
+        .await
+    }
+
+    pub fn size(&self) -> Size {
+        Size::new(self.width as u32, self.height as u32)
+    }
+
+    pub fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(Point::zero(), self.size())
+    }
+
+    async fn set_window(&mut self, area: &Rectangle) -> Result<(), Error> {
+        let x0 = area.top_left.x as u16;
+        let y0 = area.top_left.y as u16;
+        let x1 = x0 + area.size.width as u16 - 1;
+        let y1 = y0 + area.size.height as u16 - 1;
+
+        self.command(
+            Command::ColumnAddressSet,
+            &[(x0 >> 8) as u8, x0 as u8, (x1 >> 8) as u8, x1 as u8],
+        )
+        .await?;
+        self.command(
+            Command::PageAddressSet,
+            &[(y0 >> 8) as u8, y0 as u8, (y1 >> 8) as u8, y1 as u8],
+        )
+        .await?;
+        self.command(Command::MemoryWrite, &[]).await?;
+        self.dc.set_high().map_err(|_| Error::Pin)
+    }
+
+    fn bytes_per_pixel(&self) -> usize {
+        match self.controller {
+            Controller::Ili9341 => 2,
+            Controller::Ili9488 => 3,
+        }
+    }
+
+    fn encode(&mut self, index: usize, color: Rgb565) {
+        match self.controller {
+            Controller::Ili9341 => {
+                self.buffer[2 * index..2 * index + 2]
+                    .copy_from_slice(&color.into_storage().to_be_bytes());
+            }
+            Controller::Ili9488 => {
+                // the panel uses the top 6 bits of every byte
+                self.buffer[3 * index] = color.r() << 3;
+                self.buffer[3 * index + 1] = color.g() << 2;
+                self.buffer[3 * index + 2] = color.b() << 3;
+            }
+        }
+    }
+
+    /// Streams `colors` row by row into `area`. The area has to be inside the screen.
+    pub async fn set_pixels<I>(&mut self, area: Rectangle, colors: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = Rgb565>,
+    {
+        if area.is_zero_sized() {
+            return Ok(());
+        }
+        self.set_window(&area).await?;
+
+        let bytes_per_pixel = self.bytes_per_pixel();
+        let mut colors = colors.into_iter();
+        let mut remaining = area.size.width as usize * area.size.height as usize;
+
+        while remaining > 0 {
+            let count = remaining.min(CHUNK_SIZE / bytes_per_pixel);
+            for i in 0..count {
+                let color = colors.next().unwrap_or(Rgb565::BLACK);
+                self.encode(i, color);
+            }
+            self.spi
+                .write(&self.buffer[..bytes_per_pixel * count])
+                .await
+                .map_err(|_| Error::Spi)?;
+            remaining -= count;
+        }
+        Ok(())
+    }
+
+    pub async fn fill_rect(&mut self, area: Rectangle, color: Rgb565) -> Result<(), Error> {
+        let area = area.intersection(&self.bounding_box());
+        if area.is_zero_sized() {
+            return Ok(());
+        }
+        self.set_window(&area).await?;
+
+        let bytes_per_pixel = self.bytes_per_pixel();
+        for i in 0..CHUNK_SIZE / bytes_per_pixel {
+            self.encode(i, color);
+        }
+
+        let mut remaining = bytes_per_pixel * area.size.width as usize * area.size.height as usize;
+        while remaining > 0 {
+            let count = remaining.min(CHUNK_SIZE);
+            self.spi
+                .write(&self.buffer[..count])
+                .await
+                .map_err(|_| Error::Spi)?;
+            remaining -= count;
+        }
+        Ok(())
+    }
+
+    pub async fn clear(&mut self, color: Rgb565) -> Result<(), Error> {
+        self.fill_rect(self.bounding_box(), color).await
+    }
+}
+
+impl<SPI, DC> FlushTarget for Ili9341Async<SPI, DC>
+where
+    SPI: SpiDevice,
+    DC: OutputPin,
+{
+    async fn set_pixels<I>(&mut self, area: Rectangle, colors: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = Rgb565>,
+    {
+        Ili9341Async::set_pixels(self, area, colors).await
+    }
+}
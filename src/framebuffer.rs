@@ -0,0 +1,189 @@
+//! Full-frame RAM framebuffer.
+//!
+//! Everything is drawn into RAM first with the usual `embedded-graphics` calls. The
+//! framebuffer remembers which rectangles changed and `flush` sends only those to the
+//! panel, so a redraw never shows up half erased.
+
+use core::convert::Infallible;
+
+use embedded_graphics::{
+    pixelcolor::{IntoStorage, Rgb565, raw::RawU16},
+    prelude::*,
+    primitives::Rectangle,
+};
+use heapless::Vec;
+
+use crate::display::{Error, FlushTarget};
+
+const MAX_DIRTY: usize = 16;
+
+pub struct FrameBuffer<'a> {
+    pixels: &'a mut [u16],
+    size: Size,
+    dirty: Vec<Rectangle, MAX_DIRTY>,
+}
+
+impl<'a> FrameBuffer<'a> {
+    /// `pixels` holds one RGB565 value per pixel, row by row.
+    pub fn new(pixels: &'a mut [u16], size: Size) -> Self {
+        assert!(pixels.len() >= size.width as usize * size.height as usize);
+
+        FrameBuffer {
+            pixels,
+            size,
+            dirty: Vec::new(),
+        }
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    /// Forces the whole screen to be sent on the next flush.
+    pub fn invalidate(&mut self) {
+        self.dirty.clear();
+        self.dirty.push(self.bounding_box()).ok();
+    }
+
+    pub fn mark_dirty(&mut self, area: Rectangle) {
+        let area = area.intersection(&self.bounding_box());
+        if area.is_zero_sized() {
+            return;
+        }
+
+        // grow a region that already touches this one, as long as that doesn't add pixels
+        // nobody changed, otherwise start a new region
+        for dirty in self.dirty.iter_mut() {
+            let merged = envelope(dirty, &area);
+            if pixel_count(&merged) <= pixel_count(dirty) + pixel_count(&area) {
+                *dirty = merged;
+                return;
+            }
+        }
+
+        if let Err(area) = self.dirty.push(area) {
+            // out of slots, send one region that covers everything
+            let merged = self.dirty.iter().fold(area, |all, dirty| envelope(&all, dirty));
+            self.dirty.clear();
+            self.dirty.push(merged).ok();
+        }
+    }
+
+    /// Sends the changed regions to the panel.
+    pub async fn flush<D>(&mut self, display: &mut D) -> Result<(), Error>
+    where
+        D: FlushTarget,
+    {
+        let width = self.size.width as usize;
+
+        while let Some(area) = self.dirty.pop() {
+            let x = area.top_left.x as usize;
+            let y = area.top_left.y as usize;
+            let w = area.size.width as usize;
+            let h = area.size.height as usize;
+
+            let colors = self.pixels[y * width..(y + h) * width]
+                .chunks_exact(width)
+                .flat_map(|row| row[x..x + w].iter())
+                .map(|&raw| Rgb565::from(RawU16::new(raw)));
+
+            display.set_pixels(area, colors).await?;
+        }
+        Ok(())
+    }
+
+    fn index(&self, point: Point) -> Option<usize> {
+        if point.x < 0 || point.y < 0 {
+            return None;
+        }
+        let (x, y) = (point.x as u32, point.y as u32);
+        if x >= self.size.width || y >= self.size.height {
+            return None;
+        }
+        Some(y as usize * self.size.width as usize + x as usize)
+    }
+}
+
+impl OriginDimensions for FrameBuffer<'_> {
+    fn size(&self) -> Size {
+        self.size
+    }
+}
+
+impl DrawTarget for FrameBuffer<'_> {
+    type Color = Rgb565;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        // track the bounding box of what was drawn instead of marking every pixel
+        let mut min = Point::new(i32::MAX, i32::MAX);
+        let mut max = Point::new(i32::MIN, i32::MIN);
+
+        for Pixel(point, color) in pixels {
+            if let Some(index) = self.index(point) {
+                self.pixels[index] = color.into_storage();
+                min = min.component_min(point);
+                max = max.component_max(point);
+            }
+        }
+
+        if min.x <= max.x {
+            self.mark_dirty(Rectangle::with_corners(min, max));
+        }
+        Ok(())
+    }
+
+    fn fill_contiguous<I>(&mut self, area: &Rectangle, colors: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Self::Color>,
+    {
+        let drawable = area.intersection(&self.bounding_box());
+        if drawable.is_zero_sized() {
+            return Ok(());
+        }
+
+        let width = self.size.width as usize;
+        for (point, color) in area.points().zip(colors) {
+            if drawable.contains(point) {
+                self.pixels[point.y as usize * width + point.x as usize] = color.into_storage();
+            }
+        }
+
+        self.mark_dirty(drawable);
+        Ok(())
+    }
+
+    fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+        let area = area.intersection(&self.bounding_box());
+        if area.is_zero_sized() {
+            return Ok(());
+        }
+
+        let width = self.size.width as usize;
+        let x = area.top_left.x as usize;
+        let w = area.size.width as usize;
+        let raw = color.into_storage();
+
+        for y in area.rows() {
+            let start = y as usize * width + x;
+            self.pixels[start..start + w].fill(raw);
+        }
+
+        self.mark_dirty(area);
+        Ok(())
+    }
+}
+
+fn envelope(a: &Rectangle, b: &Rectangle) -> Rectangle {
+    let top_left = a.top_left.component_min(b.top_left);
+    let bottom_right = (a.top_left + a.size).component_max(b.top_left + b.size);
+
+    Rectangle::with_corners(top_left, bottom_right - Point::new(1, 1))
+}
+
+fn pixel_count(area: &Rectangle) -> u32 {
+    area.size.width * area.size.height
+}
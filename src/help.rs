@@ -0,0 +1,180 @@
+//! The paytable pages players can bring up on the main screen.
+//!
+//! Nothing on them is drawn by hand. Every row comes from `paytable::PAYTABLE` with the
+//! images from the generated symbol table, so the pages always show what
+//! `paytable::evaluate` pays.
+
+use core::fmt::Write;
+
+use embedded_graphics::{
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::Rectangle,
+    text::{Alignment, Text},
+};
+use heapless::String;
+
+use crate::compressed::CompressedImage;
+use crate::paytable::PAYTABLE;
+use crate::theme::Theme;
+
+/// Symbols are shown at 1/2 of their size by default so several lines fit on a page.
+const THUMBNAIL_SCALE: u32 = 2;
+const MARGIN: u32 = 10;
+/// Between two symbols and between two rows.
+const GAP: u32 = 4;
+
+pub struct HelpPages<'a> {
+    symbols: &'a [CompressedImage<'a>],
+    theme: Theme<'a>,
+    screen: Size,
+    scale: u32,
+    footer: Option<&'a str>,
+}
+
+impl<'a> HelpPages<'a> {
+    /// `symbols` are the symbol images by id.
+    pub fn new(screen: Size, symbols: &'a [CompressedImage<'a>], theme: Theme<'a>) -> Self {
+        HelpPages {
+            symbols,
+            theme,
+            screen,
+            scale: THUMBNAIL_SCALE,
+            footer: Some("SPIN: NEXT PAGE"),
+        }
+    }
+
+    /// Shows the symbols at 1/`scale` of their size.
+    pub fn with_scale(mut self, scale: u32) -> Self {
+        self.scale = scale.max(1);
+        self
+    }
+
+    /// Leaves out the controls line, for pages that turn by themselves.
+    pub fn without_footer(mut self) -> Self {
+        self.footer = None;
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        PAYTABLE.len().div_ceil(self.rows_per_page())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Draws page `page` over the whole screen, with the payouts for `bet`.
+    pub fn draw<D>(&self, page: usize, bet: i32, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        target.clear(self.theme.background)?;
+        let style = self.theme.text_style();
+        let line_height = Self::line_height(&self.theme);
+        let baseline = self.theme.font.baseline as i32;
+        let center_x = self.screen.width as i32 / 2;
+
+        let mut title: String<32> = String::new();
+        let _ = write!(title, "BET {} PAYS ({}/{})", bet, page + 1, self.len());
+        Text::with_alignment(
+            &title,
+            Point::new(center_x, MARGIN as i32 + baseline),
+            style,
+            Alignment::Center,
+        )
+        .draw(target)?;
+
+        let thumbnail = self.thumbnail();
+        let rows_per_page = self.rows_per_page();
+        let rows_top = (MARGIN + line_height + MARGIN) as i32;
+        let row_height = (thumbnail.height + GAP) as i32;
+        let rows = PAYTABLE
+            .iter()
+            .skip(page * rows_per_page)
+            .take(rows_per_page);
+        for (row, pay) in rows.enumerate() {
+            let y = rows_top + row as i32 * row_height;
+
+            // as many symbols as the line needs
+            if let Some(symbol) = self.symbols.get(pay.combo.symbol()) {
+                for i in 0..pay.combo.count() as i32 {
+                    let x = MARGIN as i32 + i * (thumbnail.width + GAP) as i32;
+                    draw_thumbnail(
+                        symbol,
+                        self.scale,
+                        Rectangle::new(Point::new(x, y), thumbnail),
+                        target,
+                    )?;
+                }
+            }
+
+            let mut amount: String<16> = String::new();
+            let _ = write!(amount, "{}", pay.amount(bet));
+            let text_y = y + (thumbnail.height as i32 - line_height as i32) / 2 + baseline;
+            Text::with_alignment(
+                &amount,
+                Point::new((self.screen.width - MARGIN) as i32, text_y),
+                style,
+                Alignment::Right,
+            )
+            .draw(target)?;
+        }
+
+        if let Some(footer) = self.footer {
+            let footer_y = (self.screen.height - MARGIN - line_height) as i32 + baseline;
+            Text::with_alignment(
+                footer,
+                Point::new(center_x, footer_y),
+                style,
+                Alignment::Center,
+            )
+            .draw(target)?;
+        }
+
+        Ok(())
+    }
+
+    fn thumbnail(&self) -> Size {
+        let symbol = self
+            .symbols
+            .first()
+            .map_or(Size::zero(), |image| image.size());
+        Size::new(
+            symbol.width.div_ceil(self.scale),
+            symbol.height.div_ceil(self.scale),
+        )
+    }
+
+    fn rows_per_page(&self) -> usize {
+        // the title goes above the rows and the controls, if any, below them
+        let lines = if self.footer.is_some() { 2 } else { 1 };
+        let rows_height = self
+            .screen
+            .height
+            .saturating_sub(lines * (Self::line_height(&self.theme) + MARGIN));
+        (rows_height / (self.thumbnail().height + GAP)).max(1) as usize
+    }
+
+    fn line_height(theme: &Theme) -> u32 {
+        theme.font.character_size.height
+    }
+}
+
+/// Draws every `scale`th pixel of every `scale`th row of `image`.
+fn draw_thumbnail<D>(
+    image: &CompressedImage,
+    scale: u32,
+    area: Rectangle,
+    target: &mut D,
+) -> Result<(), D::Error>
+where
+    D: DrawTarget<Color = Rgb565>,
+{
+    let width = image.size().width;
+    let pixels = image.pixels().enumerate().filter_map(|(i, color)| {
+        let (x, y) = (i as u32 % width, i as u32 / width);
+        (x % scale == 0 && y % scale == 0).then_some(color)
+    });
+    target.fill_contiguous(&area, pixels)
+}
@@ -0,0 +1,19 @@
+#![no_std]
+
+pub mod animation;
+pub mod compressed;
+pub mod config;
+pub mod display;
+pub mod framebuffer;
+pub mod help;
+pub mod melody;
+pub mod paytable;
+pub mod pcm;
+pub mod power;
+pub mod sound;
+pub mod st7735;
+pub mod symbols;
+pub mod theme;
+pub mod timeline;
+pub mod ui;
+pub mod win;
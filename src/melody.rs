@@ -0,0 +1,10 @@
+//! Tunes for the buzzer. The RTTTL parser is the `arcade_melody` crate in `melody/`,
+//! which is tested on the host.
+
+pub use arcade_melody::*;
+
+/// The jingles the machine ships with, one RTTTL file each in `assets/jingles`.
+pub const SPIN: &str = include_str!("../assets/jingles/spin.rtttl");
+pub const WIN: &str = include_str!("../assets/jingles/win.rtttl");
+pub const JACKPOT: &str = include_str!("../assets/jingles/jackpot.rtttl");
+pub const CASHOUT: &str = include_str!("../assets/jingles/cashout.rtttl");
@@ -0,0 +1,196 @@
+//! What pays and how much.
+//!
+//! The lines are checked from the top and only the first match pays. The table is
+//! ordered from the biggest win down, except that a rusty crab anywhere comes before a
+//! pair of raspberries: the machine always paid 100 for raspberry, raspberry, rusty
+//! crab and it still does. The combos are fixed, the payouts can be
+//! changed from the console and are kept in the EEPROM page before the settings, with
+//! a magic, a version and a CRC like `Config`.
+
+use core::sync::atomic::{AtomicI32, Ordering};
+
+use arcade_eeprom::{PAYTABLE_MAGIC as MAGIC, PAYTABLE_VERSION as VERSION, crc16};
+
+use crate::symbols::{CSHARP, JAVASCRIPT, NODEJS, PYTHON, RASPBERRY, RUSTY_CRAB, SYMBOL_COUNT};
+
+/// Payouts in the table are for this bet and scale with it.
+pub const BASE_BET: i32 = 500;
+
+#[derive(Clone, Copy, PartialEq, defmt::Format)]
+pub enum Combo {
+    /// The symbol on all three reels.
+    Three(usize),
+    /// The symbol on two neighbouring reels.
+    TwoAdjacent(usize),
+    /// The symbol anywhere.
+    Any(usize),
+}
+
+pub struct Pay {
+    pub combo: Combo,
+    /// For a `BASE_BET` bet.
+    pays: AtomicI32,
+}
+
+pub const LINES: usize = 9;
+/// The page before the settings in the AT24C256, see `arcade_eeprom` for the layout.
+pub use arcade_eeprom::{PAYTABLE_ADDR, PAYTABLE_SIZE};
+/// Keeps a line's win below `i32::MAX` at the biggest bet.
+pub const MAX_PAYS: i32 = 10_000_000;
+
+const _: () = assert!(PAYTABLE_SIZE == 3 + LINES * 4 + 2);
+
+pub static PAYTABLE: [Pay; LINES] = [
+    Pay::new(Combo::Three(RUSTY_CRAB), 500_000),
+    Pay::new(Combo::Three(RASPBERRY), 250_000),
+    Pay::new(Combo::Three(NODEJS), 50_000),
+    Pay::new(Combo::Three(JAVASCRIPT), 37_500),
+    Pay::new(Combo::Three(PYTHON), 25_000),
+    Pay::new(Combo::Three(CSHARP), 12_500),
+    Pay::new(Combo::TwoAdjacent(RUSTY_CRAB), 7_500),
+    Pay::new(Combo::Any(RUSTY_CRAB), 100),
+    Pay::new(Combo::TwoAdjacent(RASPBERRY), 5_000),
+];
+
+pub struct Win {
+    pub pay: &'static Pay,
+    pub amount: i32,
+    /// Reels whose symbol is part of the win.
+    pub reels: [bool; 3],
+}
+
+impl Pay {
+    pub const fn new(combo: Combo, pays: i32) -> Self {
+        Pay {
+            combo,
+            pays: AtomicI32::new(pays),
+        }
+    }
+
+    /// For a `BASE_BET` bet.
+    pub fn pays(&self) -> i32 {
+        self.pays.load(Ordering::Relaxed)
+    }
+
+    /// What this line pays for `bet`.
+    pub fn amount(&self, bet: i32) -> i32 {
+        self.pays().saturating_mul(bet / BASE_BET)
+    }
+}
+
+/// Changes what `line` pays for a `BASE_BET` bet, `false` if there is no such line or
+/// `pays` is above `MAX_PAYS`.
+pub fn set_pays(line: usize, pays: i32) -> bool {
+    match PAYTABLE.get(line) {
+        Some(pay) if (0..=MAX_PAYS).contains(&pays) => {
+            pay.pays.store(pays, Ordering::Relaxed);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// The payouts as they are stored in the EEPROM.
+pub fn to_bytes() -> [u8; PAYTABLE_SIZE] {
+    let mut bytes = [0u8; PAYTABLE_SIZE];
+    bytes[0..2].copy_from_slice(&MAGIC);
+    bytes[2] = VERSION;
+    for (i, pay) in PAYTABLE.iter().enumerate() {
+        bytes[3 + i * 4..7 + i * 4].copy_from_slice(&pay.pays().to_be_bytes());
+    }
+    let crc = crc16(&bytes[..PAYTABLE_SIZE - 2]);
+    bytes[PAYTABLE_SIZE - 2..].copy_from_slice(&crc.to_be_bytes());
+    bytes
+}
+
+/// Takes the payouts from a record written by `to_bytes`, `false` and the built in
+/// payouts for anything else.
+pub fn load(bytes: &[u8; PAYTABLE_SIZE]) -> bool {
+    let crc = u16::from_be_bytes([bytes[PAYTABLE_SIZE - 2], bytes[PAYTABLE_SIZE - 1]]);
+    if bytes[0..2] != MAGIC || bytes[2] != VERSION || crc16(&bytes[..PAYTABLE_SIZE - 2]) != crc {
+        return false;
+    }
+    let pays: [i32; LINES] = core::array::from_fn(|i| {
+        i32::from_be_bytes(bytes[3 + i * 4..7 + i * 4].try_into().unwrap())
+    });
+    if pays.iter().any(|pays| !(0..=MAX_PAYS).contains(pays)) {
+        return false;
+    }
+    for (line, pays) in pays.into_iter().enumerate() {
+        set_pays(line, pays);
+    }
+    true
+}
+
+impl Combo {
+    pub fn symbol(&self) -> usize {
+        match *self {
+            Combo::Three(symbol) | Combo::TwoAdjacent(symbol) | Combo::Any(symbol) => symbol,
+        }
+    }
+
+    /// How many of the symbol the line needs.
+    pub fn count(&self) -> usize {
+        match self {
+            Combo::Three(_) => 3,
+            Combo::TwoAdjacent(_) => 2,
+            Combo::Any(_) => 1,
+        }
+    }
+
+    /// The reels that make the line, if `reels` has it.
+    fn matches(&self, reels: [usize; 3]) -> Option<[bool; 3]> {
+        let is = reels.map(|symbol| symbol == self.symbol());
+        match self {
+            Combo::Three(_) => (is == [true; 3]).then_some(is),
+            Combo::TwoAdjacent(_) if is[0] && is[1] => Some(is),
+            Combo::TwoAdjacent(_) if is[1] && is[2] => Some(is),
+            Combo::TwoAdjacent(_) => None,
+            Combo::Any(_) => is.contains(&true).then_some(is),
+        }
+    }
+
+    /// Reels that show exactly this line and nothing that pays more.
+    pub fn example(&self) -> [usize; 3] {
+        let symbol = self.symbol();
+        let [a, b] = fillers(symbol);
+        match self {
+            Combo::Three(_) => [symbol; 3],
+            Combo::TwoAdjacent(_) => [symbol, symbol, a],
+            Combo::Any(_) => [symbol, a, b],
+        }
+    }
+}
+
+/// The best line for the symbols the reels stopped on.
+pub fn evaluate(reels: [usize; 3], bet: i32) -> Option<Win> {
+    PAYTABLE.iter().find_map(|pay| {
+        pay.combo.matches(reels).map(|winning| Win {
+            pay,
+            amount: pay.amount(bet),
+            reels: winning,
+        })
+    })
+}
+
+/// The most any line pays for `bet`.
+pub fn top_award(bet: i32) -> i32 {
+    PAYTABLE
+        .iter()
+        .map(|pay| pay.amount(bet))
+        .max()
+        .unwrap_or(0)
+}
+
+/// Two symbols other than `except` that don't pay unless there are three of them.
+fn fillers(except: usize) -> [usize; 2] {
+    let mut fillers = (0..SYMBOL_COUNT).rev().filter(|&symbol| {
+        symbol != except
+            && PAYTABLE
+                .iter()
+                .all(|pay| pay.combo.symbol() != symbol || matches!(pay.combo, Combo::Three(_)))
+    });
+    let a = fillers.next().unwrap_or(except);
+    let b = fillers.next().unwrap_or(a);
+    [a, b]
+}
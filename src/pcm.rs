@@ -0,0 +1,48 @@
+//! Short 8 bit samples for the buzzer.
+//!
+//! `build.rs` converts every WAV in `assets/samples` to unsigned 8 bit mono at
+//! `SAMPLE_RATE` and defines one `Sample` per file, named after it, e.g.
+//! `COIN_DROP`. `tools/pcm_preview` runs the same conversion on the host and writes
+//! the result back as a WAV to listen to.
+//!
+//! A sample plays by running the buzzer PWM far above hearing, with `CARRIER_TOP`
+//! and no divider, and setting the duty cycle to every sample in turn. The buzzer
+//! only follows the average, which is the waveform.
+
+/// Samples per second, must match `tools/pcm_preview/src/wav.rs`.
+pub const SAMPLE_RATE: u32 = 8000;
+/// PWM `top` while a sample plays, one step per sample value.
+pub const CARRIER_TOP: u16 = 255;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Sample {
+    pub name: &'static str,
+    /// Unsigned, 128 is silence.
+    pub data: &'static [u8],
+}
+
+impl Sample {
+    pub fn duration_ms(&self) -> u32 {
+        (self.data.len() as u64 * 1000 / SAMPLE_RATE as u64) as u32
+    }
+
+    /// Where the sample is `elapsed_ms` after it started.
+    pub fn position(&self, elapsed_ms: u64) -> usize {
+        (elapsed_ms * SAMPLE_RATE as u64 / 1000).min(self.data.len() as u64) as usize
+    }
+}
+
+impl defmt::Format for Sample {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "Sample({=str})", self.name)
+    }
+}
+
+/// The PWM compare value for one sample at `volume` percent, swinging around the
+/// middle of `CARRIER_TOP`.
+pub fn level(sample: u8, volume: u8) -> u16 {
+    let swing = (sample as i32 - 128) * volume.min(100) as i32 / 100;
+    (128 + swing).clamp(0, CARRIER_TOP as i32) as u16
+}
+
+include!(concat!(env!("OUT_DIR"), "/samples.rs"));
@@ -0,0 +1,60 @@
+//! How bright the screen is and when it sleeps.
+//!
+//! The screen goes from full brightness to dimmed to asleep the longer nobody touches
+//! the machine, with the times and levels from `Config`. Any input brings it back.
+
+use embassy_time::Duration;
+
+use crate::config::Config;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+pub enum Power {
+    On,
+    Dimmed,
+    /// Backlight off and the panel in sleep mode.
+    Asleep,
+}
+
+impl Power {
+    /// What the screen should be after `idle` without input.
+    pub fn after(idle: Duration, config: &Config) -> Self {
+        if idle >= Self::sleep_after(config) {
+            Power::Asleep
+        } else if idle >= Self::dim_after(config) {
+            Power::Dimmed
+        } else {
+            Power::On
+        }
+    }
+
+    /// How long without input until the next step down from `self`, if there is one.
+    pub fn next_step(&self, config: &Config) -> Option<Duration> {
+        match self {
+            Power::On => Some(Self::dim_after(config).min(Self::sleep_after(config))),
+            Power::Dimmed => Some(Self::sleep_after(config)),
+            Power::Asleep => None,
+        }
+    }
+
+    /// Backlight in percent.
+    pub fn brightness(&self, config: &Config) -> u8 {
+        match self {
+            Power::On => config.brightness,
+            Power::Dimmed => config.dim_brightness.min(config.brightness),
+            Power::Asleep => 0,
+        }
+    }
+
+    fn dim_after(config: &Config) -> Duration {
+        Duration::from_secs(config.dim_after_s as u64)
+    }
+
+    fn sleep_after(config: &Config) -> Duration {
+        Duration::from_secs(config.sleep_after_s as u64)
+    }
+}
+
+/// The PWM compare value for `percent` of `top`.
+pub fn duty(percent: u8, top: u16) -> u16 {
+    (top as u32 * percent.min(100) as u32 / 100) as u16
+}
@@ -0,0 +1,332 @@
+//! What the buzzer plays and which sound wins when several want it.
+//!
+//! Every game event has a `Sound` with a priority. A new sound replaces the one that
+//! plays if its priority is at least as high, otherwise it is dropped, so a win cuts
+//! the spin ticks short but ticks never cut into a win. `Priority::Overlay` sounds,
+//! the button and reel stop clicks, don't replace anything: they play over the current
+//! sound for their short length while it keeps running underneath, and it shows
+//! through their pauses.
+//!
+//! The engine only works on milliseconds, the buzzer task asks it for the `Tone` to
+//! play now and until when. A jingle is parsed once when it starts.
+//!
+//! With the `pcm` feature the reel stops, coins and the jackpot are recorded samples
+//! from `assets/samples` instead of beeps and jingles, see `pcm`.
+
+use crate::melody::{self, Melody};
+#[cfg(feature = "pcm")]
+use crate::pcm;
+use crate::pcm::Sample;
+use crate::win::WinTier;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+pub enum Priority {
+    /// Plays over the current sound instead of replacing it.
+    Overlay,
+    Ambient,
+    Effect,
+    Win,
+    Jackpot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Pattern {
+    /// `count` beeps of `frequency`.
+    Beeps {
+        frequency: u16,
+        on_ms: u16,
+        off_ms: u16,
+        count: u16,
+    },
+    /// An RTTTL jingle, see `melody`, played again after `REPEAT_GAP_MS` if `repeat`
+    /// until it is stopped.
+    Jingle { text: &'static str, repeat: bool },
+    /// A sample played once.
+    Sample(&'static Sample),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Sound {
+    pub priority: Priority,
+    pub pattern: Pattern,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Event {
+    /// The short jingle when the reels start.
+    SpinStart,
+    /// Ticks while the reels spin.
+    Spin,
+    ReelStop,
+    Win(WinTier),
+    Bet,
+    AddBalance,
+    CashOut,
+}
+
+/// The pitch of the spin ticks, the buzzer's old fixed tone.
+const TICK_HZ: u16 = 240;
+const CLICK_HZ: u16 = 880;
+const REPEAT_GAP_MS: u32 = 150;
+/// Longest silence at the end of a jingle note, so repeated notes stay apart.
+const NOTE_GAP_MS: u32 = 20;
+
+impl Event {
+    pub fn sound(&self) -> Sound {
+        let (priority, pattern) = match self {
+            Event::SpinStart => (Priority::Overlay, jingle(melody::SPIN, false)),
+            // about 10 seconds, in case the reel stops never come
+            Event::Spin => (Priority::Ambient, beeps(TICK_HZ, 50, 70, 84)),
+            #[cfg(feature = "pcm")]
+            Event::ReelStop => (Priority::Overlay, Pattern::Sample(&pcm::REEL_STOP)),
+            #[cfg(not(feature = "pcm"))]
+            Event::ReelStop => (Priority::Overlay, beeps(CLICK_HZ * 2, 15, 0, 1)),
+            Event::Win(WinTier::Small) => (Priority::Win, jingle(melody::WIN, false)),
+            #[cfg(feature = "pcm")]
+            Event::Win(WinTier::Jackpot) => (Priority::Jackpot, Pattern::Sample(&pcm::JACKPOT)),
+            #[cfg(not(feature = "pcm"))]
+            Event::Win(WinTier::Jackpot) => (Priority::Jackpot, jingle(melody::JACKPOT, true)),
+            Event::Win(_) => (Priority::Win, jingle(melody::WIN, true)),
+            #[cfg(feature = "pcm")]
+            Event::AddBalance => (Priority::Overlay, Pattern::Sample(&pcm::COIN_DROP)),
+            #[cfg(not(feature = "pcm"))]
+            Event::AddBalance => (Priority::Overlay, beeps(CLICK_HZ, 50, 0, 1)),
+            Event::Bet => (Priority::Overlay, beeps(CLICK_HZ, 50, 0, 1)),
+            Event::CashOut => (Priority::Effect, jingle(melody::CASHOUT, false)),
+        };
+        Sound { priority, pattern }
+    }
+
+    /// Whether `other` is the same kind of event, wins of any tier count as one.
+    fn is(&self, other: &Event) -> bool {
+        core::mem::discriminant(self) == core::mem::discriminant(other)
+    }
+}
+
+const fn beeps(frequency: u16, on_ms: u16, off_ms: u16, count: u16) -> Pattern {
+    Pattern::Beeps {
+        frequency,
+        on_ms,
+        off_ms,
+        count,
+    }
+}
+
+const fn jingle(text: &'static str, repeat: bool) -> Pattern {
+    Pattern::Jingle { text, repeat }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Voice {
+    Silent,
+    /// A square wave of this frequency.
+    Square(u16),
+    /// `sample` from where it is at, it started at `started_ms`.
+    Pcm {
+        sample: &'static Sample,
+        started_ms: u64,
+    },
+}
+
+/// What to play now and until when, `None` if it only changes with the next event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Tone {
+    pub voice: Voice,
+    pub until_ms: Option<u64>,
+}
+
+impl Tone {
+    const SILENT: Tone = Tone {
+        voice: Voice::Silent,
+        until_ms: None,
+    };
+}
+
+#[derive(Clone, Copy)]
+struct Playing {
+    event: Event,
+    sound: Sound,
+    /// The parsed jingle and its length, for `Pattern::Jingle`.
+    melody: Option<(Melody<'static>, u64)>,
+    started_ms: u64,
+}
+
+impl Playing {
+    /// `None` once it is over.
+    fn tone(&self, now_ms: u64) -> Option<Tone> {
+        let elapsed = now_ms.saturating_sub(self.started_ms);
+        let square = |frequency| match frequency {
+            0 => Voice::Silent,
+            frequency => Voice::Square(frequency),
+        };
+        let (voice, until) = match self.sound.pattern {
+            Pattern::Beeps {
+                frequency,
+                on_ms,
+                off_ms,
+                count,
+            } => {
+                let period = (on_ms as u64 + off_ms as u64).max(1);
+                let beep = elapsed / period;
+                if beep >= count as u64 {
+                    return None;
+                }
+                let start = beep * period;
+                if elapsed - start < on_ms as u64 {
+                    (square(frequency), start + on_ms as u64)
+                } else {
+                    (Voice::Silent, start + period)
+                }
+            }
+            Pattern::Jingle { repeat, .. } => {
+                let (melody, length) = self.melody?;
+                let period = length + if repeat { REPEAT_GAP_MS as u64 } else { 0 };
+                if period == 0 || (!repeat && elapsed >= length) {
+                    return None;
+                }
+                let start = elapsed / period * period;
+                note_at(&melody, elapsed - start)
+                    .map_or((Voice::Silent, start + period), |(f, until)| {
+                        (square(f), start + until)
+                    })
+            }
+            Pattern::Sample(sample) => {
+                let length = sample.duration_ms() as u64;
+                if elapsed >= length {
+                    return None;
+                }
+                let voice = Voice::Pcm {
+                    sample,
+                    started_ms: self.started_ms,
+                };
+                (voice, length)
+            }
+        };
+        Some(Tone {
+            voice,
+            until_ms: Some(self.started_ms + until),
+        })
+    }
+}
+
+/// The frequency at `at_ms` into `melody` and when that note (or its gap) ends,
+/// `None` after the last note.
+fn note_at(melody: &Melody<'_>, at_ms: u64) -> Option<(u16, u64)> {
+    let mut start = 0u64;
+    for note in melody.notes() {
+        let end = start + note.duration_ms as u64;
+        if at_ms < end {
+            let gap = (note.duration_ms / 10).min(NOTE_GAP_MS) as u64;
+            return Some(if at_ms < end - gap {
+                (note.frequency, end - gap)
+            } else {
+                (0, end)
+            });
+        }
+        start = end;
+    }
+    None
+}
+
+/// Plays one sound at a time, with clicks over it.
+#[derive(Default)]
+pub struct SoundEngine {
+    current: Option<Playing>,
+    overlay: Option<Playing>,
+}
+
+impl SoundEngine {
+    pub const fn new() -> Self {
+        SoundEngine {
+            current: None,
+            overlay: None,
+        }
+    }
+
+    /// Starts the sound for `event` unless something more important plays, returns
+    /// whether it started.
+    pub fn play(&mut self, event: Event, now_ms: u64) -> bool {
+        let sound = event.sound();
+        let melody = match sound.pattern {
+            Pattern::Jingle { text, .. } => match Melody::parse(text) {
+                Ok(melody) => Some((melody, melody.duration_ms() as u64)),
+                Err(_) => return false,
+            },
+            _ => None,
+        };
+        let playing = Some(Playing {
+            event,
+            sound,
+            melody,
+            started_ms: now_ms,
+        });
+
+        if sound.priority == Priority::Overlay {
+            self.overlay = playing;
+            return true;
+        }
+        self.expire(now_ms);
+        match self.current {
+            Some(current) if current.sound.priority > sound.priority => false,
+            _ => {
+                self.current = playing;
+                true
+            }
+        }
+    }
+
+    /// Stops `event` if it plays, a win stops wins of any tier.
+    pub fn stop(&mut self, event: Event) {
+        for playing in [&mut self.current, &mut self.overlay] {
+            if playing.is_some_and(|playing| playing.event.is(&event)) {
+                *playing = None;
+            }
+        }
+    }
+
+    /// What the buzzer should play at `now_ms`. The overlay plays over the current
+    /// sound, which comes through wherever the overlay is silent.
+    pub fn tone(&mut self, now_ms: u64) -> Tone {
+        self.expire(now_ms);
+        let current = self.current.and_then(|playing| playing.tone(now_ms));
+        let overlay = self.overlay.and_then(|playing| playing.tone(now_ms));
+
+        match (overlay, current) {
+            (Some(overlay), _) if overlay.voice != Voice::Silent => overlay,
+            (Some(overlay), Some(current)) => Tone {
+                voice: current.voice,
+                until_ms: earliest(overlay.until_ms, current.until_ms),
+            },
+            (Some(overlay), None) => overlay,
+            (None, Some(current)) => current,
+            (None, None) => Tone::SILENT,
+        }
+    }
+
+    /// Whether anything plays at `now_ms`.
+    pub fn is_playing(&mut self, now_ms: u64) -> bool {
+        self.expire(now_ms);
+        self.current.is_some() || self.overlay.is_some()
+    }
+
+    fn expire(&mut self, now_ms: u64) {
+        for playing in [&mut self.current, &mut self.overlay] {
+            if playing.is_some_and(|playing| playing.tone(now_ms).is_none()) {
+                *playing = None;
+            }
+        }
+    }
+}
+
+fn earliest(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// The PWM compare value for `volume` percent of the loudest a square wave with
+/// `top` gets, which is half of it.
+pub fn compare(top: u16, volume: u8) -> u16 {
+    (top as u32 * volume.min(100) as u32 / 200) as u16
+}
@@ -0,0 +1,271 @@
+//! Async ST7735 driver for the small secondary display.
+//!
+//! The `st7735-lcd` crate is built on another `embedded-graphics` than `ili9341`, which
+//! is why the small display used to sit on a second Pico. This driver talks to the panel
+//! like `Ili9341Async` does, so both panels share `SPI0` through `SpiDevice`s with their
+//! own CS pins and are fed from a `FrameBuffer`.
+
+use embassy_time::Timer;
+use embedded_graphics::{
+    pixelcolor::{IntoStorage, Rgb565},
+    prelude::*,
+    primitives::Rectangle,
+};
+use embedded_hal_1::digital::OutputPin;
+use embedded_hal_async::spi::SpiDevice;
+use ili9341::Orientation;
+
+use crate::display::{Error, FlushTarget};
+
+const CHUNK_SIZE: usize = 512; // bytes per SPI transfer
+
+#[derive(Clone, Copy)]
+#[repr(u8)]
+enum Command {
+    SoftwareReset = 0x01,
+    SleepModeOff = 0x11,
+    NormalDisplayOn = 0x13,
+    InvertOff = 0x20,
+    DisplayOn = 0x29,
+    ColumnAddressSet = 0x2a,
+    RowAddressSet = 0x2b,
+    MemoryWrite = 0x2c,
+    MemoryAccessControl = 0x36,
+    PixelFormatSet = 0x3a,
+    FrameRateNormal = 0xb1,
+    FrameRateIdle = 0xb2,
+    FrameRatePartial = 0xb3,
+    InversionControl = 0xb4,
+    PowerControl1 = 0xc0,
+    PowerControl2 = 0xc1,
+    PowerControl3 = 0xc2,
+    PowerControl4 = 0xc3,
+    PowerControl5 = 0xc4,
+    VcomControl1 = 0xc5,
+    GammaPositive = 0xe0,
+    GammaNegative = 0xe1,
+}
+
+pub struct St7735Async<SPI, DC> {
+    spi: SPI,
+    dc: DC,
+    width: u16,
+    height: u16,
+    /// Where the visible area starts in the controller's 132x162 memory, for portrait.
+    offset: (u16, u16),
+    landscape: bool,
+    buffer: [u8; CHUNK_SIZE],
+}
+
+impl<SPI, DC> St7735Async<SPI, DC>
+where
+    SPI: SpiDevice,
+    DC: OutputPin,
+{
+    /// Resets the panel and runs the init sequence of the `st7735-lcd` crate.
+    ///
+    /// `size` and `offset` are for portrait, the 1.44" 128x128 module starts at (2, 3).
+    pub async fn new<RST>(
+        spi: SPI,
+        dc: DC,
+        reset: &mut RST,
+        size: Size,
+        offset: Point,
+        orientation: Orientation,
+    ) -> Result<Self, Error>
+    where
+        RST: OutputPin,
+    {
+        let mut display = St7735Async {
+            spi,
+            dc,
+            width: size.width as u16,
+            height: size.height as u16,
+            offset: (offset.x as u16, offset.y as u16),
+            landscape: false,
+            buffer: [0; CHUNK_SIZE],
+        };
+
+        reset.set_high().map_err(|_| Error::Pin)?;
+        Timer::after_millis(1).await;
+        reset.set_low().map_err(|_| Error::Pin)?;
+        Timer::after_millis(10).await;
+        reset.set_high().map_err(|_| Error::Pin)?;
+        Timer::after_millis(120).await;
+
+        display.command(Command::SoftwareReset, &[]).await?;
+        Timer::after_millis(150).await;
+        display.command(Command::SleepModeOff, &[]).await?;
+        Timer::after_millis(255).await;
+
+        display
+            .command(Command::FrameRateNormal, &[0x01, 0x2c, 0x2d])
+            .await?;
+        display
+            .command(Command::FrameRateIdle, &[0x01, 0x2c, 0x2d])
+            .await?;
+        display
+            .command(
+                Command::FrameRatePartial,
+                &[0x01, 0x2c, 0x2d, 0x01, 0x2c, 0x2d],
+            )
+            .await?;
+        display.command(Command::InversionControl, &[0x07]).await?;
+        display
+            .command(Command::PowerControl1, &[0xa2, 0x02, 0x84])
+            .await?;
+        display.command(Command::PowerControl2, &[0xc5]).await?;
+        display
+            .command(Command::PowerControl3, &[0x0a, 0x00])
+            .await?;
+        display
+            .command(Command::PowerControl4, &[0x8a, 0x2a])
+            .await?;
+        display
+            .command(Command::PowerControl5, &[0x8a, 0xee])
+            .await?;
+        display.command(Command::VcomControl1, &[0x0e]).await?;
+        display.command(Command::InvertOff, &[]).await?;
+        display.command(Command::PixelFormatSet, &[0x05]).await?; // 16 bit RGB565
+        display
+            .command(
+                Command::GammaPositive,
+                &[
+                    0x02, 0x1c, 0x07, 0x12, 0x37, 0x32, 0x29, 0x2d, 0x29, 0x25, 0x2b, 0x39, 0x00,
+                    0x01, 0x03, 0x10,
+                ],
+            )
+            .await?;
+        display
+            .command(
+                Command::GammaNegative,
+                &[
+                    0x03, 0x1d, 0x07, 0x06, 0x2e, 0x2c, 0x29, 0x2d, 0x2e, 0x2e, 0x37, 0x3f, 0x00,
+                    0x00, 0x02, 0x10,
+                ],
+            )
+            .await?;
+        display.set_orientation(orientation).await?;
+
+        display.command(Command::NormalDisplayOn, &[]).await?;
+        Timer::after_millis(10).await;
+        display.command(Command::DisplayOn, &[]).await?;
+        Timer::after_millis(100).await;
+
+        Ok(display)
+    }
+
+    async fn command(&mut self, command: Command, args: &[u8]) -> Result<(), Error> {
+        self.dc.set_low().map_err(|_| Error::Pin)?;
+        self.spi
+            .write(&[command as u8])
+            .await
+            .map_err(|_| Error::Spi)?;
+
+        if !args.is_empty() {
+            self.dc.set_high().map_err(|_| Error::Pin)?;
+            self.spi.write(args).await.map_err(|_| Error::Spi)?;
+        }
+        Ok(())
+    }
+
+    pub async fn set_orientation(&mut self, orientation: Orientation) -> Result<(), Error> {
+        // BGR panel
+        let (madctl, landscape) = match orientation {
+            Orientation::Portrait => (0x08, false),
+            Orientation::Landscape => (0x60 | 0x08, true),
+            Orientation::PortraitFlipped => (0xc0 | 0x08, false),
+            Orientation::LandscapeFlipped => (0xa0 | 0x08, true),
+        };
+        self.command(Command::MemoryAccessControl, &[madctl])
+            .await?;
+
+        if self.landscape != landscape {
+            core::mem::swap(&mut self.width, &mut self.height);
+            self.landscape = landscape;
+        }
+        Ok(())
+    }
+
+    pub fn size(&self) -> Size {
+        Size::new(self.width as u32, self.height as u32)
+    }
+
+    pub fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(Point::zero(), self.size())
+    }
+
+    async fn set_window(&mut self, area: &Rectangle) -> Result<(), Error> {
+        // rows and columns trade places in landscape, and so do the offsets
+        let (dx, dy) = if self.landscape {
+            (self.offset.1, self.offset.0)
+        } else {
+            self.offset
+        };
+        let x0 = area.top_left.x as u16 + dx;
+        let y0 = area.top_left.y as u16 + dy;
+        let x1 = x0 + area.size.width as u16 - 1;
+        let y1 = y0 + area.size.height as u16 - 1;
+
+        self.command(
+            Command::ColumnAddressSet,
+            &[(x0 >> 8) as u8, x0 as u8, (x1 >> 8) as u8, x1 as u8],
+        )
+        .await?;
+        self.command(
+            Command::RowAddressSet,
+            &[(y0 >> 8) as u8, y0 as u8, (y1 >> 8) as u8, y1 as u8],
+        )
+        .await?;
+        self.command(Command::MemoryWrite, &[]).await?;
+        self.dc.set_high().map_err(|_| Error::Pin)
+    }
+
+    /// Streams `colors` row by row into `area`. The area has to be inside the screen.
+    pub async fn set_pixels<I>(&mut self, area: Rectangle, colors: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = Rgb565>,
+    {
+        if area.is_zero_sized() {
+            return Ok(());
+        }
+        self.set_window(&area).await?;
+
+        let mut colors = colors.into_iter();
+        let mut remaining = area.size.width as usize * area.size.height as usize;
+
+        while remaining > 0 {
+            let count = remaining.min(CHUNK_SIZE / 2);
+            for i in 0..count {
+                let color = colors.next().unwrap_or(Rgb565::BLACK);
+                self.buffer[2 * i..2 * i + 2].copy_from_slice(&color.into_storage().to_be_bytes());
+            }
+            self.spi
+                .write(&self.buffer[..2 * count])
+                .await
+                .map_err(|_| Error::Spi)?;
+            remaining -= count;
+        }
+        Ok(())
+    }
+
+    pub async fn clear(&mut self, color: Rgb565) -> Result<(), Error> {
+        let area = self.bounding_box();
+        let pixels = area.size.width as usize * area.size.height as usize;
+        self.set_pixels(area, core::iter::repeat_n(color, pixels))
+            .await
+    }
+}
+
+impl<SPI, DC> FlushTarget for St7735Async<SPI, DC>
+where
+    SPI: SpiDevice,
+    DC: OutputPin,
+{
+    async fn set_pixels<I>(&mut self, area: Rectangle, colors: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = Rgb565>,
+    {
+        St7735Async::set_pixels(self, area, colors).await
+    }
+}
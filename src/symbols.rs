@@ -0,0 +1,30 @@
+//! The reel symbols.
+//!
+//! `build.rs` converts every image in `assets/symbols` to compressed RGB565 and
+//! generates the table below, so adding a symbol is dropping a file there. It also
+//! defines one id constant per symbol, e.g. `RUSTY_CRAB`, and `SYMBOL_COUNT`,
+//! `SYMBOL_WIDTH` and `SYMBOL_HEIGHT`.
+
+use embedded_graphics::prelude::*;
+
+use crate::animation::{Animation, AnimationFrame};
+use crate::compressed::CompressedImage;
+
+pub struct Symbol {
+    /// Position on the reel strip, from the order prefix of the file name.
+    pub id: usize,
+    pub name: &'static str,
+    data: &'static [u8],
+    /// Played while the symbol is part of a win, from `assets/animations`.
+    pub animation: Option<&'static Animation>,
+}
+
+impl Symbol {
+    pub fn image(&self) -> CompressedImage<'static> {
+        CompressedImage::new(self.data, SYMBOL_SIZE)
+    }
+}
+
+pub const SYMBOL_SIZE: Size = Size::new(SYMBOL_WIDTH, SYMBOL_HEIGHT);
+
+include!(concat!(env!("OUT_DIR"), "/symbols.rs"));
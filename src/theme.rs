@@ -0,0 +1,173 @@
+//! Colors, fonts and where the HUD goes.
+//!
+//! The HUD used to be placed with coordinates worked out for a 320x240 landscape
+//! panel. `Layout` computes the same arrangement from the screen size instead, so the
+//! game also fits a portrait panel or a 320x480 one.
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_10X20, MonoFont, MonoTextStyle, MonoTextStyleBuilder},
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::Rectangle,
+};
+
+#[derive(Clone, Copy)]
+pub struct Theme<'a> {
+    pub background: Rgb565,
+    pub frame: Rgb565,
+    pub text: Rgb565,
+    pub warning: Rgb565,
+    /// Flashing borders and coins when celebrating a win.
+    pub highlight: Rgb565,
+    pub font: &'a MonoFont<'a>,
+}
+
+/// The original red and green look.
+pub const CLASSIC: Theme<'static> = Theme {
+    background: Rgb565::BLACK,
+    frame: Rgb565::RED,
+    text: Rgb565::GREEN,
+    warning: Rgb565::RED,
+    highlight: Rgb565::YELLOW,
+    font: &FONT_10X20,
+};
+
+impl<'a> Theme<'a> {
+    pub fn text_style(&self) -> MonoTextStyle<'a, Rgb565> {
+        self.style(self.text)
+    }
+
+    pub fn warning_style(&self) -> MonoTextStyle<'a, Rgb565> {
+        self.style(self.warning)
+    }
+
+    fn style(&self, color: Rgb565) -> MonoTextStyle<'a, Rgb565> {
+        MonoTextStyleBuilder::new()
+            .font(self.font)
+            .text_color(color)
+            .background_color(self.background)
+            .build()
+    }
+}
+
+const BORDER: u32 = 5;
+const SLOT_FRAME: u32 = 2;
+const SLOT_GAP: u32 = 10;
+/// Space between a text line and the lines around it.
+const PADDING: u32 = 5;
+/// Characters reserved for the bet, "BET: 10000".
+const BET_CHARS: u32 = 11;
+/// Screens narrower than this stack balance and bet on two lines.
+const ONE_LINE_STATUS_WIDTH: u32 = 300;
+
+/// Where every part of the HUD goes for one screen size.
+pub struct Layout {
+    pub screen: Rectangle,
+    pub border: u32,
+    /// Inside the border, above the status line.
+    pub play_area: Rectangle,
+    /// Top left corners of the three reel windows.
+    pub slots: [Point; 3],
+    pub slot_frames: [Rectangle; 3],
+    /// Zero when the reels don't leave room for frames.
+    pub slot_frame_thickness: u32,
+    /// The line between the reels and the balance.
+    pub status_line: Rectangle,
+    /// The line between balance and bet, when they share a line.
+    pub status_divider: Option<Rectangle>,
+    /// Center of the message above the reels.
+    pub banner: Point,
+    /// Center of the last win under the reels.
+    pub last_win: Point,
+    pub balance: Point,
+    pub bet: Point,
+}
+
+impl Layout {
+    pub fn new(screen: Size, slot_size: Size, theme: &Theme) -> Self {
+        let (w, h) = (screen.width, screen.height);
+        let char_width = theme.font.character_size.width + theme.font.character_spacing;
+        let line_height = theme.font.character_size.height + 2 * PADDING;
+        // moves a text baseline from the top of its line to its vertical center
+        let baseline = (theme.font.baseline as i32 + line_height as i32
+            - theme.font.character_size.height as i32)
+            / 2;
+
+        // balance and bet along the bottom
+        let rows = if w >= ONE_LINE_STATUS_WIDTH { 1 } else { 2 };
+        let status_top = h - BORDER - rows * line_height;
+        let status_line = Rectangle::new(
+            Point::new(0, (status_top - BORDER) as i32),
+            Size::new(w, BORDER),
+        );
+        let (status_divider, balance, bet) = if rows == 1 {
+            let divider_x = w - BORDER - BET_CHARS * char_width;
+            let divider = Rectangle::new(
+                Point::new(divider_x as i32, status_top as i32),
+                Size::new(BORDER, h - status_top),
+            );
+            let y = status_top as i32 + baseline;
+            (
+                Some(divider),
+                Point::new((BORDER + PADDING) as i32, y),
+                Point::new((divider_x + BORDER + PADDING) as i32, y),
+            )
+        } else {
+            let y = status_top as i32 + baseline;
+            (
+                None,
+                Point::new((BORDER + PADDING) as i32, y),
+                Point::new((BORDER + PADDING) as i32, y + line_height as i32),
+            )
+        };
+
+        // the reels sit centered in what is left, the message above them and the last
+        // win below
+        let inner = w.saturating_sub(2 * BORDER);
+        // 80 pixel symbols exactly fill a 240 pixel wide portrait screen, then the reels
+        // go without frames and cover the side borders
+        let thickness = if 3 * (slot_size.width + 2 * SLOT_FRAME) <= inner {
+            SLOT_FRAME
+        } else {
+            0
+        };
+        let frame_size = slot_size + Size::new(2 * thickness, 2 * thickness);
+        let gap = (inner.saturating_sub(3 * frame_size.width) / 4).min(SLOT_GAP);
+        let row_width = 3 * frame_size.width + 2 * gap;
+        let x0 = (w as i32 - row_width as i32) / 2;
+        let play_height = status_line.top_left.y - BORDER as i32;
+        let y0 = BORDER as i32 + (play_height - frame_size.height as i32) / 2;
+
+        let frame = |i: i32| {
+            Rectangle::new(
+                Point::new(x0 + i * (frame_size.width + gap) as i32, y0),
+                frame_size,
+            )
+        };
+        let slot_frames = [frame(0), frame(1), frame(2)];
+        let slots =
+            slot_frames.map(|f| f.top_left + Point::new(thickness as i32, thickness as i32));
+
+        let center_x = w as i32 / 2;
+        let above = y0 - line_height as i32;
+        let below = y0 + frame_size.height as i32;
+
+        Layout {
+            screen: Rectangle::new(Point::zero(), screen),
+            border: BORDER,
+            play_area: Rectangle::with_corners(
+                Point::new(BORDER as i32, BORDER as i32),
+                Point::new((w - BORDER) as i32 - 1, status_line.top_left.y - 1),
+            ),
+            slots,
+            slot_frames,
+            slot_frame_thickness: thickness,
+            status_line,
+            status_divider,
+            banner: Point::new(center_x, above.max(BORDER as i32) + baseline),
+            last_win: Point::new(center_x, below + baseline),
+            balance,
+            bet,
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! The spin sequence as a timeline of cues the lights and the buzzer follow.
+//!
+//! The display task runs the spin, so it is the only one that knows when a reel really
+//! stops or when the win meter is done counting. It sends a `Cue` for every step along
+//! with the time it happened, and the other tasks start their effects at that time
+//! instead of running their own timers from the spin press. A celebration carries the
+//! time it ends, so the lights and the buzzer stop with the meter even if
+//! `CelebrationEnd` never reaches them.
+
+use embassy_time::Duration;
+
+use crate::win::WinTier;
+
+pub const REELS: u8 = 3;
+/// How long the lights and the buzzer wait for the reels before they give up on the spin.
+pub const SPIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub enum Cue {
+    /// The reels started moving.
+    SpinStart,
+    /// Reel `0` to `REELS - 1` stopped, they stop left to right.
+    ReelStopped(u8),
+    /// All reels stopped and the result is on the screen, with the tier if it won. The
+    /// spin effects end here.
+    ResultShown(Option<WinTier>),
+    /// The win meter starts counting up, until `ends_ms`.
+    CelebrationStart { tier: WinTier, ends_ms: u64 },
+    /// The meter is done or a button skipped it.
+    CelebrationEnd,
+}
+
+/// A cue and when it happened, in milliseconds since boot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, defmt::Format)]
+pub struct Timed {
+    pub cue: Cue,
+    pub at_ms: u64,
+}
+
+impl Cue {
+    pub fn at(self, at_ms: u64) -> Timed {
+        Timed { cue: self, at_ms }
+    }
+}
+
+impl Timed {
+    /// How long from this cue until `ends_ms`, for effects that end with the celebration.
+    pub fn ms_until(&self, ends_ms: u64) -> u64 {
+        ends_ms.saturating_sub(self.at_ms)
+    }
+}
@@ -0,0 +1,533 @@
+//! Retained-mode widgets for the HUD.
+//!
+//! Every widget owns its state and knows its bounds. Setting a value only marks the
+//! widget dirty when the value really changed, and `draw` does nothing for widgets that
+//! are up to date, so the framebuffer only has to flush what moved.
+
+use core::fmt::Write;
+
+use embassy_time::{Duration, Instant};
+use embedded_graphics::{
+    image::{Image, ImageDrawable},
+    mono_font::MonoTextStyle,
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::{Circle, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle, StrokeAlignment},
+    text::{Alignment, Text},
+};
+use heapless::{String, Vec};
+
+use crate::animation::{Animation, AnimationPlayer};
+
+/// What erased text is filled with when its style has no background.
+const BACKGROUND: Rgb565 = Rgb565::BLACK;
+
+pub trait Widget {
+    fn bounds(&self) -> Rectangle;
+
+    /// Draws the widget if it changed since the last call.
+    fn draw<D>(&mut self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>;
+
+    /// Forces the next `draw` to redraw, e.g. after something else painted over the widget.
+    fn invalidate(&mut self);
+}
+
+/// A filled rectangle or a border, used for the HUD lines and the slot frames.
+pub struct Frame {
+    area: Rectangle,
+    style: PrimitiveStyle<Rgb565>,
+    dirty: bool,
+}
+
+impl Frame {
+    pub fn filled(area: Rectangle, color: Rgb565) -> Self {
+        Frame {
+            area,
+            style: PrimitiveStyle::with_fill(color),
+            dirty: true,
+        }
+    }
+
+    /// A border drawn inside `area`.
+    pub fn outline(area: Rectangle, thickness: u32, color: Rgb565) -> Self {
+        Frame {
+            area,
+            style: PrimitiveStyleBuilder::new()
+                .stroke_color(color)
+                .stroke_width(thickness)
+                .stroke_alignment(StrokeAlignment::Inside)
+                .build(),
+            dirty: true,
+        }
+    }
+
+    pub fn set_color(&mut self, color: Rgb565) {
+        let current = self.style.fill_color.or(self.style.stroke_color);
+        if current != Some(color) {
+            if self.style.fill_color.is_some() {
+                self.style.fill_color = Some(color);
+            } else {
+                self.style.stroke_color = Some(color);
+            }
+            self.dirty = true;
+        }
+    }
+}
+
+impl Widget for Frame {
+    fn bounds(&self) -> Rectangle {
+        self.area
+    }
+
+    fn draw<D>(&mut self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        if self.dirty {
+            self.area.into_styled(self.style).draw(target)?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    fn invalidate(&mut self) {
+        self.dirty = true;
+    }
+}
+
+/// One line of text. Changing it wipes exactly the previously drawn text.
+pub struct Label<'a> {
+    position: Point,
+    style: MonoTextStyle<'a, Rgb565>,
+    alignment: Alignment,
+    text: String<32>,
+    drawn: Rectangle,
+    dirty: bool,
+}
+
+impl<'a> Label<'a> {
+    pub fn new(position: Point, style: MonoTextStyle<'a, Rgb565>) -> Self {
+        Label {
+            position,
+            style,
+            alignment: Alignment::Left,
+            text: String::new(),
+            drawn: Rectangle::zero(),
+            dirty: true,
+        }
+    }
+
+    pub fn with_alignment(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        if self.text != text {
+            self.text.clear();
+            // anything longer than the buffer is cut off
+            for c in text.chars() {
+                if self.text.push(c).is_err() {
+                    break;
+                }
+            }
+            self.dirty = true;
+        }
+    }
+
+    pub fn set_style(&mut self, style: MonoTextStyle<'a, Rgb565>) {
+        self.style = style;
+        self.dirty = true;
+    }
+}
+
+impl Widget for Label<'_> {
+    fn bounds(&self) -> Rectangle {
+        self.drawn
+    }
+
+    fn draw<D>(&mut self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let background = self.style.background_color.unwrap_or(BACKGROUND);
+        target.fill_solid(&self.drawn, background)?;
+
+        let text = Text::with_alignment(&self.text, self.position, self.style, self.alignment);
+        self.drawn = if self.text.is_empty() {
+            Rectangle::zero()
+        } else {
+            text.bounding_box()
+        };
+        text.draw(target)?;
+
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn invalidate(&mut self) {
+        self.dirty = true;
+    }
+}
+
+/// A label showing `prefix` followed by a number, e.g. "BALANCE: 1500".
+pub struct NumberField<'a> {
+    prefix: &'a str,
+    value: Option<i32>,
+    label: Label<'a>,
+}
+
+impl<'a> NumberField<'a> {
+    pub fn new(prefix: &'a str, position: Point, style: MonoTextStyle<'a, Rgb565>) -> Self {
+        NumberField {
+            prefix,
+            value: None,
+            label: Label::new(position, style),
+        }
+    }
+
+    pub fn with_alignment(mut self, alignment: Alignment) -> Self {
+        self.label = self.label.with_alignment(alignment);
+        self
+    }
+
+    pub fn value(&self) -> Option<i32> {
+        self.value
+    }
+
+    pub fn set(&mut self, value: i32) {
+        if self.value != Some(value) {
+            self.value = Some(value);
+
+            let mut text: String<32> = String::new();
+            write!(&mut text, "{}{}", self.prefix, value).ok();
+            self.label.set_text(&text);
+        }
+    }
+}
+
+impl Widget for NumberField<'_> {
+    fn bounds(&self) -> Rectangle {
+        self.label.bounds()
+    }
+
+    fn draw<D>(&mut self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        self.label.draw(target)
+    }
+
+    fn invalidate(&mut self) {
+        self.label.invalidate();
+    }
+}
+
+/// The window of one reel. The strip is the symbol images one above the other and
+/// `position` is how far it has scrolled, in pixels. A highlighted window plays an
+/// animation instead of showing the strip.
+pub struct SlotWindow<'a, I> {
+    area: Rectangle,
+    symbols: &'a [I],
+    position: u32,
+    highlight: Option<AnimationPlayer>,
+    dirty: bool,
+}
+
+impl<'a, I> SlotWindow<'a, I>
+where
+    I: ImageDrawable<Color = Rgb565>,
+{
+    pub fn new(top_left: Point, symbols: &'a [I]) -> Self {
+        SlotWindow {
+            area: Rectangle::new(top_left, symbols[0].size()),
+            symbols,
+            position: 0,
+            highlight: None,
+            dirty: true,
+        }
+    }
+
+    pub fn position(&self) -> u32 {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: u32) {
+        if self.position != position {
+            self.position = position;
+            self.dirty = true;
+        }
+    }
+
+    /// Plays `animation` in the window until `clear_highlight`.
+    pub fn highlight(&mut self, animation: &'static Animation) {
+        self.highlight = Some(AnimationPlayer::new(animation, Instant::now()));
+        self.dirty = true;
+    }
+
+    pub fn clear_highlight(&mut self) {
+        if self.highlight.take().is_some() {
+            self.dirty = true;
+        }
+    }
+
+    /// When the highlight animation needs the next redraw.
+    pub fn next_frame_at(&self) -> Option<Instant> {
+        self.highlight.as_ref().map(|player| player.next_frame_at())
+    }
+}
+
+impl<I> Widget for SlotWindow<'_, I>
+where
+    I: ImageDrawable<Color = Rgb565>,
+{
+    fn bounds(&self) -> Rectangle {
+        self.area
+    }
+
+    fn draw<D>(&mut self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        if let Some(player) = self.highlight.as_mut() {
+            if player.update(Instant::now()) {
+                self.dirty = true;
+            }
+        }
+        if !self.dirty {
+            return Ok(());
+        }
+
+        if let Some(player) = &self.highlight {
+            Image::new(&player.image(), self.area.top_left).draw(target)?;
+            self.dirty = false;
+            return Ok(());
+        }
+
+        // compose from the two strip symbols the window overlaps, clipped to the slot
+        let height = self.area.size.height;
+        let symbol = (self.position / height) as usize % self.symbols.len();
+        let next = (symbol + 1) % self.symbols.len();
+        let offset = (self.position % height) as i32;
+        let top_left = self.area.top_left;
+        let mut window = target.clipped(&self.area);
+
+        Image::new(&self.symbols[symbol], top_left + Point::new(0, offset)).draw(&mut window)?;
+        Image::new(
+            &self.symbols[next],
+            top_left + Point::new(0, offset - height as i32),
+        )
+        .draw(&mut window)?;
+
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn invalidate(&mut self) {
+        self.dirty = true;
+    }
+}
+
+/// A centered message that can take itself down after a while.
+pub struct MessageBanner<'a> {
+    label: Label<'a>,
+    expires: Option<Instant>,
+}
+
+impl<'a> MessageBanner<'a> {
+    pub fn new(center: Point, style: MonoTextStyle<'a, Rgb565>) -> Self {
+        MessageBanner {
+            label: Label::new(center, style).with_alignment(Alignment::Center),
+            expires: None,
+        }
+    }
+
+    pub fn show(&mut self, text: &str, style: MonoTextStyle<'a, Rgb565>) {
+        self.label.set_style(style);
+        self.label.set_text(text);
+        self.expires = None;
+    }
+
+    pub fn show_for(&mut self, text: &str, style: MonoTextStyle<'a, Rgb565>, duration: Duration) {
+        self.show(text, style);
+        self.expires = Some(Instant::now() + duration);
+    }
+
+    pub fn clear(&mut self) {
+        self.label.set_text("");
+        self.expires = None;
+    }
+}
+
+impl Widget for MessageBanner<'_> {
+    fn bounds(&self) -> Rectangle {
+        self.label.bounds()
+    }
+
+    fn draw<D>(&mut self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        if self
+            .expires
+            .is_some_and(|expires| Instant::now() >= expires)
+        {
+            self.clear();
+        }
+        self.label.draw(target)
+    }
+
+    fn invalidate(&mut self) {
+        self.label.invalidate();
+    }
+}
+
+const MAX_COINS: usize = 24;
+const COIN_SIZE: u32 = 10;
+/// Heights and speeds are kept in 1/256 pixels, speeds per millisecond.
+const SUBPIXEL: i32 = 256;
+/// Added to the falling speed every millisecond.
+const GRAVITY: i32 = 1;
+/// 250 pixels per second.
+const COIN_MAX_SPEED: i32 = SUBPIXEL / 4;
+
+struct Coin {
+    x: i32,
+    y: i32,
+    speed: i32,
+}
+
+/// Coins falling over `area` while a win is celebrated.
+///
+/// Coins move on every draw, so the owner erases the areas from `take_stale` and
+/// redraws whatever was underneath before drawing the shower again.
+pub struct CoinShower {
+    area: Rectangle,
+    color: Rgb565,
+    coins: Vec<Coin, MAX_COINS>,
+    drawn: Vec<Rectangle, MAX_COINS>,
+    spawn_every: Option<Duration>,
+    next_spawn: Instant,
+    last_step: Instant,
+    seed: u32,
+}
+
+impl CoinShower {
+    pub fn new(area: Rectangle, color: Rgb565) -> Self {
+        CoinShower {
+            area,
+            color,
+            coins: Vec::new(),
+            drawn: Vec::new(),
+            spawn_every: None,
+            next_spawn: Instant::MIN,
+            last_step: Instant::MIN,
+            seed: 1,
+        }
+    }
+
+    /// Starts dropping coins, nothing happens for zero coins per second.
+    pub fn start(&mut self, coins_per_second: u32) {
+        if coins_per_second == 0 {
+            return;
+        }
+        let now = Instant::now();
+        self.spawn_every = Some(Duration::from_millis(1000 / coins_per_second as u64));
+        self.next_spawn = now;
+        self.last_step = now;
+        self.seed = (now.as_ticks() as u32) | 1;
+    }
+
+    /// Removes all coins, they are erased on the next `take_stale`.
+    pub fn stop(&mut self) {
+        self.spawn_every = None;
+        self.coins.clear();
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.spawn_every.is_some() || !self.coins.is_empty() || !self.drawn.is_empty()
+    }
+
+    /// Where the coins were drawn last time.
+    pub fn take_stale(&mut self) -> Vec<Rectangle, MAX_COINS> {
+        core::mem::take(&mut self.drawn)
+    }
+
+    fn random(&mut self) -> u32 {
+        // xorshift, good enough to scatter coins
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed
+    }
+
+    fn step(&mut self, now: Instant) {
+        let elapsed = (now - self.last_step).as_millis().min(100) as i32;
+        self.last_step = now;
+
+        let bottom = (self.area.top_left.y + self.area.size.height as i32) * SUBPIXEL;
+        for coin in self.coins.iter_mut() {
+            coin.speed = (coin.speed + GRAVITY * elapsed).min(COIN_MAX_SPEED);
+            coin.y += coin.speed * elapsed;
+        }
+        self.coins.retain(|coin| coin.y < bottom);
+
+        if let Some(every) = self.spawn_every {
+            while now >= self.next_spawn {
+                self.next_spawn += every;
+                let width = self.area.size.width.saturating_sub(COIN_SIZE).max(1);
+                let x = self.area.top_left.x + (self.random() % width) as i32;
+                let speed = SUBPIXEL / 16 + (self.random() % (SUBPIXEL as u32 / 8)) as i32;
+                let coin = Coin {
+                    x,
+                    y: (self.area.top_left.y - COIN_SIZE as i32) * SUBPIXEL,
+                    speed,
+                };
+                if self.coins.push(coin).is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+impl Widget for CoinShower {
+    fn bounds(&self) -> Rectangle {
+        self.area
+    }
+
+    fn draw<D>(&mut self, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        if self.spawn_every.is_none() && self.coins.is_empty() {
+            return Ok(());
+        }
+        self.step(Instant::now());
+
+        let fill = PrimitiveStyle::with_fill(self.color);
+        let mut clipped = target.clipped(&self.area);
+        self.drawn.clear();
+        for coin in self.coins.iter() {
+            let coin = Circle::new(Point::new(coin.x, coin.y / SUBPIXEL), COIN_SIZE);
+            coin.into_styled(fill).draw(&mut clipped)?;
+            let drawn = coin.bounding_box().intersection(&self.area);
+            if !drawn.is_zero_sized() {
+                self.drawn.push(drawn).ok();
+            }
+        }
+        Ok(())
+    }
+
+    fn invalidate(&mut self) {}
+}
@@ -0,0 +1,83 @@
+//! How big a win is, which decides how it is celebrated.
+
+use embassy_time::Duration;
+
+/// Win tiers by multiple of the bet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, defmt::Format)]
+pub enum WinTier {
+    Small,
+    Big,
+    Mega,
+    Jackpot,
+}
+
+const BIG_MULTIPLE: i32 = 10;
+const MEGA_MULTIPLE: i32 = 50;
+const JACKPOT_MULTIPLE: i32 = 1000;
+
+impl WinTier {
+    pub fn for_win(win: i32, bet: i32) -> Self {
+        let multiple = win / bet.max(1);
+        if multiple >= JACKPOT_MULTIPLE {
+            WinTier::Jackpot
+        } else if multiple >= MEGA_MULTIPLE {
+            WinTier::Mega
+        } else if multiple >= BIG_MULTIPLE {
+            WinTier::Big
+        } else {
+            WinTier::Small
+        }
+    }
+
+    /// How long the win meter counts up, the LEDs and the buzzer follow it.
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(match self {
+            WinTier::Small => 800,
+            WinTier::Big => 2000,
+            WinTier::Mega => 3500,
+            WinTier::Jackpot => 6000,
+        })
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            WinTier::Small => "THAT'S A WIN!!!",
+            WinTier::Big => "BIG WIN!",
+            WinTier::Mega => "MEGA WIN!!",
+            WinTier::Jackpot => "JACKPOT!!!",
+        }
+    }
+
+    /// Coins spawned per second during the coin shower, none for small wins.
+    pub fn coins_per_second(&self) -> u32 {
+        match self {
+            WinTier::Small => 0,
+            WinTier::Big => 10,
+            WinTier::Mega => 25,
+            WinTier::Jackpot => 50,
+        }
+    }
+}
+
+impl From<WinTier> for arcade_link::Tier {
+    fn from(tier: WinTier) -> Self {
+        match tier {
+            WinTier::Small => arcade_link::Tier::Small,
+            WinTier::Big => arcade_link::Tier::Big,
+            WinTier::Mega => arcade_link::Tier::Mega,
+            WinTier::Jackpot => arcade_link::Tier::Jackpot,
+        }
+    }
+}
+
+/// What the win meter shows `elapsed` into counting up to `win`.
+pub fn count_up(win: i32, elapsed: Duration, duration: Duration) -> i32 {
+    if elapsed >= duration {
+        return win;
+    }
+    // ease out, the meter slows down as it gets close
+    let t = elapsed.as_millis() as i64;
+    let d = duration.as_millis().max(1) as i64;
+    let eased = t * (2 * d - t);
+    (win as i64 * eased / (d * d)) as i32
+}
@@ -0,0 +1,78 @@
+//! Prints the frames of a strip effect, to look at or to diff.
+//!
+//! ```text
+//! cargo run --example render -- rainbow
+//! cargo run --example render -- win-jackpot --frames 40 --every 50
+//! cargo run --example render -- comet --text
+//! ```
+//!
+//! Every frame is one line, a colored block per LED in a terminal with true color,
+//! or the hex colors with `--text`. The colors are what goes to the strip, after
+//! the gamma correction.
+
+use std::{env, process};
+
+use arcade_strip::{Effect, Rgb, STRIP_LEN, Show, Tier};
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut effect = None;
+    let mut frames = 25;
+    let mut every = 40;
+    let mut text = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => frames = number(args.next()),
+            "--every" => every = number(args.next()),
+            "--text" => text = true,
+            name => effect = Some(parse(name).unwrap_or_else(|| usage())),
+        }
+    }
+    let effect = effect.unwrap_or_else(|| usage());
+
+    let mut show = Show::new(Effect::Off);
+    show.play(effect, 0);
+    let mut leds = [Rgb::OFF; STRIP_LEN];
+    for frame in 0..frames {
+        let now = frame * every;
+        show.render(now, &mut leds);
+        print!("{:>6} ms ", now);
+        for led in &leds {
+            if text {
+                print!(" {:02x}{:02x}{:02x}", led.r, led.g, led.b);
+            } else {
+                print!("\x1b[48;2;{};{};{}m \x1b[0m", led.r, led.g, led.b);
+            }
+        }
+        println!();
+    }
+}
+
+fn parse(name: &str) -> Option<Effect> {
+    Some(match name {
+        "off" => Effect::Off,
+        "comet" => Effect::Comet,
+        "rainbow" => Effect::Rainbow,
+        "flash" => Effect::Solid(Rgb::WHITE),
+        "win-small" => Effect::Win(Tier::Small),
+        "win-big" => Effect::Win(Tier::Big),
+        "win-mega" => Effect::Win(Tier::Mega),
+        "win-jackpot" => Effect::Win(Tier::Jackpot),
+        "attract" => Effect::Attract,
+        _ => return None,
+    })
+}
+
+fn number(arg: Option<String>) -> u64 {
+    arg.and_then(|arg| arg.parse().ok())
+        .unwrap_or_else(|| usage())
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: render <off|comet|rainbow|flash|win-small|win-big|win-mega|win-jackpot|attract> \
+         [--frames N] [--every MS] [--text]"
+    );
+    process::exit(2);
+}
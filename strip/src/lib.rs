@@ -0,0 +1,451 @@
+//! Effects for the WS2812 strip around the cabinet.
+//!
+//! An `Effect` draws the whole strip for a point in time: a rainbow chase while the
+//! reels spin, a gold flash that turns into the color of the win tier, a comet going
+//! round between games and a mix of chases in attract mode. `Show` decides which
+//! effect runs: a new effect replaces the old one right away, short flashes go over
+//! whatever runs, and it goes back to the idle effect when an effect ends or runs out
+//! of time.
+//!
+//! Only `core` is used, the firmware sends the frames to the strip through PIO and
+//! `examples/render.rs` prints them on the host.
+
+#![no_std]
+
+pub use arcade_link::Tier;
+
+/// LEDs on the strip.
+pub const STRIP_LEN: usize = 60;
+/// How often an animated effect is redrawn, 50 frames per second.
+pub const FRAME_MS: u64 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const OFF: Rgb = Rgb::new(0, 0, 0);
+    pub const WHITE: Rgb = Rgb::new(255, 255, 255);
+    pub const GOLD: Rgb = Rgb::new(255, 160, 0);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb { r, g, b }
+    }
+
+    /// `self` at `level` out of 255.
+    pub fn scale(self, level: u8) -> Self {
+        let scale = |c: u8| (c as u16 * level as u16 / 255) as u8;
+        Rgb::new(scale(self.r), scale(self.g), scale(self.b))
+    }
+
+    /// Gamma corrected for the strip, which is linear in duty cycle while the eye
+    /// is not.
+    pub fn gamma(self) -> Self {
+        Rgb::new(gamma(self.r), gamma(self.g), gamma(self.b))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Effect {
+    Off,
+    /// A comet with a fading tail going round, between games.
+    Comet,
+    /// The colors of the rainbow running along the strip, while the reels spin.
+    Rainbow,
+    /// The whole strip in one color, a reel stop flashes it white.
+    Solid(Rgb),
+    /// Gold flashes for `GOLD_FLASH_MS`, then a chase in the tier's color that gets
+    /// faster for bigger wins.
+    Win(Tier),
+    /// A block bouncing from end to end, then every other LED alternating, in a new
+    /// color every round. Attract mode plays it until somebody comes to play.
+    Attract,
+}
+
+const COMET_STEP_MS: u32 = 40;
+const COMET_TAIL: usize = 8;
+const COMET_COLOR: Rgb = Rgb::new(80, 140, 255);
+const RAINBOW_STEP_MS: u32 = 8;
+const GOLD_FLASH_MS: u32 = 600;
+const GOLD_BLINK_MS: u32 = 100;
+const ATTRACT_BOUNCE_MS: u32 = 2400;
+const ATTRACT_ALTERNATE_MS: u32 = 400;
+const ATTRACT_ROUND_MS: u32 = ATTRACT_BOUNCE_MS + 4 * ATTRACT_ALTERNATE_MS;
+const ATTRACT_BLOCK: usize = 6;
+
+impl Effect {
+    /// Draws the effect `elapsed_ms` after it started.
+    pub fn render(&self, elapsed_ms: u32, leds: &mut [Rgb]) {
+        let len = leds.len().max(1);
+        match *self {
+            Effect::Off => leds.fill(Rgb::OFF),
+            Effect::Solid(color) => leds.fill(color),
+            Effect::Comet => {
+                let head = (elapsed_ms / COMET_STEP_MS) as usize % len;
+                for (i, led) in leds.iter_mut().enumerate() {
+                    // how far behind the head, going round the end of the strip
+                    let behind = (head + len - i) % len;
+                    *led = if behind < COMET_TAIL {
+                        COMET_COLOR.scale((255 - behind * 255 / COMET_TAIL) as u8)
+                    } else {
+                        Rgb::OFF
+                    };
+                }
+            }
+            Effect::Rainbow => {
+                let shift = (elapsed_ms / RAINBOW_STEP_MS) as usize;
+                for (i, led) in leds.iter_mut().enumerate() {
+                    *led = wheel(((i * 256 / len + shift) % 256) as u8);
+                }
+            }
+            Effect::Win(tier) => {
+                if elapsed_ms < GOLD_FLASH_MS {
+                    let on = (elapsed_ms / GOLD_BLINK_MS).is_multiple_of(2);
+                    leds.fill(if on { Rgb::GOLD } else { Rgb::OFF });
+                    return;
+                }
+                // every third LED lit, moving along
+                let step = ((elapsed_ms - GOLD_FLASH_MS) / tier_step_ms(tier)) as usize;
+                let color = tier_color(tier);
+                for (i, led) in leds.iter_mut().enumerate() {
+                    *led = if i % 3 == step % 3 {
+                        color
+                    } else {
+                        color.scale(24)
+                    };
+                }
+            }
+            Effect::Attract => {
+                let round = elapsed_ms / ATTRACT_ROUND_MS;
+                let at = elapsed_ms % ATTRACT_ROUND_MS;
+                let color = wheel((round * 85 % 256) as u8);
+                if at < ATTRACT_BOUNCE_MS {
+                    // there and back again
+                    let span = len.saturating_sub(ATTRACT_BLOCK).max(1);
+                    let moved = at as usize * 2 * span / ATTRACT_BOUNCE_MS as usize;
+                    let start = if moved <= span {
+                        moved
+                    } else {
+                        2 * span - moved
+                    };
+                    for (i, led) in leds.iter_mut().enumerate() {
+                        let lit = (start..start + ATTRACT_BLOCK).contains(&i);
+                        *led = if lit { color } else { Rgb::OFF };
+                    }
+                } else {
+                    let step = ((at - ATTRACT_BOUNCE_MS) / ATTRACT_ALTERNATE_MS) as usize;
+                    for (i, led) in leds.iter_mut().enumerate() {
+                        *led = if i % 2 == step % 2 { color } else { Rgb::OFF };
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether the effect changes over time and has to be redrawn.
+    pub fn is_animated(&self) -> bool {
+        !matches!(self, Effect::Off | Effect::Solid(_))
+    }
+}
+
+pub fn tier_color(tier: Tier) -> Rgb {
+    match tier {
+        Tier::Small => Rgb::new(0, 255, 60),
+        Tier::Big => Rgb::GOLD,
+        Tier::Mega => Rgb::new(180, 0, 255),
+        Tier::Jackpot => Rgb::new(255, 0, 40),
+    }
+}
+
+fn tier_step_ms(tier: Tier) -> u32 {
+    match tier {
+        Tier::Small => 120,
+        Tier::Big => 90,
+        Tier::Mega => 60,
+        Tier::Jackpot => 40,
+    }
+}
+
+/// Red to green to blue and back to red as `position` goes round.
+pub fn wheel(position: u8) -> Rgb {
+    let p = position as u16 * 3;
+    match position {
+        0..=84 => Rgb::new((255 - p) as u8, p as u8, 0),
+        85..=169 => {
+            let p = p - 255;
+            Rgb::new(0, (255 - p) as u8, p as u8)
+        }
+        _ => {
+            let p = p - 510;
+            Rgb::new(p as u8, 0, (255 - p) as u8)
+        }
+    }
+}
+
+/// About `level` to the power of 2.2, 0.8 x^2 + 0.2 x^3.
+fn gamma(level: u8) -> u8 {
+    let x = level as u32;
+    ((4 * x * x * 255 + x * x * x) / (5 * 255 * 255)) as u8
+}
+
+#[derive(Clone, Copy)]
+struct Playing {
+    effect: Effect,
+    started_ms: u64,
+    /// Back to idle at this time.
+    ends_ms: Option<u64>,
+}
+
+/// Which effect runs.
+pub struct Show {
+    idle: Effect,
+    current: Playing,
+    flash: Option<Playing>,
+    /// Percent, scales every color.
+    brightness: u8,
+}
+
+impl Show {
+    pub const fn new(idle: Effect) -> Self {
+        Show {
+            idle,
+            current: Playing {
+                effect: idle,
+                started_ms: 0,
+                ends_ms: None,
+            },
+            flash: None,
+            brightness: 100,
+        }
+    }
+
+    /// Switches to `effect` right away, until another one is played.
+    pub fn play(&mut self, effect: Effect, now_ms: u64) {
+        self.current = Playing {
+            effect,
+            started_ms: now_ms,
+            ends_ms: None,
+        };
+    }
+
+    /// Switches to `effect` right away, back to idle after `ms` at the latest.
+    pub fn play_for(&mut self, effect: Effect, now_ms: u64, ms: u64) {
+        self.current = Playing {
+            effect,
+            started_ms: now_ms,
+            ends_ms: Some(now_ms + ms),
+        };
+    }
+
+    /// Shows `effect` for `ms` over the current one, which keeps running underneath.
+    pub fn flash(&mut self, effect: Effect, now_ms: u64, ms: u64) {
+        self.flash = Some(Playing {
+            effect,
+            started_ms: now_ms,
+            ends_ms: Some(now_ms + ms),
+        });
+    }
+
+    pub fn idle(&mut self, now_ms: u64) {
+        self.play(self.idle, now_ms);
+    }
+
+    /// Scales every effect, e.g. to dim the strip in attract mode.
+    pub fn set_brightness(&mut self, percent: u8) {
+        self.brightness = percent.min(100);
+    }
+
+    /// Draws the strip at `now_ms`, gamma corrected and ready to send. Returns when
+    /// it has to be drawn again, `None` if only the next event changes it.
+    pub fn render(&mut self, now_ms: u64, leds: &mut [Rgb]) -> Option<u64> {
+        if self.current.ends_ms.is_some_and(|ends| now_ms >= ends) {
+            self.current = Playing {
+                effect: self.idle,
+                started_ms: now_ms,
+                ends_ms: None,
+            };
+        }
+        if self
+            .flash
+            .is_some_and(|flash| flash.ends_ms.is_some_and(|ends| now_ms >= ends))
+        {
+            self.flash = None;
+        }
+
+        let playing = self.flash.unwrap_or(self.current);
+        let elapsed = now_ms.saturating_sub(playing.started_ms) as u32;
+        playing.effect.render(elapsed, leds);
+
+        let level = (self.brightness as u16 * 255 / 100) as u8;
+        for led in leds.iter_mut() {
+            *led = led.scale(level).gamma();
+        }
+
+        let redraw = playing.effect.is_animated().then_some(now_ms + FRAME_MS);
+        [
+            redraw,
+            self.current.ends_ms,
+            self.flash.and_then(|f| f.ends_ms),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(show: &mut Show, now_ms: u64) -> [Rgb; STRIP_LEN] {
+        let mut leds = [Rgb::OFF; STRIP_LEN];
+        show.render(now_ms, &mut leds);
+        leds
+    }
+
+    /// What `effect` shows `elapsed_ms` after it started, at full brightness.
+    fn effect_frame(effect: Effect, elapsed_ms: u64) -> [Rgb; STRIP_LEN] {
+        let mut show = Show::new(effect);
+        frame(&mut show, elapsed_ms)
+    }
+
+    fn lit(leds: &[Rgb]) -> impl Iterator<Item = usize> + '_ {
+        leds.iter()
+            .enumerate()
+            .filter(|(_, led)| **led != Rgb::OFF)
+            .map(|(i, _)| i)
+    }
+
+    #[test]
+    fn the_comet_tail_follows_the_head_round_the_strip() {
+        let leds = effect_frame(Effect::Comet, 10 * COMET_STEP_MS as u64);
+        assert_eq!(leds[10], COMET_COLOR.gamma());
+        assert!(lit(&leds).eq(10 - (COMET_TAIL - 1)..=10));
+        // the tail fades away from the head
+        assert!(leds[3].b < leds[4].b && leds[9].b < leds[10].b);
+
+        // at the start of the strip the tail is at its end
+        let leds = effect_frame(Effect::Comet, STRIP_LEN as u64 * COMET_STEP_MS as u64);
+        assert_eq!(leds[0], COMET_COLOR.gamma());
+        assert!(lit(&leds).eq((0..1).chain(STRIP_LEN - (COMET_TAIL - 1)..STRIP_LEN)));
+    }
+
+    #[test]
+    fn the_rainbow_wraps_round() {
+        let start = effect_frame(Effect::Rainbow, 0);
+        assert_eq!(start[0], Rgb::new(255, 0, 0));
+        assert_ne!(start[0], start[STRIP_LEN / 2]);
+        // one LED further along every step, the same again after the whole wheel
+        let later = effect_frame(Effect::Rainbow, RAINBOW_STEP_MS as u64 * 30);
+        assert_ne!(later, start);
+        assert_eq!(
+            effect_frame(Effect::Rainbow, RAINBOW_STEP_MS as u64 * 256),
+            start
+        );
+        // and the end of the wheel runs into its start
+        assert_eq!(wheel(255), wheel(0));
+    }
+
+    #[test]
+    fn a_win_flashes_gold_then_chases_in_the_tier_color() {
+        let win = Effect::Win(Tier::Mega);
+        assert!(
+            effect_frame(win, 0)
+                .iter()
+                .all(|&led| led == Rgb::GOLD.gamma())
+        );
+        let blink = effect_frame(win, GOLD_BLINK_MS as u64);
+        assert!(blink.iter().all(|&led| led == Rgb::OFF));
+        // still the whole strip in one color right before the chase
+        let last = effect_frame(win, GOLD_FLASH_MS as u64 - 1);
+        assert!(last.iter().all(|&led| led == last[0]));
+
+        let color = tier_color(Tier::Mega);
+        let chase = effect_frame(win, GOLD_FLASH_MS as u64);
+        for (i, &led) in chase.iter().enumerate() {
+            let expected = if i % 3 == 0 { color } else { color.scale(24) };
+            assert_eq!(led, expected.gamma(), "led {}", i);
+        }
+        let step = GOLD_FLASH_MS as u64 + tier_step_ms(Tier::Mega) as u64;
+        assert_eq!(effect_frame(win, step)[1], color.gamma());
+        // bigger wins chase faster
+        assert!(tier_step_ms(Tier::Jackpot) < tier_step_ms(Tier::Small));
+    }
+
+    #[test]
+    fn a_flash_goes_back_to_what_ran_underneath() {
+        let mut show = Show::new(Effect::Off);
+        show.play(Effect::Comet, 0);
+        show.flash(Effect::Solid(Rgb::WHITE), 100, 80);
+        assert!(frame(&mut show, 150).iter().all(|&led| led == Rgb::WHITE));
+        // the comet kept going while the flash was up
+        assert_eq!(frame(&mut show, 180), effect_frame(Effect::Comet, 180));
+        assert_eq!(frame(&mut show, 400), effect_frame(Effect::Comet, 400));
+    }
+
+    #[test]
+    fn play_for_goes_back_to_idle() {
+        let mut show = Show::new(Effect::Comet);
+        show.play_for(Effect::Rainbow, 1000, 500);
+        assert_eq!(frame(&mut show, 1200), effect_frame(Effect::Rainbow, 200));
+        // the idle effect starts over when it comes back
+        assert_eq!(frame(&mut show, 1500), effect_frame(Effect::Comet, 0));
+        assert_eq!(frame(&mut show, 1600), effect_frame(Effect::Comet, 100));
+
+        show.play(Effect::Rainbow, 2000);
+        assert_eq!(frame(&mut show, 9000), effect_frame(Effect::Rainbow, 7000));
+        show.idle(9000);
+        assert_eq!(frame(&mut show, 9000), effect_frame(Effect::Comet, 0));
+    }
+
+    #[test]
+    fn brightness_scales_every_color() {
+        let mut show = Show::new(Effect::Solid(Rgb::WHITE));
+        show.set_brightness(50);
+        let dimmed = Rgb::WHITE.scale(127).gamma();
+        assert!(frame(&mut show, 0).iter().all(|&led| led == dimmed));
+        assert!(dimmed.r < 64);
+        show.set_brightness(0);
+        assert!(frame(&mut show, 0).iter().all(|&led| led == Rgb::OFF));
+        show.set_brightness(250);
+        assert!(frame(&mut show, 0).iter().all(|&led| led == Rgb::WHITE));
+        assert_eq!(Rgb::new(200, 100, 50).scale(128), Rgb::new(100, 50, 25));
+    }
+
+    #[test]
+    fn render_says_when_to_draw_again() {
+        let mut leds = [Rgb::OFF; STRIP_LEN];
+        let mut show = Show::new(Effect::Off);
+        // nothing changes until the next event
+        assert_eq!(show.render(0, &mut leds), None);
+        show.play_for(Effect::Solid(Rgb::GOLD), 100, 400);
+        assert_eq!(show.render(100, &mut leds), Some(500));
+        // animated effects every frame, but the end of a flash comes first
+        show.play(Effect::Comet, 600);
+        assert_eq!(show.render(600, &mut leds), Some(600 + FRAME_MS));
+        show.flash(Effect::Solid(Rgb::WHITE), 700, 5);
+        assert_eq!(show.render(700, &mut leds), Some(705));
+        assert_eq!(show.render(705, &mut leds), Some(705 + FRAME_MS));
+    }
+
+    #[test]
+    fn attract_mode_bounces_then_alternates() {
+        let start = effect_frame(Effect::Attract, 0);
+        assert!(lit(&start).eq(0..ATTRACT_BLOCK));
+        let far_end = effect_frame(Effect::Attract, ATTRACT_BOUNCE_MS as u64 / 2);
+        assert!(lit(&far_end).eq(STRIP_LEN - ATTRACT_BLOCK..STRIP_LEN));
+        let alternate = effect_frame(Effect::Attract, ATTRACT_BOUNCE_MS as u64);
+        assert!(lit(&alternate).eq((0..STRIP_LEN).step_by(2)));
+        let next = ATTRACT_BOUNCE_MS as u64 + ATTRACT_ALTERNATE_MS as u64;
+        assert!(lit(&effect_frame(Effect::Attract, next)).eq((1..STRIP_LEN).step_by(2)));
+        // the next round is another color
+        let round = effect_frame(Effect::Attract, ATTRACT_ROUND_MS as u64);
+        assert!(lit(&round).eq(0..ATTRACT_BLOCK));
+        assert_ne!(round[0], start[0]);
+    }
+}
@@ -0,0 +1,230 @@
+//! CSV and JSON for what the tool pulls off the machine, and the CSV files it reads.
+
+use std::fmt::Write as _;
+
+use arcade_console::audit::{Game, Meters};
+use arcade_console::{UidHex, parse_uid};
+
+use crate::machine::{Card, PayLine};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+pub fn meters(meters: &Meters, format: Format) -> String {
+    let entries = meters.entries();
+    match format {
+        Format::Csv => table(
+            "meter,value",
+            entries
+                .iter()
+                .map(|(name, value)| format!("{},{}", name, value)),
+        ),
+        Format::Json => {
+            let fields: Vec<String> = entries
+                .iter()
+                .map(|(name, value)| format!("\"{}\": {}", name, value))
+                .collect();
+            format!("{{{}}}\n", fields.join(", "))
+        }
+    }
+}
+
+pub fn history(games: &[Game], format: Format) -> String {
+    match format {
+        Format::Csv => table(
+            "number,bet,reel1,reel2,reel3,win",
+            games.iter().map(|game| {
+                let [a, b, c] = game.reels;
+                format!(
+                    "{},{},{},{},{},{}",
+                    game.number, game.bet, a, b, c, game.win
+                )
+            }),
+        ),
+        Format::Json => array(games.iter().map(|game| {
+            format!(
+                "{{\"number\": {}, \"bet\": {}, \"reels\": [{}, {}, {}], \"win\": {}}}",
+                game.number, game.bet, game.reels[0], game.reels[1], game.reels[2], game.win
+            )
+        })),
+    }
+}
+
+pub fn cards(cards: &[Card], format: Format) -> String {
+    match format {
+        Format::Csv => table(
+            "uid,balance",
+            cards
+                .iter()
+                .map(|card| format!("{},{}", UidHex(card.uid), card.balance)),
+        ),
+        Format::Json => array(cards.iter().map(|card| {
+            format!(
+                "{{\"uid\": \"{}\", \"balance\": {}}}",
+                UidHex(card.uid),
+                card.balance
+            )
+        })),
+    }
+}
+
+pub fn paytable(lines: &[PayLine], format: Format) -> String {
+    match format {
+        Format::Csv => table(
+            "line,combo,symbol,pays",
+            lines
+                .iter()
+                .map(|line| format!("{},{},{},{}", line.line, line.combo, line.symbol, line.pays)),
+        ),
+        Format::Json => array(lines.iter().map(|line| {
+            format!(
+                "{{\"line\": {}, \"combo\": \"{}\", \"symbol\": \"{}\", \"pays\": {}}}",
+                line.line, line.combo, line.symbol, line.pays
+            )
+        })),
+    }
+}
+
+fn table(header: &str, rows: impl Iterator<Item = String>) -> String {
+    let mut text = format!("{}\n", header);
+    for row in rows {
+        let _ = writeln!(text, "{}", row);
+    }
+    text
+}
+
+fn array(items: impl Iterator<Item = String>) -> String {
+    let items: Vec<String> = items.map(|item| format!("  {}", item)).collect();
+    if items.is_empty() {
+        return "[]\n".into();
+    }
+    format!("[\n{}\n]\n", items.join(",\n"))
+}
+
+/// `uid,balance` rows. A header, empty lines and lines starting with `#` are skipped.
+pub fn read_cards(text: &str) -> Result<Vec<Card>, String> {
+    rows(text)
+        .map(|(number, fields)| match fields[..] {
+            [uid, balance] => Ok(Card {
+                uid: parse_uid(uid).map_err(|e| format!("line {}: {}", number, e))?,
+                balance: balance
+                    .parse()
+                    .map_err(|_| format!("line {}: bad balance {:?}", number, balance))?,
+            }),
+            _ => Err(format!("line {}: expected uid,balance", number)),
+        })
+        .collect()
+}
+
+/// The paytable as `paytable --format csv` writes it, the line in the first column and
+/// what it pays in the last, or just `line,pays`.
+pub fn read_paytable(text: &str) -> Result<Vec<(u8, u32)>, String> {
+    rows(text)
+        .map(|(number, fields)| {
+            let (Some(line), Some(pays)) = (fields.first(), fields.last()) else {
+                return Err(format!("line {}: expected line,pays", number));
+            };
+            if fields.len() < 2 {
+                return Err(format!("line {}: expected line,pays", number));
+            }
+            let line = line
+                .parse()
+                .map_err(|_| format!("line {}: bad line number {:?}", number, line))?;
+            let pays = pays
+                .parse()
+                .map_err(|_| format!("line {}: bad payout {:?}", number, pays))?;
+            Ok((line, pays))
+        })
+        .collect()
+}
+
+/// The fields of every row with its line number, without the header.
+fn rows(text: &str) -> impl Iterator<Item = (usize, Vec<&str>)> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| (number, line.split(',').map(str::trim).collect::<Vec<_>>()))
+        // a header starts with a name, every row with a uid or a line number
+        .filter(|(number, fields)| {
+            *number > 1 || fields[0].chars().all(|c| c.is_ascii_hexdigit() || c == ':')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meters_as_csv_and_json() {
+        let meters = Meters {
+            games: 2,
+            coin_in: 1000,
+            ..Meters::default()
+        };
+        let csv = super::meters(&meters, Format::Csv);
+        assert!(csv.starts_with("meter,value\ngames,2\ncoin_in,1000\n"));
+        let json = super::meters(&meters, Format::Json);
+        assert!(json.starts_with("{\"games\": 2, \"coin_in\": 1000, "));
+    }
+
+    #[test]
+    fn history_as_csv_and_json() {
+        let games = [Game {
+            number: 1,
+            bet: 500,
+            reels: [0, 0, 4],
+            win: 7500,
+        }];
+        assert_eq!(
+            history(&games, Format::Csv),
+            "number,bet,reel1,reel2,reel3,win\n1,500,0,0,4,7500\n"
+        );
+        assert_eq!(
+            history(&games, Format::Json),
+            "[\n  {\"number\": 1, \"bet\": 500, \"reels\": [0, 0, 4], \"win\": 7500}\n]\n"
+        );
+        assert_eq!(history(&[], Format::Json), "[]\n");
+    }
+
+    #[test]
+    fn reads_cards_with_or_without_a_header() {
+        let cards = read_cards("uid,balance\n50f36d14, 80000\n\n# spare\n0a:55:34:00,0\n");
+        assert_eq!(
+            cards,
+            Ok(vec![
+                Card {
+                    uid: [0x50, 0xf3, 0x6d, 0x14],
+                    balance: 80000
+                },
+                Card {
+                    uid: [0x0a, 0x55, 0x34, 0x00],
+                    balance: 0
+                },
+            ])
+        );
+        assert_eq!(read_cards("50f36d14,1\n").unwrap().len(), 1);
+        assert!(read_cards("50f36d14\n").is_err());
+        assert!(read_cards("50f36d14,-1\n").is_err());
+    }
+
+    #[test]
+    fn reads_back_the_paytable_it_writes() {
+        let lines = [PayLine {
+            line: 8,
+            combo: "any".into(),
+            symbol: "rusty_crab".into(),
+            pays: 100,
+        }];
+        let csv = paytable(&lines, Format::Csv);
+        assert_eq!(read_paytable(&csv), Ok(vec![(8, 100)]));
+        assert_eq!(
+            read_paytable("0,400000\n8,200\n"),
+            Ok(vec![(0, 400000), (8, 200)])
+        );
+        assert!(read_paytable("line,pays\nx,1\n").is_err());
+    }
+}
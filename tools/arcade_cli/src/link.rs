@@ -0,0 +1,150 @@
+//! Sends console commands and reads the answers, see `arcade_console` for the protocol.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The machine answered `ERR` with this reason.
+    Device(String),
+    /// The answer wasn't what the command gives.
+    Protocol(String),
+    /// What was written to the EEPROM at this address reads back different.
+    Verify(u16),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Device(reason) => write!(f, "the machine said: {}", reason),
+            Error::Protocol(what) => write!(f, "unexpected answer: {}", what),
+            Error::Verify(addr) => write!(f, "the EEPROM at {:04x} reads back different", addr),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+/// The console over anything that reads and writes bytes, the serial port or the mock.
+pub struct Link<P> {
+    port: P,
+    /// Bytes read past the end of the last line.
+    pending: Vec<u8>,
+}
+
+impl<P: Read + Write> Link<P> {
+    pub fn new(port: P) -> Self {
+        Link {
+            port,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Sends `command` and returns the lines before `OK`.
+    pub fn run(&mut self, command: &str) -> Result<Vec<String>, Error> {
+        let (lines, result) = self.answer(command)?;
+        result.map(|_| lines)
+    }
+
+    /// Sends `command` and returns the lines of the answer, also when it ends with `ERR`.
+    pub fn answer(&mut self, command: &str) -> Result<(Vec<String>, Result<(), Error>), Error> {
+        self.port.write_all(command.as_bytes())?;
+        self.port.write_all(b"\r\n")?;
+        self.port.flush()?;
+
+        let mut lines = Vec::new();
+        loop {
+            let line = self.read_line()?;
+            if line == "OK" {
+                return Ok((lines, Ok(())));
+            }
+            if let Some(reason) = line.strip_prefix("ERR") {
+                return Ok((lines, Err(Error::Device(reason.trim().to_string()))));
+            }
+            lines.push(line);
+        }
+    }
+
+    /// The next line without its `\r\n`.
+    fn read_line(&mut self) -> Result<String, Error> {
+        loop {
+            if let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = self.pending.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                return Ok(line.trim_end_matches(['\r', '\n']).to_string());
+            }
+            let mut buffer = [0u8; 64];
+            let count = self.port.read(&mut buffer)?;
+            if count == 0 {
+                return Err(Error::Io(io::ErrorKind::UnexpectedEof.into()));
+            }
+            self.pending.extend_from_slice(&buffer[..count]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Answers from `input`, keeps what was written.
+    struct Script {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Script {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            // a few bytes at a time, like USB packets
+            let len = buf.len().min(5);
+            self.input.read(&mut buf[..len])
+        }
+    }
+
+    impl Write for Script {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn link(answers: &str) -> Link<Script> {
+        Link::new(Script {
+            input: Cursor::new(answers.as_bytes().to_vec()),
+            output: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn reads_lines_until_ok() {
+        let mut link = link("balance 500\r\ncard in\r\nOK\r\nseed 7\r\nOK\r\n");
+        assert_eq!(link.run("balance").unwrap(), ["balance 500", "card in"]);
+        assert_eq!(link.run("rng seed").unwrap(), ["seed 7"]);
+        assert_eq!(link.port.output, b"balance\r\nrng seed\r\n");
+    }
+
+    #[test]
+    fn err_is_a_device_error() {
+        let mut link = link("ERR login first\r\n");
+        match link.run("reboot") {
+            Err(Error::Device(reason)) => assert_eq!(reason, "login first"),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn an_answer_cut_short_is_an_error() {
+        let mut link = link("games 3\r\n");
+        assert!(matches!(link.run("meters"), Err(Error::Io(_))));
+    }
+}
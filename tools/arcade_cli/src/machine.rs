@@ -0,0 +1,395 @@
+//! What the tool asks the machine for, on top of the console commands.
+
+use std::io::{Read, Write};
+use std::ops::Range;
+
+use arcade_console::audit::{Game, Meters};
+use arcade_console::{EEPROM_READ_MAX, EEPROM_WRITE_MAX, Hex, Uid, UidHex, parse_uid};
+use arcade_eeprom::{
+    CARDS_ADDR, CONFIG_ADDR, CONFIG_SIZE, EEPROM_SIZE, HEADER_SIZE, PAYTABLE_ADDR, PAYTABLE_SIZE,
+    PIN_ADDR, PIN_SIZE, RecordError, card_addr, read_header, read_pin,
+};
+
+use crate::link::{Error, Link};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Card {
+    pub uid: Uid,
+    pub balance: u32,
+}
+
+/// A paytable line as `paytable` prints it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PayLine {
+    pub line: u8,
+    /// `three`, `two` or `any`.
+    pub combo: String,
+    pub symbol: String,
+    /// For the base bet.
+    pub pays: u32,
+}
+
+pub fn meters<P: Read + Write>(link: &mut Link<P>) -> Result<Meters, Error> {
+    let mut meters = Meters::default();
+    for line in link.run("meters")? {
+        let known = match line.split_once(' ') {
+            Some((name, value)) => value.parse().is_ok_and(|value| meters.set(name, value)),
+            None => false,
+        };
+        if !known {
+            return Err(Error::Protocol(line));
+        }
+    }
+    Ok(meters)
+}
+
+pub fn history<P: Read + Write>(link: &mut Link<P>) -> Result<Vec<Game>, Error> {
+    link.run("log dump")?
+        .into_iter()
+        .map(|line| Game::parse(&line).ok_or(Error::Protocol(line)))
+        .collect()
+}
+
+pub fn cards<P: Read + Write>(link: &mut Link<P>) -> Result<Vec<Card>, Error> {
+    link.run("cards list")?
+        .into_iter()
+        .map(|line| {
+            let card = line.split_once(' ').and_then(|(uid, balance)| {
+                Some(Card {
+                    uid: parse_uid(uid).ok()?,
+                    balance: balance.parse().ok()?,
+                })
+            });
+            card.ok_or(Error::Protocol(line))
+        })
+        .collect()
+}
+
+pub fn paytable<P: Read + Write>(link: &mut Link<P>) -> Result<Vec<PayLine>, Error> {
+    link.run("paytable")?
+        .into_iter()
+        .map(|line| pay_line(&line).ok_or(Error::Protocol(line)))
+        .collect()
+}
+
+/// `<line> <combo> <symbol> <pays>`.
+fn pay_line(line: &str) -> Option<PayLine> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words[..] {
+        [number, combo, symbol, pays] => Some(PayLine {
+            line: number.parse().ok()?,
+            combo: combo.to_string(),
+            symbol: symbol.to_string(),
+            pays: pays.parse().ok()?,
+        }),
+        _ => None,
+    }
+}
+
+/// Sets every card's balance, logged in with `pin` for as long as it takes.
+pub fn provision<P: Read + Write>(
+    link: &mut Link<P>,
+    pin: u32,
+    cards: &[Card],
+) -> Result<(), Error> {
+    logged_in(link, pin, |link| {
+        for card in cards {
+            link.run(&format!("card set {} {}", UidHex(card.uid), card.balance))?;
+        }
+        Ok(())
+    })
+}
+
+/// Sets what each `(line, pays)` pays for the base bet.
+pub fn push_paytable<P: Read + Write>(
+    link: &mut Link<P>,
+    pin: u32,
+    pays: &[(u8, u32)],
+) -> Result<(), Error> {
+    logged_in(link, pin, |link| {
+        for (line, pays) in pays {
+            link.run(&format!("paytable set {} {}", line, pays))?;
+        }
+        Ok(())
+    })
+}
+
+/// Stores `new` as the console PIN. A machine without a PIN takes the first one
+/// without a login, so `pin` is only needed to change it.
+pub fn set_pin<P: Read + Write>(
+    link: &mut Link<P>,
+    pin: Option<u32>,
+    new: u32,
+) -> Result<(), Error> {
+    let command = format!("pin set {}", new);
+    match pin {
+        Some(pin) => logged_in(link, pin, |link| link.run(&command).map(|_| ())),
+        None => link.run(&command).map(|_| ()),
+    }
+}
+
+/// The self test report, and `Err` after it if something failed.
+pub fn selftest<P: Read + Write>(
+    link: &mut Link<P>,
+) -> Result<(Vec<String>, Result<(), Error>), Error> {
+    link.answer("selftest")
+}
+
+/// The whole EEPROM, a page per command.
+pub fn dump<P: Read + Write>(link: &mut Link<P>) -> Result<Vec<u8>, Error> {
+    let mut image = Vec::with_capacity(EEPROM_SIZE);
+    for addr in (0..EEPROM_SIZE).step_by(EEPROM_READ_MAX) {
+        image.extend(read(link, addr as u16, EEPROM_READ_MAX)?);
+    }
+    Ok(image)
+}
+
+/// What the game keeps in `image`: the card table as far as it has cards, the paytable,
+/// the settings and the console PIN if the image has one, so flashing an image without
+/// a PIN keeps the machine's. `Err` if the image has no card table.
+pub fn regions(image: &[u8]) -> Result<Vec<Range<usize>>, RecordError> {
+    let start = CARDS_ADDR as usize;
+    let header: [u8; HEADER_SIZE] = image[start..start + HEADER_SIZE].try_into().unwrap();
+    let count = read_header(&header)?;
+    let mut regions = vec![
+        start..card_addr(count) as usize,
+        PAYTABLE_ADDR as usize..PAYTABLE_ADDR as usize + PAYTABLE_SIZE,
+        CONFIG_ADDR as usize..CONFIG_ADDR as usize + CONFIG_SIZE,
+    ];
+    let pin = PIN_ADDR as usize..PIN_ADDR as usize + PIN_SIZE;
+    if read_pin(image[pin.clone()].try_into().unwrap()).is_ok() {
+        regions.push(pin);
+    }
+    Ok(regions)
+}
+
+/// Writes `regions` of `image` to the EEPROM, reads them back and reboots the machine,
+/// which loads the cards at boot. The rest of the EEPROM is left alone. A reboot ends
+/// the login, so it only logs out itself when something fails.
+pub fn flash<P: Read + Write>(
+    link: &mut Link<P>,
+    pin: u32,
+    image: &[u8],
+    regions: &[Range<usize>],
+) -> Result<usize, Error> {
+    link.run(&format!("login {}", pin))?;
+    let written = write_regions(link, image, regions);
+    if written.is_err() {
+        let _ = link.run("logout");
+    }
+    let written = written?;
+    link.run("reboot")?;
+    Ok(written)
+}
+
+/// Regions start in line with a write, so no write crosses a page.
+fn write_regions<P: Read + Write>(
+    link: &mut Link<P>,
+    image: &[u8],
+    regions: &[Range<usize>],
+) -> Result<usize, Error> {
+    for region in regions {
+        for addr in region.clone().step_by(EEPROM_WRITE_MAX) {
+            let end = region.end.min(addr + EEPROM_WRITE_MAX);
+            link.run(&format!(
+                "eeprom write {:x} {}",
+                addr,
+                Hex(&image[addr..end])
+            ))?;
+        }
+    }
+    for region in regions {
+        for addr in region.clone().step_by(EEPROM_READ_MAX) {
+            let end = region.end.min(addr + EEPROM_READ_MAX);
+            if read(link, addr as u16, end - addr)? != image[addr..end] {
+                return Err(Error::Verify(addr as u16));
+            }
+        }
+    }
+    Ok(regions.iter().map(|region| region.len()).sum())
+}
+
+fn read<P: Read + Write>(link: &mut Link<P>, addr: u16, len: usize) -> Result<Vec<u8>, Error> {
+    let mut lines = link.run(&format!("eeprom read {:x} {}", addr, len))?;
+    let line = lines.pop().unwrap_or_default();
+    match from_hex(&line) {
+        Some(data) if lines.is_empty() && data.len() == len => Ok(data),
+        _ => Err(Error::Protocol(line)),
+    }
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Logs out again even when `work` fails halfway.
+fn logged_in<P: Read + Write>(
+    link: &mut Link<P>,
+    pin: u32,
+    work: impl FnOnce(&mut Link<P>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    link.run(&format!("login {}", pin))?;
+    let result = work(link);
+    let logout = link.run("logout");
+    result.and(logout.map(|_| ()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, MockPort};
+
+    fn mock() -> Link<MockPort> {
+        Link::new(MockPort::new())
+    }
+
+    #[test]
+    fn reads_the_meters_and_the_history() {
+        let mut link = mock();
+        let meters = meters(&mut link).unwrap();
+        assert_eq!(meters.games, 3);
+        assert_eq!(meters.coin_in, 2000);
+        assert_eq!(meters.coin_out, 7700);
+        let history = history(&mut link).unwrap();
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[1].reels, [0, 0, 4]);
+        assert_eq!(history[1].win, 7500);
+    }
+
+    #[test]
+    fn provisions_cards() {
+        let mut link = mock();
+        let card = Card {
+            uid: [10, 85, 52, 0],
+            balance: 25_000,
+        };
+        provision(&mut link, mock::PIN, &[card]).unwrap();
+        assert!(cards(&mut link).unwrap().contains(&card));
+        // and logged out again
+        assert!(matches!(
+            link.run("card set 0a553400 1"),
+            Err(Error::Device(reason)) if reason == "login first"
+        ));
+    }
+
+    #[test]
+    fn a_wrong_pin_changes_nothing() {
+        let mut link = mock();
+        let card = Card {
+            uid: [10, 85, 52, 0],
+            balance: 1,
+        };
+        let result = provision(&mut link, 1111, &[card]);
+        assert!(matches!(result, Err(Error::Device(reason)) if reason == "wrong pin"));
+        assert!(!cards(&mut link).unwrap().contains(&card));
+    }
+
+    #[test]
+    fn an_unknown_card_stops_provisioning_and_logs_out() {
+        let mut link = mock();
+        let unknown = Card {
+            uid: [1, 2, 3, 4],
+            balance: 1,
+        };
+        let result = provision(&mut link, mock::PIN, &[unknown]);
+        assert!(matches!(result, Err(Error::Device(reason)) if reason == "unknown card"));
+        assert!(link.run("reboot").is_err());
+    }
+
+    #[test]
+    fn pushes_the_paytable() {
+        let mut link = mock();
+        push_paytable(&mut link, mock::PIN, &[(7, 200), (0, 400_000)]).unwrap();
+        let lines = paytable(&mut link).unwrap();
+        assert_eq!(lines.len(), 9);
+        assert_eq!(lines[0].pays, 400_000);
+        assert_eq!(
+            lines[7],
+            PayLine {
+                line: 7,
+                combo: "any".into(),
+                symbol: "rusty_crab".into(),
+                pays: 200
+            }
+        );
+    }
+
+    #[test]
+    fn dumps_and_flashes_the_eeprom() {
+        let mut link = mock();
+        let mut image = dump(&mut link).unwrap();
+        assert_eq!(image.len(), EEPROM_SIZE);
+        assert_eq!(image[..HEADER_SIZE], arcade_eeprom::header_bytes(2));
+
+        // a third card and a balance changed behind the firmware's back
+        let card = arcade_eeprom::Card {
+            uid: [1, 2, 3, 4],
+            balance: 500,
+        };
+        image[..HEADER_SIZE].copy_from_slice(&arcade_eeprom::header_bytes(3));
+        let slot = card_addr(2) as usize;
+        image[slot..slot + arcade_eeprom::CARD_SIZE].copy_from_slice(&card.to_bytes());
+        let regions = regions(&image).unwrap();
+        assert_eq!(regions[0], 0..0x40);
+        assert_eq!(regions.len(), 4);
+
+        flash(&mut link, mock::PIN, &image, &regions).unwrap();
+        assert_eq!(dump(&mut link).unwrap(), image);
+        let cards = cards(&mut link).unwrap();
+        assert_eq!(cards.len(), 3);
+        assert_eq!(
+            cards[2],
+            Card {
+                uid: card.uid,
+                balance: 500
+            }
+        );
+        // the reboot logged out
+        assert!(link.run("reboot").is_err());
+    }
+
+    #[test]
+    fn an_image_without_a_card_table_has_no_regions() {
+        let image = vec![0xff; EEPROM_SIZE];
+        assert_eq!(regions(&image), Err(RecordError::Blank));
+    }
+
+    #[test]
+    fn a_machine_without_a_pin_takes_the_first_one() {
+        let mut port = MockPort::new();
+        port.machine.eeprom[PIN_ADDR as usize..][..PIN_SIZE].fill(0xff);
+        port.machine.boot();
+        let mut link = Link::new(port);
+        assert!(matches!(
+            link.run("login 2580"),
+            Err(Error::Device(reason)) if reason == "no pin set, use pin set first"
+        ));
+        // nor does an image without a pin wipe the one the machine has
+        let image = dump(&mut link).unwrap();
+        assert_eq!(regions(&image).unwrap().len(), 3);
+
+        set_pin(&mut link, None, 4711).unwrap();
+        assert!(matches!(
+            set_pin(&mut link, None, 1234),
+            Err(Error::Device(reason)) if reason == "login first"
+        ));
+        set_pin(&mut link, Some(4711), 1234).unwrap();
+        link.run("login 1234").unwrap();
+        // the pin is stored, the reboot reads it back
+        link.run("reboot").unwrap();
+        push_paytable(&mut link, 1234, &[(7, 200)]).unwrap();
+    }
+
+    #[test]
+    fn runs_the_self_test() {
+        let (report, result) = selftest(&mut mock()).unwrap();
+        assert_eq!(report, ["eeprom ok", "cards ok", "reader ok 0x92"]);
+        assert!(result.is_ok());
+    }
+}
@@ -0,0 +1,209 @@
+//! Companion tool for the machine's USB console.
+//!
+//! ```text
+//! cargo run -- --port /dev/ttyACM0 pin set 4711
+//! cargo run -- --port /dev/ttyACM0 meters --format json
+//! cargo run -- --port /dev/ttyACM0 history > games.csv
+//! cargo run -- --port /dev/ttyACM0 --pin 4711 provision cards.csv
+//! cargo run -- --port /dev/ttyACM0 --pin 4711 paytable push paytable.csv
+//! cargo run -- --mock selftest
+//! cargo run -- --port /dev/ttyACM0 dump machine.bin
+//! cargo run -- --port /dev/ttyACM0 --pin 4711 flash cards.bin
+//! ```
+//!
+//! `--mock` talks to a machine simulated in the tool instead of a port, see `mock`. The
+//! PIN can also come from `ARCADE_PIN`. A new machine has no PIN and takes the first
+//! `pin set` without one.
+
+mod export;
+mod link;
+mod machine;
+mod mock;
+
+use std::io::{Read, Write};
+use std::time::Duration;
+use std::{env, fs, process};
+
+use arcade_console::parse_uid;
+use arcade_eeprom::EEPROM_SIZE;
+
+use crate::export::Format;
+use crate::link::{Error, Link};
+use crate::machine::Card;
+use crate::mock::MockPort;
+
+const USAGE: &str = "\
+usage: arcade_cli (--port <path> | --mock) [--pin <pin>] <command>
+
+commands:
+  meters [--format csv|json]          the audit meters
+  history [--format csv|json]         the last games
+  cards [--format csv|json]           every card and its balance
+  provision <uid> <balance>           sets a card's balance
+  provision <file.csv>                sets every uid,balance in the file
+  paytable [--format csv|json]        what every line pays for the base bet
+  paytable push <file.csv>            sets the payouts from line,...,pays rows
+  selftest                            checks the EEPROM and the card reader
+  dump <file.bin>                     saves the whole EEPROM to the file
+  flash <file.bin>                    writes the cards, paytable, settings and pin of
+                                      an image, checks them and reboots the machine
+  pin set <new pin>                   stores the console pin, --pin is the old one";
+
+/// How long the machine gets to answer a command.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Options {
+    port: Option<String>,
+    mock: bool,
+    pin: Option<u32>,
+    format: Format,
+    /// The command and its arguments.
+    words: Vec<String>,
+}
+
+fn main() {
+    let options = options(env::args().skip(1)).unwrap_or_else(|e| usage(&e));
+
+    let result = if options.mock {
+        run(&mut Link::new(MockPort::new()), &options)
+    } else {
+        let Some(path) = &options.port else {
+            usage("--port or --mock is needed");
+        };
+        // a CDC ACM port ignores the baud rate
+        match serialport::new(path, 115_200).timeout(TIMEOUT).open() {
+            Ok(port) => run(&mut Link::new(port), &options),
+            Err(e) => Err(format!("{}: {}", path, e)),
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn options(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        port: None,
+        mock: false,
+        pin: env::var("ARCADE_PIN").ok().and_then(|pin| pin.parse().ok()),
+        format: Format::Csv,
+        words: Vec::new(),
+    };
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--port" => options.port = Some(value()?),
+            "--mock" => options.mock = true,
+            "--pin" => {
+                let pin = value()?;
+                options.pin = Some(pin.parse().map_err(|_| format!("bad pin {:?}", pin))?);
+            }
+            "--format" => {
+                options.format = match value()?.as_str() {
+                    "csv" => Format::Csv,
+                    "json" => Format::Json,
+                    other => return Err(format!("unknown format {:?}", other)),
+                }
+            }
+            "-h" | "--help" => usage(""),
+            _ => options.words.push(arg),
+        }
+    }
+    Ok(options)
+}
+
+fn run<P: Read + Write>(link: &mut Link<P>, options: &Options) -> Result<(), String> {
+    let words: Vec<&str> = options.words.iter().map(String::as_str).collect();
+    let format = options.format;
+    let pin = || {
+        options
+            .pin
+            .ok_or("this needs the console pin, --pin or ARCADE_PIN")
+    };
+    let failed = |e: Error| e.to_string();
+
+    match words[..] {
+        ["meters"] => print!(
+            "{}",
+            export::meters(&machine::meters(link).map_err(failed)?, format)
+        ),
+        ["history"] => print!(
+            "{}",
+            export::history(&machine::history(link).map_err(failed)?, format)
+        ),
+        ["cards"] => print!(
+            "{}",
+            export::cards(&machine::cards(link).map_err(failed)?, format)
+        ),
+        ["provision", uid, balance] => {
+            let card = Card {
+                uid: parse_uid(uid).map_err(|e| e.to_string())?,
+                balance: balance
+                    .parse()
+                    .map_err(|_| format!("bad balance {:?}", balance))?,
+            };
+            machine::provision(link, pin()?, &[card]).map_err(failed)?;
+            println!("{} set to {}", uid, balance);
+        }
+        ["provision", file] => {
+            let cards = export::read_cards(&read(file)?).map_err(|e| format!("{}: {}", file, e))?;
+            machine::provision(link, pin()?, &cards).map_err(failed)?;
+            println!("{} card(s) provisioned", cards.len());
+        }
+        ["paytable"] => print!(
+            "{}",
+            export::paytable(&machine::paytable(link).map_err(failed)?, format)
+        ),
+        ["paytable", "push", file] => {
+            let pays =
+                export::read_paytable(&read(file)?).map_err(|e| format!("{}: {}", file, e))?;
+            machine::push_paytable(link, pin()?, &pays).map_err(failed)?;
+            println!("{} line(s) pushed", pays.len());
+        }
+        ["selftest"] => {
+            let (report, result) = machine::selftest(link).map_err(failed)?;
+            for line in report {
+                println!("{}", line);
+            }
+            result.map_err(|e| format!("self test failed, {}", e))?;
+            println!("self test passed");
+        }
+        ["dump", file] => {
+            let image = machine::dump(link).map_err(failed)?;
+            fs::write(file, image).map_err(|e| format!("{}: {}", file, e))?;
+            println!("{} bytes saved to {}", EEPROM_SIZE, file);
+        }
+        ["flash", file] => {
+            let image = fs::read(file).map_err(|e| format!("{}: {}", file, e))?;
+            if image.len() != EEPROM_SIZE {
+                return Err(format!("{}: an image is {} bytes", file, EEPROM_SIZE));
+            }
+            let regions = machine::regions(&image)
+                .map_err(|e| format!("{}: card table {}, see eeprom_image verify", file, e))?;
+            let written = machine::flash(link, pin()?, &image, &regions).map_err(failed)?;
+            println!("{} bytes written and checked, the machine reboots", written);
+        }
+        ["pin", "set", new] => {
+            let new = new.parse().map_err(|_| format!("bad pin {:?}", new))?;
+            machine::set_pin(link, options.pin, new).map_err(failed)?;
+            println!("console pin set");
+        }
+        _ => usage("unknown command"),
+    }
+    Ok(())
+}
+
+fn read(file: &str) -> Result<String, String> {
+    fs::read_to_string(file).map_err(|e| format!("{}: {}", file, e))
+}
+
+fn usage(problem: &str) -> ! {
+    if !problem.is_empty() {
+        eprintln!("{}\n", problem);
+    }
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
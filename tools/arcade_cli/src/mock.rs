@@ -0,0 +1,265 @@
+//! A machine that answers console commands like the firmware does, for `--mock` and the
+//! tests. It starts with two cards and the paytable the firmware ships with and a few
+//! games already played. The cards and the console PIN are in an EEPROM behind it and
+//! are read again on `reboot`, like the firmware does at boot.
+
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::time::Instant;
+
+use arcade_console::audit::Audit;
+use arcade_console::{Auth, AuthError, Command, Hex, LineBuffer, MAX_LINE, UidHex, parse};
+use arcade_eeprom::{
+    CARD_SIZE, CARDS_ADDR, Card, EEPROM_SIZE, HEADER_SIZE, PIN_ADDR, PIN_SIZE, card_addr,
+    header_bytes, pin_bytes, read_header, read_pin, within_page,
+};
+
+pub const PIN: u32 = 2580;
+
+const PAYTABLE: [(&str, &str, u32); 9] = [
+    ("three", "rusty_crab", 500_000),
+    ("three", "raspberry", 250_000),
+    ("three", "nodejs", 50_000),
+    ("three", "javascript", 37_500),
+    ("three", "python", 25_000),
+    ("three", "csharp", 12_500),
+    ("two", "rusty_crab", 7_500),
+    ("any", "rusty_crab", 100),
+    ("two", "raspberry", 5_000),
+];
+const MAX_PAYS: u32 = 10_000_000;
+
+pub struct Machine {
+    cards: Vec<Card>,
+    pub eeprom: Vec<u8>,
+    balance: i32,
+    card_in: bool,
+    paytable: Vec<(&'static str, &'static str, u32)>,
+    audit: Audit,
+    auth: Auth,
+    started: Instant,
+}
+
+impl Machine {
+    pub fn new() -> Self {
+        let mut audit = Audit::new();
+        audit.meters.cards_in = 1;
+        audit.game(500, [1, 3, 2], 0, false);
+        audit.game(500, [0, 0, 4], 7_500, false);
+        audit.game(1000, [5, 0, 2], 200, false);
+        let cards = vec![
+            Card {
+                uid: [80, 243, 109, 20],
+                balance: 80_000,
+            },
+            Card {
+                uid: [10, 85, 52, 0],
+                balance: 100_000,
+            },
+        ];
+        let mut eeprom = vec![0xff; EEPROM_SIZE];
+        store(&mut eeprom, CARDS_ADDR, &header_bytes(cards.len()));
+        for (index, card) in cards.iter().enumerate() {
+            store(&mut eeprom, card_addr(index), &card.to_bytes());
+        }
+        store(&mut eeprom, PIN_ADDR, &pin_bytes(PIN));
+        Machine {
+            cards,
+            eeprom,
+            balance: 0,
+            card_in: false,
+            paytable: PAYTABLE.to_vec(),
+            audit,
+            auth: Auth::new(Some(PIN)),
+            started: Instant::now(),
+        }
+    }
+
+    /// The answer to `line` as the firmware sends it, ending with `OK` or `ERR`.
+    pub fn answer(&mut self, line: &str) -> String {
+        let mut out = String::new();
+        let result = match parse(line) {
+            Ok(command) => self.run(command, &mut out),
+            Err(error) => Err(error.to_string()),
+        };
+        match result {
+            Ok(()) => out.push_str("OK\r\n"),
+            Err(reason) => {
+                let _ = write!(out, "ERR {}\r\n", reason);
+            }
+        }
+        out
+    }
+
+    fn run(&mut self, command: Command, out: &mut String) -> Result<(), String> {
+        let now = self.started.elapsed().as_millis() as u64;
+        if !self.auth.allows(&command, now) {
+            return Err("login first".into());
+        }
+
+        match command {
+            Command::Help => {
+                for usage in [
+                    "balance",
+                    "cards list",
+                    "card set <uid> <amount>",
+                    "meters",
+                    "log dump",
+                    "paytable",
+                    "paytable set <line> <pays>",
+                    "rng seed",
+                    "selftest",
+                    "eeprom read <addr> <len>",
+                    "eeprom write <addr> <hex>",
+                    "reboot",
+                    "pin set <pin>",
+                    "login <pin>",
+                    "logout",
+                ] {
+                    let _ = write!(out, "{}\r\n", usage);
+                }
+            }
+            Command::Balance => {
+                let card = if self.card_in { "in" } else { "out" };
+                let _ = write!(out, "balance {}\r\ncard {}\r\n", self.balance, card);
+            }
+            Command::CardsList => {
+                for card in &self.cards {
+                    let _ = write!(out, "{} {}\r\n", UidHex(card.uid), card.balance);
+                }
+            }
+            Command::CardSet { uid, amount } => {
+                let Some(index) = self.cards.iter().position(|card| card.uid == uid) else {
+                    return Err("unknown card".into());
+                };
+                if self.card_in {
+                    return Err("a card is in the machine, cash out first".into());
+                }
+                self.cards[index].balance = amount;
+                let record = self.cards[index].to_bytes();
+                store(&mut self.eeprom, card_addr(index), &record);
+            }
+            Command::Meters => {
+                for (name, value) in self.audit.meters.entries() {
+                    let _ = write!(out, "{} {}\r\n", name, value);
+                }
+            }
+            Command::LogDump => {
+                for game in self.audit.history.iter() {
+                    let _ = write!(out, "{}\r\n", game);
+                }
+            }
+            Command::Paytable => {
+                for (line, (combo, symbol, pays)) in self.paytable.iter().enumerate() {
+                    let _ = write!(out, "{} {} {} {}\r\n", line, combo, symbol, pays);
+                }
+            }
+            Command::PaytableSet { line, pays } => match self.paytable.get_mut(line as usize) {
+                Some(entry) if pays <= MAX_PAYS => entry.2 = pays,
+                _ => return Err("no such line or too much".into()),
+            },
+            Command::RngSeed => out.push_str("seed 1234567\r\n"),
+            Command::SelfTest => out.push_str("eeprom ok\r\ncards ok\r\nreader ok 0x92\r\n"),
+            Command::EepromRead { addr, len } => {
+                let start = addr as usize;
+                let Some(data) = self.eeprom.get(start..start + len as usize) else {
+                    return Err("outside the EEPROM".into());
+                };
+                let _ = write!(out, "{}\r\n", Hex(data));
+            }
+            Command::EepromWrite { addr, len, data } => {
+                if !within_page(addr, len as usize) {
+                    return Err("outside the EEPROM or across a page".into());
+                }
+                if self.card_in {
+                    return Err("a card is in the machine, cash out first".into());
+                }
+                store(&mut self.eeprom, addr, &data[..len as usize]);
+            }
+            Command::Reboot => self.boot(),
+            Command::PinSet { pin } => {
+                store(&mut self.eeprom, PIN_ADDR, &pin_bytes(pin));
+                self.auth.set_pin(pin);
+            }
+            Command::Login { pin } => match self.auth.login(pin, now) {
+                Ok(()) => {}
+                Err(AuthError::NoPin) => return Err("no pin set, use pin set first".into()),
+                Err(AuthError::WrongPin) => return Err("wrong pin".into()),
+                Err(AuthError::Locked { .. }) => return Err("too many wrong pins".into()),
+            },
+            Command::Logout => self.auth.logout(),
+        }
+        Ok(())
+    }
+
+    /// What the firmware does at boot that the tool can see: the cards and the PIN are
+    /// read from the EEPROM and nobody is logged in.
+    pub fn boot(&mut self) {
+        let header: [u8; HEADER_SIZE] = self.eeprom[..HEADER_SIZE].try_into().unwrap();
+        let count = read_header(&header).unwrap_or(0);
+        self.cards = (0..count)
+            .filter_map(|index| {
+                let start = card_addr(index) as usize;
+                let record: [u8; CARD_SIZE] =
+                    self.eeprom[start..start + CARD_SIZE].try_into().unwrap();
+                Card::from_bytes(&record).ok()
+            })
+            .collect();
+        self.balance = 0;
+        self.card_in = false;
+        let pin: [u8; PIN_SIZE] = self.eeprom[PIN_ADDR as usize..][..PIN_SIZE]
+            .try_into()
+            .unwrap();
+        self.auth = Auth::new(read_pin(&pin).ok());
+    }
+}
+
+fn store(eeprom: &mut [u8], addr: u16, bytes: &[u8]) {
+    eeprom[addr as usize..addr as usize + bytes.len()].copy_from_slice(bytes);
+}
+
+/// The machine behind a serial port: what is written to it are commands, what is read
+/// are its answers.
+pub struct MockPort {
+    pub machine: Machine,
+    line: LineBuffer<MAX_LINE>,
+    answers: Vec<u8>,
+}
+
+impl MockPort {
+    pub fn new() -> Self {
+        MockPort {
+            machine: Machine::new(),
+            line: LineBuffer::new(),
+            answers: Vec::new(),
+        }
+    }
+}
+
+impl Write for MockPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            let answer = match self.line.push(byte) {
+                None => continue,
+                Some(Ok(text)) if text.trim().is_empty() => continue,
+                Some(Ok(text)) => self.machine.answer(text),
+                Some(Err(error)) => format!("ERR {}\r\n", error),
+            };
+            self.answers.extend_from_slice(answer.as_bytes());
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Read for MockPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = buf.len().min(self.answers.len());
+        buf[..count].copy_from_slice(&self.answers[..count]);
+        self.answers.drain(..count);
+        Ok(count)
+    }
+}
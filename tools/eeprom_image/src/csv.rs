@@ -0,0 +1,62 @@
+//! The `uid,balance` files `cards` writes and `new` reads, the same rows as
+//! `arcade_cli provision` takes.
+
+use arcade_console::parse_uid;
+use arcade_eeprom::{Card, MAX_BALANCE};
+
+/// A header, empty lines and lines starting with `#` are skipped.
+pub fn read_cards(text: &str) -> Result<Vec<Card>, String> {
+    let mut cards: Vec<Card> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || (number == 1 && line.starts_with("uid")) {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let [uid, balance] = fields[..] else {
+            return Err(format!("line {}: expected uid,balance", number));
+        };
+        let card = Card {
+            uid: parse_uid(uid).map_err(|e| format!("line {}: {}", number, e))?,
+            balance: match balance.parse() {
+                Ok(balance) if balance <= MAX_BALANCE => balance,
+                _ => return Err(format!("line {}: bad balance {:?}", number, balance)),
+            },
+        };
+        if cards.iter().any(|known| known.uid == card.uid) {
+            return Err(format!("line {}: {} is there twice", number, uid));
+        }
+        cards.push(card);
+    }
+    Ok(cards)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_uid_balance_rows() {
+        let cards = read_cards("uid,balance\n50f36d14, 80000\n\n# spare\n0a:55:34:00,100000\n");
+        assert_eq!(
+            cards,
+            Ok(vec![
+                Card {
+                    uid: [0x50, 0xf3, 0x6d, 0x14],
+                    balance: 80_000
+                },
+                Card {
+                    uid: [0x0a, 0x55, 0x34, 0x00],
+                    balance: 100_000
+                },
+            ])
+        );
+        assert!(read_cards("50f36d14\n").is_err());
+        assert!(read_cards("50f36d14,3000000000\n").is_err());
+        assert_eq!(
+            read_cards("50f36d14,1\n50:f3:6d:14,2\n"),
+            Err("line 2: 50:f3:6d:14 is there twice".to_string())
+        );
+    }
+}
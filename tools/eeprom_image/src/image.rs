@@ -0,0 +1,350 @@
+//! A dump of the whole EEPROM and the records in it, see `arcade_eeprom` for the layout.
+
+use arcade_console::UidHex;
+use arcade_eeprom::{
+    CARD_SIZE, CARDS_ADDR, CONFIG_ADDR, CONFIG_MAGIC, CONFIG_SIZE, CONFIG_VERSION, Card,
+    EEPROM_SIZE, HEADER_SIZE, LEGACY_SIZE, MAX_CARDS, PAYTABLE_ADDR, PAYTABLE_MAGIC, PAYTABLE_SIZE,
+    PAYTABLE_VERSION, PIN_ADDR, PIN_SIZE, RecordError, card_addr, check_record, header_bytes,
+    legacy_cards, pin_bytes, read_header, read_pin,
+};
+
+pub struct Image(Vec<u8>);
+
+/// What is at the start of the EEPROM.
+#[derive(Debug, PartialEq)]
+pub enum Cards {
+    /// Every slot of the card table.
+    Table(Vec<Result<Card, RecordError>>),
+    /// Cards of the layout from before the card table, without CRCs. The firmware
+    /// moves them into a table at boot.
+    Legacy(Vec<Card>),
+    /// A card table that doesn't read.
+    Broken(RecordError),
+}
+
+/// One line of `verify`.
+pub struct Check {
+    pub what: String,
+    /// What was found, or what is wrong.
+    pub result: Result<String, String>,
+}
+
+impl Image {
+    /// An EEPROM that was never written.
+    pub fn blank() -> Self {
+        Image(vec![0xff; EEPROM_SIZE])
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, String> {
+        if bytes.len() != EEPROM_SIZE {
+            return Err(format!(
+                "{} bytes, a dump of the AT24C256 is {}",
+                bytes.len(),
+                EEPROM_SIZE
+            ));
+        }
+        Ok(Image(bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn cards(&self) -> Cards {
+        let header: [u8; HEADER_SIZE] = self.bytes(CARDS_ADDR, HEADER_SIZE).try_into().unwrap();
+        match read_header(&header) {
+            Ok(count) => Cards::Table(
+                (0..count)
+                    .map(|index| {
+                        let record = self.bytes(card_addr(index), CARD_SIZE);
+                        Card::from_bytes(record.try_into().unwrap())
+                    })
+                    .collect(),
+            ),
+            Err(RecordError::Blank | RecordError::BadMagic) => {
+                let legacy: [u8; LEGACY_SIZE] =
+                    self.bytes(CARDS_ADDR, LEGACY_SIZE).try_into().unwrap();
+                Cards::Legacy(legacy_cards(&legacy).collect())
+            }
+            Err(e) => Cards::Broken(e),
+        }
+    }
+
+    /// Replaces the card table, the slots it doesn't use are blanked.
+    pub fn set_cards(&mut self, cards: &[Card]) -> Result<(), String> {
+        if cards.len() > MAX_CARDS {
+            return Err(format!(
+                "{} cards, the EEPROM holds {}",
+                cards.len(),
+                MAX_CARDS
+            ));
+        }
+        let end = card_addr(MAX_CARDS) as usize;
+        self.0[CARDS_ADDR as usize..end].fill(0xff);
+        self.put(CARDS_ADDR, &header_bytes(cards.len()));
+        for (index, card) in cards.iter().enumerate() {
+            self.put(card_addr(index), &card.to_bytes());
+        }
+        Ok(())
+    }
+
+    /// Takes the paytable, the settings and the console PIN from `other`.
+    pub fn copy_settings(&mut self, other: &Image) {
+        for (addr, len) in [
+            (PAYTABLE_ADDR, PAYTABLE_SIZE),
+            (CONFIG_ADDR, CONFIG_SIZE),
+            (PIN_ADDR, PIN_SIZE),
+        ] {
+            let bytes = other.bytes(addr, len).to_vec();
+            self.put(addr, &bytes);
+        }
+    }
+
+    pub fn set_pin(&mut self, pin: u32) {
+        self.put(PIN_ADDR, &pin_bytes(pin));
+    }
+
+    /// Every record with what is in it. A blank paytable or settings record is fine,
+    /// the firmware uses its defaults.
+    pub fn verify(&self) -> Vec<Check> {
+        let mut checks = Vec::new();
+        match self.cards() {
+            Cards::Table(slots) => {
+                checks.push(Check {
+                    what: "card table".into(),
+                    result: Ok(format!("{} card(s)", slots.len())),
+                });
+                let mut seen = Vec::new();
+                for (index, slot) in slots.iter().enumerate() {
+                    let result = match slot {
+                        Ok(card) if seen.contains(&card.uid) => {
+                            Err(format!("{} is in the table twice", UidHex(card.uid)))
+                        }
+                        Ok(card) => {
+                            seen.push(card.uid);
+                            Ok(format!("{} {}", UidHex(card.uid), card.balance))
+                        }
+                        Err(e) => Err(e.to_string()),
+                    };
+                    checks.push(Check {
+                        what: format!("card {}", index),
+                        result,
+                    });
+                }
+            }
+            Cards::Legacy(cards) if !cards.is_empty() => checks.push(Check {
+                what: "card table".into(),
+                result: Ok(format!(
+                    "old layout without CRCs, {} card(s), the firmware moves them into a table at boot",
+                    cards.len()
+                )),
+            }),
+            Cards::Legacy(_) => checks.push(Check {
+                what: "card table".into(),
+                result: Err("no cards".into()),
+            }),
+            Cards::Broken(e) => checks.push(Check {
+                what: "card table".into(),
+                result: Err(e.to_string()),
+            }),
+        }
+
+        let paytable = self.bytes(PAYTABLE_ADDR, PAYTABLE_SIZE);
+        checks.push(Check {
+            what: "paytable".into(),
+            result: match check_record(paytable, PAYTABLE_MAGIC, PAYTABLE_VERSION) {
+                Ok(()) => {
+                    let pays: Vec<String> = paytable[3..PAYTABLE_SIZE - 2]
+                        .chunks_exact(4)
+                        .map(|pays| i32::from_be_bytes(pays.try_into().unwrap()).to_string())
+                        .collect();
+                    Ok(format!("pays {}", pays.join(" ")))
+                }
+                Err(RecordError::Blank) => Ok("blank, the built in payouts are used".into()),
+                Err(e) => Err(e.to_string()),
+            },
+        });
+
+        let config = self.bytes(CONFIG_ADDR, CONFIG_SIZE);
+        checks.push(Check {
+            what: "settings".into(),
+            result: match check_record(config, CONFIG_MAGIC, CONFIG_VERSION) {
+                Ok(()) => Ok(settings(config)),
+                Err(RecordError::Blank) => Ok("blank, the defaults are used".into()),
+                Err(e) => Err(e.to_string()),
+            },
+        });
+
+        // the pin itself stays out of the report
+        let pin: [u8; PIN_SIZE] = self.bytes(PIN_ADDR, PIN_SIZE).try_into().unwrap();
+        checks.push(Check {
+            what: "console pin".into(),
+            result: match read_pin(&pin) {
+                Ok(_) => Ok("set".into()),
+                Err(RecordError::Blank) => {
+                    Ok("blank, the console takes no login until one is set".into())
+                }
+                Err(e) => Err(e.to_string()),
+            },
+        });
+        checks
+    }
+
+    fn bytes(&self, addr: u16, len: usize) -> &[u8] {
+        &self.0[addr as usize..addr as usize + len]
+    }
+
+    fn put(&mut self, addr: u16, bytes: &[u8]) {
+        self.0[addr as usize..addr as usize + bytes.len()].copy_from_slice(bytes);
+    }
+}
+
+/// A settings record that checked out, as `src/config.rs` writes it.
+fn settings(config: &[u8]) -> String {
+    let muted = if config[10] != 0 { ", muted" } else { "" };
+    format!(
+        "backlight {} %, {} % after {} s, off after {} s, volume {} %{}, attract mode after {} s",
+        config[3],
+        config[4],
+        u16::from_be_bytes([config[5], config[6]]),
+        u16::from_be_bytes([config[7], config[8]]),
+        config[9],
+        muted,
+        u16::from_be_bytes([config[11], config[12]])
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card(last: u8, balance: u32) -> Card {
+        Card {
+            uid: [0x50, 0xf3, 0x6d, last],
+            balance,
+        }
+    }
+
+    #[test]
+    fn a_new_image_has_the_cards_and_checks_out() {
+        let mut image = Image::blank();
+        image.set_cards(&[card(1, 80_000), card(2, 0)]).unwrap();
+        assert_eq!(
+            image.cards(),
+            Cards::Table(vec![Ok(card(1, 80_000)), Ok(card(2, 0))])
+        );
+        let checks = image.verify();
+        assert!(checks.iter().all(|check| check.result.is_ok()));
+        assert_eq!(checks.len(), 1 + 2 + 3);
+        assert_eq!(checks[1].result, Ok("50f36d01 80000".to_string()));
+    }
+
+    #[test]
+    fn fewer_cards_blank_the_old_slots() {
+        let mut image = Image::blank();
+        image.set_cards(&[card(1, 1), card(2, 2)]).unwrap();
+        image.set_cards(&[card(3, 3)]).unwrap();
+        assert_eq!(image.cards(), Cards::Table(vec![Ok(card(3, 3))]));
+        assert!(
+            image
+                .bytes(card_addr(1), CARD_SIZE)
+                .iter()
+                .all(|&b| b == 0xff)
+        );
+        assert!(image.set_cards(&vec![card(0, 0); MAX_CARDS + 1]).is_err());
+    }
+
+    #[test]
+    fn finds_spoilt_and_doubled_cards() {
+        let mut image = Image::blank();
+        image
+            .set_cards(&[card(1, 1), card(1, 2), card(3, 3)])
+            .unwrap();
+        image.0[card_addr(2) as usize + 5] ^= 0x40;
+        let problems: Vec<String> = image
+            .verify()
+            .into_iter()
+            .filter_map(|check| check.result.err())
+            .collect();
+        assert_eq!(problems, ["50f36d01 is in the table twice", "bad crc"]);
+    }
+
+    #[test]
+    fn reads_the_old_layout() {
+        let mut image = Image::blank();
+        image.put(0, &[80, 243, 109, 20, 0, 1, 56, 128]);
+        image.put(8, &[10, 85, 52, 0, 0, 1, 134, 160]);
+        assert_eq!(
+            image.cards(),
+            Cards::Legacy(vec![
+                Card {
+                    uid: [80, 243, 109, 20],
+                    balance: 80_000
+                },
+                Card {
+                    uid: [10, 85, 52, 0],
+                    balance: 100_000
+                },
+            ])
+        );
+        assert!(image.verify()[0].result.is_ok());
+        assert!(Image::blank().verify()[0].result.is_err());
+    }
+
+    #[test]
+    fn copies_the_paytable_and_the_settings() {
+        let mut old = Image::blank();
+        let mut config = vec![
+            b'C',
+            b'F',
+            CONFIG_VERSION,
+            100,
+            30,
+            1,
+            44,
+            3,
+            132,
+            80,
+            1,
+            0,
+            60,
+        ];
+        let crc = arcade_eeprom::crc16(&config);
+        config.extend(crc.to_be_bytes());
+        old.put(CONFIG_ADDR, &config);
+        let mut paytable = vec![b'P', b'T', 1];
+        for pays in [
+            500_000i32, 250_000, 50_000, 37_500, 25_000, 12_500, 7_500, 200, 5_000,
+        ] {
+            paytable.extend(pays.to_be_bytes());
+        }
+        let crc = arcade_eeprom::crc16(&paytable);
+        paytable.extend(crc.to_be_bytes());
+        old.put(PAYTABLE_ADDR, &paytable);
+
+        let mut image = Image::blank();
+        old.set_pin(4711);
+        image.copy_settings(&old);
+        let checks = image.verify();
+        assert_eq!(checks[3].result, Ok("set".to_string()));
+        assert_eq!(image.bytes(PIN_ADDR, PIN_SIZE), pin_bytes(4711));
+        assert!(
+            checks[1]
+                .result
+                .as_ref()
+                .unwrap()
+                .ends_with(" 7500 200 5000")
+        );
+        assert_eq!(
+            checks[2].result,
+            Ok(
+                "backlight 100 %, 30 % after 300 s, off after 900 s, volume 80 %, muted, attract mode after 60 s"
+                    .to_string()
+            )
+        );
+
+        image.0[CONFIG_ADDR as usize + 3] = 50;
+        assert_eq!(image.verify()[2].result, Err("bad crc".to_string()));
+        assert!(Image::from_bytes(vec![0; 100]).is_err());
+    }
+}
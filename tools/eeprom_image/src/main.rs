@@ -0,0 +1,143 @@
+//! Reads, checks and makes images of the machine's EEPROM, offline.
+//!
+//! ```text
+//! cargo run -- cards machine.bin > cards.csv
+//! cargo run -- verify machine.bin
+//! cargo run -- new cards.csv fresh.bin --from machine.bin
+//! cargo run -- new cards.csv fresh.bin --pin 4711
+//! ```
+//!
+//! An image is the 32 KB of the AT24C256 byte for byte, as an external programmer
+//! reads and writes it and as `arcade_cli dump` saves it. `new` makes one with the cards
+//! of a `uid,balance` file, which is how cards get onto a machine now that the firmware
+//! has none built in.
+
+mod csv;
+mod image;
+
+use std::{env, fs, process};
+
+use arcade_console::UidHex;
+
+use crate::image::{Cards, Image};
+
+const USAGE: &str = "\
+usage: eeprom_image <command>
+
+commands:
+  cards <image.bin>                           the cards as uid,balance rows
+  verify <image.bin>                          checks every record, fails if one is bad
+  new <cards.csv> <image.bin> [--from <old.bin>] [--pin <pin>]
+                                              an image with the cards in the file, the
+                                              paytable, settings and console pin of
+                                              old.bin and the pin given";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let words: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let result = match words[..] {
+        ["cards", file] => cards(file),
+        ["verify", file] => verify(file),
+        ["new", csv, out, ref options @ ..] => match new_options(options) {
+            Some((old, pin)) => new(csv, out, old, pin),
+            None => usage(),
+        },
+        _ => usage(),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn cards(file: &str) -> Result<(), String> {
+    let image = read(file)?;
+    println!("uid,balance");
+    match image.cards() {
+        Cards::Table(slots) => {
+            let mut bad = 0;
+            for (index, slot) in slots.iter().enumerate() {
+                match slot {
+                    Ok(card) => println!("{},{}", UidHex(card.uid), card.balance),
+                    Err(e) => {
+                        eprintln!("card {}: {}", index, e);
+                        bad += 1;
+                    }
+                }
+            }
+            if bad > 0 {
+                return Err(format!("{}: {} card(s) left out", file, bad));
+            }
+        }
+        Cards::Legacy(cards) => {
+            for card in &cards {
+                println!("{},{}", UidHex(card.uid), card.balance);
+            }
+            eprintln!("{}: old layout without CRCs", file);
+        }
+        Cards::Broken(e) => return Err(format!("{}: card table {}", file, e)),
+    }
+    Ok(())
+}
+
+fn verify(file: &str) -> Result<(), String> {
+    let image = read(file)?;
+    let mut bad = 0;
+    for check in image.verify() {
+        match check.result {
+            Ok(found) => println!("{:<12} ok   {}", check.what, found),
+            Err(problem) => {
+                println!("{:<12} BAD  {}", check.what, problem);
+                bad += 1;
+            }
+        }
+    }
+    match bad {
+        0 => Ok(()),
+        _ => Err(format!("{}: {} bad record(s)", file, bad)),
+    }
+}
+
+/// `--from <old.bin>` and `--pin <pin>`, in any order.
+fn new_options<'a>(mut options: &[&'a str]) -> Option<(Option<&'a str>, Option<u32>)> {
+    let (mut old, mut pin) = (None, None);
+    while let [option, value, rest @ ..] = options {
+        match *option {
+            "--from" if old.is_none() => old = Some(*value),
+            "--pin" if pin.is_none() => pin = Some(value.parse().ok()?),
+            _ => return None,
+        }
+        options = rest;
+    }
+    options.is_empty().then_some((old, pin))
+}
+
+fn new(csv: &str, out: &str, old: Option<&str>, pin: Option<u32>) -> Result<(), String> {
+    let text = fs::read_to_string(csv).map_err(|e| format!("{}: {}", csv, e))?;
+    let cards = csv::read_cards(&text).map_err(|e| format!("{}: {}", csv, e))?;
+
+    let mut image = Image::blank();
+    image
+        .set_cards(&cards)
+        .map_err(|e| format!("{}: {}", csv, e))?;
+    if let Some(old) = old {
+        image.copy_settings(&read(old)?);
+    }
+    if let Some(pin) = pin {
+        image.set_pin(pin);
+    }
+    fs::write(out, image.as_bytes()).map_err(|e| format!("{}: {}", out, e))?;
+    println!("{} card(s) written to {}", cards.len(), out);
+    Ok(())
+}
+
+fn read(file: &str) -> Result<Image, String> {
+    let bytes = fs::read(file).map_err(|e| format!("{}: {}", file, e))?;
+    Image::from_bytes(bytes).map_err(|e| format!("{}: {}", file, e))
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
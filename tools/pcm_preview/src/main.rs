@@ -0,0 +1,99 @@
+//! Previews the 8 bit PCM stream `build.rs` makes of a WAV.
+//!
+//! ```text
+//! cargo run -- ../../assets/samples/coin_drop.wav
+//! ```
+//!
+//! prints the source format, how long the sample is and how much flash it takes,
+//! draws its envelope and writes `coin_drop.preview.wav` next to the source, which
+//! plays the converted stream as the buzzer gets it.
+
+mod wav;
+
+use std::path::Path;
+use std::{env, fs, process};
+
+const ENVELOPE_COLUMNS: usize = 64;
+const ENVELOPE_ROWS: usize = 8;
+
+fn main() {
+    let paths: Vec<String> = env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("usage: pcm_preview <file.wav>...");
+        process::exit(2);
+    }
+
+    let mut failed = false;
+    for path in &paths {
+        if let Err(e) = preview(Path::new(path)) {
+            eprintln!("{}: {}", path, e);
+            failed = true;
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn preview(path: &Path) -> Result<(), String> {
+    let bytes = fs::read(path).map_err(|e| e.to_string())?;
+    let source = wav::read(&bytes)?;
+    let pcm = wav::to_pcm(&source);
+
+    println!("{}", path.display());
+    println!(
+        "  source:    {} Hz, {} bit, {} channel(s), {} ms",
+        source.sample_rate,
+        source.bits,
+        source.channels,
+        source.samples.len() as u64 * 1000 / source.sample_rate as u64
+    );
+    let clipped = source.samples.iter().filter(|s| s.abs() >= 1.0).count();
+    let peak = pcm
+        .iter()
+        .map(|&s| (s as i32 - 128).abs())
+        .max()
+        .unwrap_or(0);
+    println!(
+        "  converted: {} Hz, 8 bit, {} ms, {} bytes of flash, peak {} of 127, {} clipped",
+        wav::SAMPLE_RATE,
+        pcm.len() as u64 * 1000 / wav::SAMPLE_RATE as u64,
+        pcm.len(),
+        peak,
+        clipped
+    );
+    print_envelope(&pcm);
+
+    let out = path.with_extension("preview.wav");
+    fs::write(&out, wav::write(&pcm)).map_err(|e| e.to_string())?;
+    println!("  wrote {}", out.display());
+    Ok(())
+}
+
+/// Peak level per column, loudest at the top.
+fn print_envelope(pcm: &[u8]) {
+    if pcm.is_empty() {
+        return;
+    }
+    let per_column = pcm.len().div_ceil(ENVELOPE_COLUMNS);
+    let peaks: Vec<usize> = pcm
+        .chunks(per_column)
+        .map(|chunk| {
+            let peak = chunk
+                .iter()
+                .map(|&s| (s as i32 - 128).unsigned_abs())
+                .max()
+                .unwrap_or(0);
+            (peak as usize * ENVELOPE_ROWS).div_ceil(128)
+        })
+        .collect();
+
+    for row in (1..=ENVELOPE_ROWS).rev() {
+        let line: String = peaks
+            .iter()
+            .map(|&p| if p >= row { '#' } else { ' ' })
+            .collect();
+        println!("  |{}", line.trim_end());
+    }
+    println!("  +{}", "-".repeat(peaks.len()));
+}
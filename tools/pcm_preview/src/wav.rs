@@ -0,0 +1,118 @@
+//! WAV to 8 bit PCM, shared by `build.rs` and the preview tool so the preview is
+//! exactly what ends up in the firmware.
+//!
+//! Any uncompressed 8 or 16 bit WAV works, in any sample rate and with any number
+//! of channels. The channels are mixed to mono and resampled to `SAMPLE_RATE` with
+//! linear interpolation. The output is unsigned, 128 is silence.
+
+/// Must match `SAMPLE_RATE` in `src/pcm.rs`.
+pub const SAMPLE_RATE: u32 = 8000;
+
+pub struct Wav {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub bits: u16,
+    /// Mono, -1.0..=1.0
+    pub samples: Vec<f32>,
+}
+
+pub fn read(bytes: &[u8]) -> Result<Wav, String> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err("not a RIFF WAVE file".into());
+    }
+
+    let mut format = None;
+    let mut data = None;
+    let mut at = 12;
+    while at + 8 <= bytes.len() {
+        let id = &bytes[at..at + 4];
+        let len = u32::from_le_bytes(bytes[at + 4..at + 8].try_into().unwrap()) as usize;
+        let body = bytes
+            .get(at + 8..at + 8 + len)
+            .ok_or_else(|| format!("chunk {:?} runs past the end", String::from_utf8_lossy(id)))?;
+        match id {
+            b"fmt " if len >= 16 => format = Some(body),
+            b"data" => data = Some(body),
+            _ => {}
+        }
+        // chunks are padded to an even length
+        at += 8 + len + len % 2;
+    }
+    let format = format.ok_or("no fmt chunk")?;
+    let data = data.ok_or("no data chunk")?;
+
+    let tag = u16::from_le_bytes([format[0], format[1]]);
+    let channels = u16::from_le_bytes([format[2], format[3]]);
+    let sample_rate = u32::from_le_bytes(format[4..8].try_into().unwrap());
+    let bits = u16::from_le_bytes([format[14], format[15]]);
+    if tag != 1 {
+        return Err(format!("format {} isn't plain PCM", tag));
+    }
+    if channels == 0 || sample_rate == 0 {
+        return Err("no channels or no sample rate".into());
+    }
+
+    let values: Vec<f32> = match bits {
+        8 => data.iter().map(|&b| (b as f32 - 128.0) / 128.0).collect(),
+        16 => data
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+            .collect(),
+        _ => return Err(format!("{} bit samples, use 8 or 16", bits)),
+    };
+    let samples = values
+        .chunks_exact(channels as usize)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+
+    Ok(Wav {
+        sample_rate,
+        channels,
+        bits,
+        samples,
+    })
+}
+
+/// Resamples to `SAMPLE_RATE` and converts to unsigned 8 bit.
+pub fn to_pcm(wav: &Wav) -> Vec<u8> {
+    if wav.samples.is_empty() {
+        return Vec::new();
+    }
+    let step = wav.sample_rate as f64 / SAMPLE_RATE as f64;
+    let len = (wav.samples.len() as f64 / step).floor() as usize;
+    let last = wav.samples.len() - 1;
+
+    (0..len)
+        .map(|i| {
+            let at = i as f64 * step;
+            let index = (at.floor() as usize).min(last);
+            let fraction = (at - index as f64) as f32;
+            let a = wav.samples[index];
+            let b = wav.samples[(index + 1).min(last)];
+            let value = a + (b - a) * fraction;
+            (value * 127.0 + 128.0).round().clamp(0.0, 255.0) as u8
+        })
+        .collect()
+}
+
+/// A mono 8 bit WAV of converted samples, to listen to them.
+pub fn write(pcm: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(44 + pcm.len() + 1);
+    out.extend_from_slice(b"RIFF");
+    out.extend_from_slice(&(36 + pcm.len() as u32 + pcm.len() as u32 % 2).to_le_bytes());
+    out.extend_from_slice(b"WAVEfmt ");
+    out.extend_from_slice(&16u32.to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    out.extend_from_slice(&1u16.to_le_bytes()); // mono
+    out.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    out.extend_from_slice(&SAMPLE_RATE.to_le_bytes()); // bytes per second
+    out.extend_from_slice(&1u16.to_le_bytes()); // bytes per frame
+    out.extend_from_slice(&8u16.to_le_bytes());
+    out.extend_from_slice(b"data");
+    out.extend_from_slice(&(pcm.len() as u32).to_le_bytes());
+    out.extend_from_slice(pcm);
+    if pcm.len() % 2 == 1 {
+        out.push(0);
+    }
+    out
+}